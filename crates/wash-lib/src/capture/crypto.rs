@@ -0,0 +1,256 @@
+//! Encryption of capture files for a recipient's curve key, so captures can be shared without
+//! exposing their payloads to anyone but the holder of the matching seed.
+//!
+//! Keys are encoded like nkeys xkeys (`X...` public keys and `SX...` seeds), but the file format
+//! is specific to wash and is NOT compatible with nkeys `XKey::seal`/`XKey::open` or other xkey
+//! tooling: encrypted captures can only be read with `wash capture --seed`. An encrypted capture
+//! starts with [`MAGIC`] and an ephemeral public key. The key shared between the ephemeral key
+//! and the recipient's key (X25519) is run through HKDF-SHA256 to derive a ChaCha20-Poly1305
+//! key, which seals the capture in chunks so it never has to be held in memory.
+//!
+//! TODO: seal each chunk with nkeys `XKey::seal` (XSalsa20-Poly1305), so captures can be opened
+//! with standard xkey tooling, once the workspace moves to nkeys 0.4. nkeys 0.4 can't be added
+//! next to the nkeys 0.2 that async-nats, wascap and wadm depend on, since the signatory versions
+//! they use require incompatible releases of base64ct, and no XSalsa20-Poly1305 implementation is
+//! available to the workspace until then. The key encoding in [`xkey`] goes away with it
+use anyhow::{bail, Context, Result};
+use curve25519_dalek::{constants::X25519_BASEPOINT, montgomery::MontgomeryPoint, scalar::Scalar};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::xkey;
+
+/// Bytes at the start of every encrypted capture file
+pub const MAGIC: &[u8; 8] = b"WCAPENC1";
+
+const HKDF_INFO: &[u8] = b"wash capture encryption v1";
+/// Size of the plaintext sealed in each chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Set in a chunk's length header to mark the final chunk, so truncated files are detected
+const LAST_CHUNK_FLAG: u32 = 1 << 31;
+const TAG_LEN: usize = 16;
+
+/// An X25519 key pair encoded like an nkeys xkey. Keys created from a public key can only be
+/// used to encrypt
+pub struct CurveKey {
+    public: [u8; 32],
+    seed: Option<[u8; 32]>,
+}
+
+impl CurveKey {
+    /// Generates a new random key pair
+    pub fn new() -> Result<Self> {
+        let mut seed = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut seed)
+            .map_err(|_| anyhow::anyhow!("Unable to generate a random key"))?;
+        Ok(Self::from_raw_seed(seed))
+    }
+
+    /// Parses an encoded seed (`SX...`)
+    pub fn from_seed(seed: &str) -> Result<Self> {
+        Ok(Self::from_raw_seed(xkey::decode_seed(seed.trim())?))
+    }
+
+    /// Parses an encoded public key (`X...`)
+    pub fn from_public_key(public_key: &str) -> Result<Self> {
+        Ok(CurveKey {
+            public: xkey::decode_public_key(public_key.trim())?,
+            seed: None,
+        })
+    }
+
+    fn from_raw_seed(seed: [u8; 32]) -> Self {
+        CurveKey {
+            public: (X25519_BASEPOINT * clamp(seed)).to_bytes(),
+            seed: Some(seed),
+        }
+    }
+
+    pub fn public_key(&self) -> String {
+        xkey::encode_public_key(&self.public)
+    }
+
+    /// Returns the encoded seed, or an error if the key was created from a public key
+    pub fn seed(&self) -> Result<String> {
+        let seed = self.seed.context("This key has no seed")?;
+        Ok(xkey::encode_seed(&seed))
+    }
+
+    /// Derives the chunk encryption key shared between this key's seed and the other public key
+    fn shared_key(&self, other: &[u8; 32], salt: &[u8]) -> Result<LessSafeKey> {
+        let seed = self
+            .seed
+            .context("A seed is required to derive a shared key")?;
+        let shared = MontgomeryPoint(*other) * clamp(seed);
+        if shared.as_bytes().iter().all(|b| *b == 0) {
+            bail!("Invalid curve public key");
+        }
+        let prk = Salt::new(HKDF_SHA256, salt).extract(shared.as_bytes());
+        let okm = prk
+            .expand(&[HKDF_INFO], &CHACHA20_POLY1305)
+            .map_err(|_| anyhow::anyhow!("Unable to derive encryption key"))?;
+        Ok(LessSafeKey::new(UnboundKey::from(okm)))
+    }
+}
+
+fn clamp(mut seed: [u8; 32]) -> Scalar {
+    seed[0] &= 248;
+    seed[31] &= 127;
+    seed[31] |= 64;
+    Scalar::from_bits(seed)
+}
+
+fn nonce(counter: u64, last: bool) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Reads until the buffer is full or the reader is exhausted, returning the number of bytes read
+async fn read_full<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypts everything read from `reader` for the recipient, writing the result to `writer`
+pub async fn encrypt<R, W>(mut reader: R, mut writer: W, recipient: &CurveKey) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let ephemeral = CurveKey::new()?;
+    let salt = [ephemeral.public, recipient.public].concat();
+    let key = ephemeral.shared_key(&recipient.public, &salt)?;
+    writer.write_all(MAGIC).await?;
+    writer.write_all(&ephemeral.public).await?;
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut counter = 0u64;
+    loop {
+        let n = read_full(&mut reader, &mut buf).await?;
+        let last = n < CHUNK_SIZE;
+        let mut chunk = buf[..n].to_vec();
+        key.seal_in_place_append_tag(nonce(counter, last), Aad::empty(), &mut chunk)
+            .map_err(|_| anyhow::anyhow!("Unable to encrypt capture"))?;
+        let header = chunk.len() as u32 | if last { LAST_CHUNK_FLAG } else { 0 };
+        writer.write_all(&header.to_be_bytes()).await?;
+        writer.write_all(&chunk).await?;
+        if last {
+            break;
+        }
+        counter += 1;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Decrypts a capture encrypted with [`encrypt`] using the recipient's seed. The reader must be
+/// positioned after [`MAGIC`]
+pub async fn decrypt<R, W>(mut reader: R, mut writer: W, key: &CurveKey) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut ephemeral = [0u8; 32];
+    reader.read_exact(&mut ephemeral).await?;
+    let salt = [ephemeral, key.public].concat();
+    let key = key.shared_key(&ephemeral, &salt)?;
+
+    let mut counter = 0u64;
+    loop {
+        let mut header = [0u8; 4];
+        reader
+            .read_exact(&mut header)
+            .await
+            .context("The encrypted capture is truncated")?;
+        let header = u32::from_be_bytes(header);
+        let last = header & LAST_CHUNK_FLAG != 0;
+        let len = (header & !LAST_CHUNK_FLAG) as usize;
+        if len > CHUNK_SIZE + TAG_LEN {
+            bail!("The encrypted capture is corrupted");
+        }
+        let mut chunk = vec![0u8; len];
+        reader
+            .read_exact(&mut chunk)
+            .await
+            .context("The encrypted capture is truncated")?;
+        let plaintext = key
+            .open_in_place(nonce(counter, last), Aad::empty(), &mut chunk)
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Unable to decrypt capture. Was it encrypted for the public key of this seed?"
+                )
+            })?;
+        writer.write_all(plaintext).await?;
+        if last {
+            break;
+        }
+        counter += 1;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_encoding() {
+        let key = CurveKey::new().unwrap();
+        let public_key = key.public_key();
+        let seed = key.seed().unwrap();
+        assert!(public_key.starts_with('X'));
+        assert!(seed.starts_with("SX"));
+
+        let restored = CurveKey::from_seed(&seed).unwrap();
+        assert_eq!(restored.public_key(), public_key);
+        let public = CurveKey::from_public_key(&public_key).unwrap();
+        assert!(public.seed().is_err());
+
+        assert!(CurveKey::from_public_key(&seed).is_err());
+        assert!(CurveKey::from_seed(&public_key).is_err());
+        let mut corrupted = public_key.into_bytes();
+        corrupted[5] = if corrupted[5] == b'A' { b'B' } else { b'A' };
+        assert!(CurveKey::from_public_key(std::str::from_utf8(&corrupted).unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_roundtrip() {
+        let recipient = CurveKey::new().unwrap();
+        let public = CurveKey::from_public_key(&recipient.public_key()).unwrap();
+        // Spans several chunks, with a partial chunk at the end
+        let data = (0..CHUNK_SIZE * 2 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let mut encrypted = Vec::new();
+        encrypt(&data[..], &mut encrypted, &public).await.unwrap();
+        assert_eq!(&encrypted[..MAGIC.len()], MAGIC);
+
+        let mut decrypted = Vec::new();
+        decrypt(&encrypted[MAGIC.len()..], &mut decrypted, &recipient)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, data);
+
+        let other = CurveKey::new().unwrap();
+        assert!(decrypt(&encrypted[MAGIC.len()..], Vec::new(), &other)
+            .await
+            .is_err());
+        let truncated = &encrypted[MAGIC.len()..encrypted.len() - 200];
+        assert!(decrypt(truncated, Vec::new(), &recipient).await.is_err());
+    }
+}
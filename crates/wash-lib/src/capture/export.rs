@@ -0,0 +1,350 @@
+//! Export of captures to formats that can be consumed without wash, such as JSON Lines and a
+//! HAR-like format for `wasmcloud:httpserver` traffic
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde_json::{json, Value as JsonValue};
+use time::format_description::well_known::Rfc3339;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use wasmbus_rpc::core::{Invocation, InvocationResponse, WasmCloudEntity};
+
+use super::{CaptureReader, LatticeEvent, SerializableMessage};
+use crate::httpserver::{
+    content_type, HttpRequest, HttpResponse, HANDLE_REQUEST, HTTPSERVER_CONTRACT,
+};
+use crate::spier::ObservedMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per captured message
+    JsonLines,
+    /// An HTTP Archive containing the httpserver requests and their responses
+    Har,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "jsonlines" | "json-lines" => Ok(ExportFormat::JsonLines),
+            "har" => Ok(ExportFormat::Har),
+            _ => bail!("unknown export format '{s}', expected 'jsonl' or 'har'"),
+        }
+    }
+}
+
+/// Writes all messages of the capture to `out` in the given format, returning the number of
+/// records (messages or HTTP requests) that were written
+pub async fn export_capture<W: AsyncWrite + Unpin>(
+    reader: &mut CaptureReader,
+    format: ExportFormat,
+    mut out: W,
+) -> Result<usize> {
+    let count = match format {
+        ExportFormat::JsonLines => {
+            let mut count = 0;
+            while let Some(msg) = reader.next_message().await? {
+                let mut line = serde_json::to_vec(&message_json(&msg)?)?;
+                line.push(b'\n');
+                out.write_all(&line).await?;
+                count += 1;
+            }
+            count
+        }
+        ExportFormat::Har => {
+            let mut har = HarBuilder::default();
+            while let Some(msg) = reader.next_message().await? {
+                har.add_message(&msg)?;
+            }
+            let count = har.entries.len();
+            out.write_all(&serde_json::to_vec_pretty(&har.finish())?)
+                .await?;
+            count
+        }
+    };
+    out.flush().await?;
+    Ok(count)
+}
+
+/// Converts a captured message to a JSON object, decoding invocations, responses and their
+/// payloads where possible
+pub fn message_json(msg: &SerializableMessage) -> Result<JsonValue> {
+    let mut value = json!({
+        "subject": msg.subject,
+        "reply": msg.reply,
+        "published": msg.published.format(&Rfc3339)?,
+        "size": msg.length,
+    });
+    let fields = if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+        json!({
+            "kind": "invocation",
+            "id": inv.id,
+            "origin": entity_json(&inv.origin),
+            "target": entity_json(&inv.target),
+            "operation": inv.operation,
+            "payload_size": inv.content_length.unwrap_or(inv.msg.len() as u64),
+            "payload": ObservedMessage::parse(inv.msg).to_json(),
+        })
+    } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+        json!({
+            "kind": "response",
+            "invocation_id": resp.invocation_id,
+            "error": resp.error,
+            "payload": ObservedMessage::parse(resp.msg).to_json(),
+        })
+    } else if let Some(event) = LatticeEvent::from_message(msg) {
+        json!({
+            "kind": "event",
+            "event_type": event.kind,
+            "source": event.source,
+            "payload": event.data,
+        })
+    } else {
+        json!({
+            "kind": "other",
+            "payload": ObservedMessage::parse(msg.payload.to_vec()).to_json(),
+        })
+    };
+    if let (Some(value), JsonValue::Object(fields)) = (value.as_object_mut(), fields) {
+        value.extend(fields);
+    }
+    Ok(value)
+}
+
+fn entity_json(entity: &WasmCloudEntity) -> JsonValue {
+    json!({
+        "public_key": entity.public_key,
+        "contract_id": entity.contract_id,
+        "link_name": entity.link_name,
+    })
+}
+
+/// Collects HAR entries for httpserver requests, filling in responses as they are found
+#[derive(Default)]
+struct HarBuilder {
+    entries: Vec<JsonValue>,
+    /// Entries still waiting for a response, keyed by invocation ID
+    pending: HashMap<String, (usize, time::OffsetDateTime)>,
+}
+
+impl HarBuilder {
+    fn add_message(&mut self, msg: &SerializableMessage) -> Result<()> {
+        if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            if inv.origin.contract_id != HTTPSERVER_CONTRACT || inv.operation != HANDLE_REQUEST {
+                return Ok(());
+            }
+            let Ok(req) = rmp_serde::from_slice::<HttpRequest>(&inv.msg) else {
+                return Ok(());
+            };
+            self.pending
+                .insert(inv.id, (self.entries.len(), msg.published));
+            self.entries
+                .push(har_entry(&req, &inv.target.public_key, msg.published)?);
+        } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            let Some((index, requested)) = self.pending.remove(&resp.invocation_id) else {
+                return Ok(());
+            };
+            let entry = &mut self.entries[index];
+            let elapsed = (msg.published - requested).as_seconds_f64() * 1000.0;
+            entry["time"] = json!(elapsed);
+            entry["timings"]["wait"] = json!(elapsed);
+            if let Some(error) = resp.error.filter(|e| !e.is_empty()) {
+                entry["response"]["_error"] = json!(error);
+            } else if let Ok(http) = rmp_serde::from_slice::<HttpResponse>(&resp.msg) {
+                entry["response"] = har_response(&http);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> JsonValue {
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": "wash",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": self.entries,
+            }
+        })
+    }
+}
+
+/// Builds an entry for the request. The response is empty (status 0) until it is captured
+fn har_entry(req: &HttpRequest, actor: &str, published: time::OffsetDateTime) -> Result<JsonValue> {
+    let host = req
+        .header
+        .get("host")
+        .and_then(|values| values.first())
+        .map(String::as_str)
+        .unwrap_or("localhost");
+    let url = if req.query_string.is_empty() {
+        format!("http://{host}{}", req.path)
+    } else {
+        format!("http://{host}{}?{}", req.path, req.query_string)
+    };
+    let query = req
+        .query_string
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            json!({ "name": name, "value": value })
+        })
+        .collect::<Vec<_>>();
+    let mut request = json!({
+        "method": req.method,
+        "url": url,
+        "httpVersion": "HTTP/1.1",
+        "cookies": [],
+        "headers": har_headers(&req.header),
+        "queryString": query,
+        "headersSize": -1,
+        "bodySize": req.body.len(),
+    });
+    if !req.body.is_empty() {
+        request["postData"] = json!({
+            "mimeType": content_type(&req.header).unwrap_or_default(),
+            "text": String::from_utf8_lossy(&req.body),
+        });
+    }
+    Ok(json!({
+        "startedDateTime": published.format(&Rfc3339)?,
+        "time": 0,
+        "request": request,
+        "response": {
+            "status": 0,
+            "statusText": "",
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": [],
+            "content": { "size": 0, "mimeType": "" },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "cache": {},
+        "timings": { "send": 0, "wait": 0, "receive": 0 },
+        "_actor": actor,
+    }))
+}
+
+fn har_response(resp: &HttpResponse) -> JsonValue {
+    json!({
+        "status": resp.status_code,
+        "statusText": "",
+        "httpVersion": "HTTP/1.1",
+        "cookies": [],
+        "headers": har_headers(&resp.header),
+        "content": {
+            "size": resp.body.len(),
+            "mimeType": content_type(&resp.header).unwrap_or_default(),
+            "text": String::from_utf8_lossy(&resp.body),
+        },
+        "redirectURL": "",
+        "headersSize": -1,
+        "bodySize": resp.body.len(),
+    })
+}
+
+/// Converts headers to HAR name/value pairs, sorted by name so the output is stable
+fn har_headers(headers: &HashMap<String, Vec<String>>) -> Vec<JsonValue> {
+    let mut names = headers.keys().collect::<Vec<_>>();
+    names.sort();
+    names
+        .into_iter()
+        .flat_map(|name| {
+            headers[name]
+                .iter()
+                .map(move |value| json!({ "name": name, "value": value }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(payload: Vec<u8>, published: time::OffsetDateTime) -> SerializableMessage {
+        SerializableMessage {
+            subject: "wasmbus.rpc.default.MACTOR".to_string(),
+            reply: None,
+            length: payload.len(),
+            payload: payload.into(),
+            description: None,
+            published,
+        }
+    }
+
+    #[test]
+    fn test_export_har() {
+        let start = time::OffsetDateTime::now_utc();
+        let mut inv = Invocation::default();
+        inv.origin.public_key = "VHTTPSERVER".to_string();
+        inv.origin.contract_id = HTTPSERVER_CONTRACT.to_string();
+        inv.origin.link_name = "default".to_string();
+        inv.target.public_key = "MACTOR".to_string();
+        inv.operation = HANDLE_REQUEST.to_string();
+        inv.id = "1".to_string();
+        inv.msg = HttpRequest {
+            method: "POST".to_string(),
+            path: "/orders".to_string(),
+            query_string: "verbose=true".to_string(),
+            header: HashMap::from([("host".to_string(), vec!["example.com".to_string()])]),
+            body: b"{}".to_vec(),
+        }
+        .to_bytes()
+        .unwrap();
+        let request = message(rmp_serde::to_vec_named(&inv).unwrap(), start);
+
+        let mut resp = InvocationResponse::default();
+        resp.invocation_id = "1".to_string();
+        resp.msg = rmp_serde::to_vec_named(&HttpResponse {
+            status_code: 201,
+            header: HashMap::from([("content-type".to_string(), vec!["text/plain".to_string()])]),
+            body: b"created".to_vec(),
+        })
+        .unwrap();
+        let response = message(
+            rmp_serde::to_vec_named(&resp).unwrap(),
+            start + time::Duration::milliseconds(20),
+        );
+
+        let json = message_json(&request).unwrap();
+        assert_eq!(json["kind"], "invocation");
+        assert_eq!(json["operation"], HANDLE_REQUEST);
+        assert_eq!(json["payload"]["path"], "/orders");
+        assert_eq!(message_json(&response).unwrap()["kind"], "response");
+
+        let mut har = HarBuilder::default();
+        har.add_message(&request).unwrap();
+        har.add_message(&response).unwrap();
+        let har = har.finish();
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(
+            entry["request"]["url"],
+            "http://example.com/orders?verbose=true"
+        );
+        assert_eq!(
+            entry["request"]["queryString"],
+            json!([{"name": "verbose", "value": "true"}])
+        );
+        assert_eq!(entry["request"]["postData"]["text"], "{}");
+        assert_eq!(entry["response"]["status"], 201);
+        assert_eq!(entry["response"]["content"]["text"], "created");
+        assert_eq!(entry["time"], 20.0);
+    }
+
+    #[test]
+    fn test_export_format() {
+        assert_eq!(
+            "jsonl".parse::<ExportFormat>().unwrap(),
+            ExportFormat::JsonLines
+        );
+        assert_eq!("HAR".parse::<ExportFormat>().unwrap(), ExportFormat::Har);
+        assert!("csv".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,1338 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_compression::tokio::{bufread::GzipDecoder, write::GzipEncoder};
+use futures::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+};
+use tokio_tar::{Archive, Entries};
+use wasmbus_rpc::core::{Invocation, InvocationResponse, LinkDefinition};
+use wasmcloud_control_interface::HostInventory;
+
+use crate::id::{ModuleId, ServiceId};
+use crate::spier::ObservedMessage;
+
+mod crypto;
+mod export;
+mod redact;
+mod xkey;
+pub use crypto::CurveKey;
+pub use export::{export_capture, message_json, ExportFormat};
+pub use redact::{Redactor, REDACTED};
+
+pub const INVENTORY_FILE: &str = "inventory.json";
+pub const MESSAGES_DIR: &str = "messages";
+pub const LINKS_FILE: &str = "links.json";
+/// Prefix of the subject that lattice control events are published on, followed by the lattice
+pub const EVENT_SUBJECT_PREFIX: &str = "wasmbus.evt";
+/// Prefix of the subject that recorded invocation responses are published on, followed by the
+/// lattice
+pub const RESPONSE_SUBJECT_PREFIX: &str = "wash.capture.responses";
+
+/// A subset of NATS message info that we need to serialize for now. Basically it is all the types that easily
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializableMessage {
+    pub subject: String,
+    pub reply: Option<String>,
+    pub payload: bytes::Bytes,
+    pub description: Option<String>,
+    pub length: usize,
+    pub published: time::OffsetDateTime,
+}
+
+impl TryFrom<async_nats::jetstream::Message> for SerializableMessage {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: async_nats::jetstream::Message) -> Result<Self, Self::Error> {
+        let published = msg.info().map_err(|e| anyhow::anyhow!("{e:?}"))?.published;
+        Ok(Self {
+            subject: msg.message.subject,
+            reply: msg.message.reply,
+            payload: msg.message.payload,
+            description: msg.message.description,
+            length: msg.message.length,
+            published,
+        })
+    }
+}
+
+/// Criteria used to select messages while reading a capture. All criteria that are set must
+/// match for a message to be returned.
+///
+/// The actor and provider criteria only apply to invocations. Lattice events are only selected by
+/// time and subject, and invocation responses, which don't name their origin or target, are
+/// returned by a [`CaptureReader`] if and only if the invocation they answer was returned
+#[derive(Debug, Default, Clone)]
+pub struct CaptureFilter {
+    /// Only return messages published at or after this time
+    pub start: Option<time::OffsetDateTime>,
+    /// Only return messages published at or before this time
+    pub end: Option<time::OffsetDateTime>,
+    /// Only return messages whose subject matches this NATS subject, which may contain the `*`
+    /// and `>` wildcards
+    pub subject: Option<String>,
+    /// Only return invocations sent to or from this actor
+    pub actor_id: Option<ModuleId>,
+    /// Only return invocations sent to or from this provider
+    pub provider_id: Option<ServiceId>,
+}
+
+impl CaptureFilter {
+    /// Returns true if the message matches the filter. Responses are matched like any other
+    /// message that isn't an invocation, use a [`CaptureReader`] to pair them with invocations
+    pub fn matches(&self, msg: &SerializableMessage) -> bool {
+        if matches!(self.start, Some(start) if msg.published < start)
+            || matches!(self.end, Some(end) if msg.published > end)
+        {
+            return false;
+        }
+        if let Some(pattern) = &self.subject {
+            if !subject_matches(pattern, &msg.subject) {
+                return false;
+            }
+        }
+        if self.actor_id.is_none() && self.provider_id.is_none() {
+            return true;
+        }
+        match rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            Ok(inv) => self.invocation_matches(&inv),
+            Err(_) => true,
+        }
+    }
+
+    /// Returns true if the actor and provider criteria match the origin or target of the
+    /// invocation
+    fn invocation_matches(&self, inv: &Invocation) -> bool {
+        let is_endpoint = |id: &str| inv.origin.public_key == id || inv.target.public_key == id;
+        self.actor_id
+            .as_ref()
+            .map(|id| is_endpoint(id.as_ref()))
+            .unwrap_or(true)
+            && self
+                .provider_id
+                .as_ref()
+                .map(|id| is_endpoint(id.as_ref()))
+                .unwrap_or(true)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start.is_none()
+            && self.end.is_none()
+            && self.subject.is_none()
+            && self.actor_id.is_none()
+            && self.provider_id.is_none()
+    }
+}
+
+/// Matches a NATS subject against a pattern that may contain the `*` (single token) and `>`
+/// (one or more trailing tokens) wildcards
+fn subject_matches(pattern: &str, subject: &str) -> bool {
+    let mut subject_tokens = subject.split('.');
+    for token in pattern.split('.') {
+        match (token, subject_tokens.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (token, Some(actual)) if token == actual => {}
+            _ => return false,
+        }
+    }
+    subject_tokens.next().is_none()
+}
+
+/// A streaming reader for capture tarballs, which reads messages one at a time rather than
+/// loading the whole capture into memory.
+///
+/// NOTE: The interior structure of the tarball is not a guaranteed API and may change in the
+/// future. All interactions should be done through this type or [`ReadCapture`]
+pub struct CaptureReader {
+    entries: Entries<GzipDecoder<BufReader<File>>>,
+    inventory: Vec<HostInventory>,
+    links: Vec<LinkDefinition>,
+    /// A message entry that was read while looking for the inventory
+    buffered: Option<SerializableMessage>,
+    filter: CaptureFilter,
+    /// IDs of the returned invocations whose response hasn't been read yet
+    returned_invocations: HashSet<String>,
+}
+
+impl CaptureReader {
+    /// Opens the capture file at the given path, reading the inventory and links but no messages
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_key(path, None).await
+    }
+
+    /// Opens the capture file at the given path like [`CaptureReader::open`]. If the capture is
+    /// encrypted, it is decrypted with the key, which must have the seed of the recipient key
+    pub async fn open_with_key(path: impl AsRef<Path>, key: Option<&CurveKey>) -> Result<Self> {
+        let mut file = File::open(path).await?;
+        let mut magic = [0u8; 8];
+        let encrypted = file.read_exact(&mut magic).await.is_ok() && &magic == crypto::MAGIC;
+        if encrypted {
+            let key = key.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "This capture is encrypted, provide the seed of the key it was encrypted for"
+                )
+            })?;
+            // Decrypt to an anonymous temporary file, which is removed once the reader is dropped
+            let mut decrypted = File::from_std(tempfile::tempfile()?);
+            crypto::decrypt(BufReader::new(file), &mut decrypted, key).await?;
+            file = decrypted;
+        }
+        file.seek(SeekFrom::Start(0)).await?;
+        let mut archive = Archive::new(GzipDecoder::new(BufReader::new(file)));
+        let mut reader = CaptureReader {
+            entries: archive.entries()?,
+            inventory: Vec::new(),
+            links: Vec::new(),
+            buffered: None,
+            filter: CaptureFilter::default(),
+            returned_invocations: HashSet::new(),
+        };
+        // The inventory and links are always written before any messages. Once we see the first
+        // message, hold on to it so it is still returned
+        while let Some(entry) = reader.next_entry().await? {
+            match entry {
+                CaptureEntry::Inventory(inventory) => reader.inventory = inventory,
+                CaptureEntry::Links(links) => reader.links = links,
+                CaptureEntry::Message(msg) => {
+                    reader.buffered = Some(msg);
+                    break;
+                }
+            }
+        }
+        Ok(reader)
+    }
+
+    /// Only return messages that match the given filter
+    pub fn with_filter(mut self, filter: CaptureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// The host inventory recorded when the capture was started
+    pub fn inventory(&self) -> &[HostInventory] {
+        &self.inventory
+    }
+
+    /// The link definitions recorded when the capture was started. Captures taken with older
+    /// versions of wash do not contain links
+    pub fn links(&self) -> &[LinkDefinition] {
+        &self.links
+    }
+
+    /// Returns the next message matching the filter, in the order they were captured, or `None`
+    /// once the capture has been read completely
+    pub async fn next_message(&mut self) -> Result<Option<SerializableMessage>> {
+        if let Some(msg) = self.buffered.take() {
+            if self.keep(&msg) {
+                return Ok(Some(msg));
+            }
+        }
+        while let Some(entry) = self.next_entry().await? {
+            match entry {
+                CaptureEntry::Message(msg) if self.keep(&msg) => return Ok(Some(msg)),
+                CaptureEntry::Message(_) => {}
+                CaptureEntry::Inventory(inventory) => self.inventory = inventory,
+                CaptureEntry::Links(links) => self.links = links,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies the filter to the message, keeping responses only if their invocation was kept
+    fn keep(&mut self, msg: &SerializableMessage) -> bool {
+        if self.filter.is_empty() {
+            return true;
+        }
+        if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            let keep = self.filter.matches(msg);
+            if keep {
+                self.returned_invocations.insert(inv.id);
+            }
+            keep
+        } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            self.returned_invocations.remove(&resp.invocation_id)
+        } else {
+            self.filter.matches(msg)
+        }
+    }
+
+    /// Converts the reader into a stream of messages matching the filter
+    pub fn into_stream(self) -> impl Stream<Item = Result<SerializableMessage>> {
+        futures::stream::try_unfold(self, |mut reader| async move {
+            Ok(reader.next_message().await?.map(|msg| (msg, reader)))
+        })
+    }
+
+    /// Reads the next known entry from the tarball, skipping anything else
+    async fn next_entry(&mut self) -> Result<Option<CaptureEntry>> {
+        while let Some(mut entry) = self.entries.try_next().await? {
+            let path = entry.path()?;
+            if path.file_name().unwrap_or_default() == INVENTORY_FILE {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).await?;
+                // We can't use a reader because it is async
+                return Ok(Some(CaptureEntry::Inventory(serde_json::from_slice(&buf)?)));
+            } else if path.file_name().unwrap_or_default() == LINKS_FILE {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).await?;
+                return Ok(Some(CaptureEntry::Links(serde_json::from_slice(&buf)?)));
+            } else if path
+                .parent()
+                .and_then(|p| p.file_name())
+                .unwrap_or_default()
+                == MESSAGES_DIR
+                && path.extension().unwrap_or_default() == "json"
+            {
+                // For any path that matches messages/*.json, we want to read the file and
+                // deserialize it. Ordering should be the same as we wrote it (in order), so reading
+                // out should be ok
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).await?;
+                return Ok(Some(CaptureEntry::Message(serde_json::from_slice(&buf)?)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+enum CaptureEntry {
+    Inventory(Vec<HostInventory>),
+    Links(Vec<LinkDefinition>),
+    Message(SerializableMessage),
+}
+
+/// A read capture is a parsed tarball that contains all of the messages and inventory for a given
+/// capture.
+///
+/// This loads all of the data into memory, so prefer [`CaptureReader`] for large captures.
+///
+/// NOTE: The interior structure of the tarball is not a guaranteed API and may change in the
+/// future. All interactions should be done through this type
+pub struct ReadCapture {
+    pub inventory: Vec<HostInventory>,
+    pub links: Vec<LinkDefinition>,
+    pub messages: Vec<SerializableMessage>,
+}
+
+impl ReadCapture {
+    /// Loads the given capture file from the path and returns all of the data
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = CaptureReader::open(path).await?;
+        let mut messages = Vec::new();
+        while let Some(msg) = reader.next_message().await? {
+            messages.push(msg);
+        }
+        Ok(ReadCapture {
+            inventory: reader.inventory,
+            links: reader.links,
+            messages,
+        })
+    }
+}
+
+/// A lattice control event, such as an actor starting or a link being put, that was captured
+/// alongside the RPC messages
+#[derive(Debug, Clone)]
+pub struct LatticeEvent {
+    /// The type of the event without the `com.wasmcloud.lattice.` prefix, e.g. `actor_started`
+    pub kind: String,
+    /// The host that published the event
+    pub source: String,
+    pub data: serde_json::Value,
+    pub published: time::OffsetDateTime,
+}
+
+impl LatticeEvent {
+    /// Parses the message as a control event, returning `None` if it was not published on the
+    /// lattice event subject or isn't a valid CloudEvent
+    pub fn from_message(msg: &SerializableMessage) -> Option<Self> {
+        if !subject_matches(&format!("{EVENT_SUBJECT_PREFIX}.*"), &msg.subject) {
+            return None;
+        }
+        let event: serde_json::Value = serde_json::from_slice(&msg.payload).ok()?;
+        let kind = event.get("type")?.as_str()?;
+        Some(LatticeEvent {
+            kind: kind
+                .strip_prefix("com.wasmcloud.lattice.")
+                .unwrap_or(kind)
+                .to_string(),
+            source: event
+                .get("source")
+                .and_then(|s| s.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            data: event.get("data").cloned().unwrap_or_default(),
+            published: msg.published,
+        })
+    }
+
+    /// A short description of what the event is about, such as the actor or link it concerns
+    pub fn summary(&self) -> String {
+        let field = |name: &str| self.data.get(name).and_then(|v| v.as_str());
+        match self.kind.as_str() {
+            "linkdef_set" | "linkdef_deleted" => format!(
+                "{} -> {} ({}, link {})",
+                field("actor_id").unwrap_or_default(),
+                field("provider_id").unwrap_or_default(),
+                field("contract_id").unwrap_or_default(),
+                field("link_name").unwrap_or_default(),
+            ),
+            _ => {
+                let mut summary = field("public_key").unwrap_or_default().to_string();
+                if let Some(link_name) = field("link_name") {
+                    summary.push_str(&format!(" (link {link_name})"));
+                }
+                if let Some(image_ref) = field("image_ref").filter(|r| !r.is_empty()) {
+                    summary.push_str(&format!(" from {image_ref}"));
+                }
+                summary
+            }
+        }
+    }
+}
+
+/// Statistics for the invocations of one operation between an origin and a target
+#[derive(Debug, Clone, Default)]
+pub struct OperationStats {
+    /// Public key of the invoking actor or provider
+    pub origin: String,
+    /// Public key of the invoked actor or provider
+    pub target: String,
+    pub operation: String,
+    /// Number of invocations
+    pub count: u64,
+    /// Number of captured responses that contained an error
+    pub errors: u64,
+    /// Number of captured responses, successful or not
+    pub responses: u64,
+    /// Sizes of the invocation payloads in bytes, sorted ascending
+    pub payload_sizes: Vec<usize>,
+    /// Time between publishing the invocation and its response, sorted ascending. Only available
+    /// when the capture includes responses
+    pub latencies: Vec<std::time::Duration>,
+}
+
+impl OperationStats {
+    /// Returns the payload size at the given percentile (0-100)
+    pub fn payload_percentile(&self, pct: f64) -> Option<usize> {
+        percentile(&self.payload_sizes, pct)
+    }
+
+    /// Returns the latency at the given percentile (0-100)
+    pub fn latency_percentile(&self, pct: f64) -> Option<std::time::Duration> {
+        percentile(&self.latencies, pct)
+    }
+}
+
+/// Returns the value at the given percentile (0-100) of sorted values, using the nearest-rank
+/// method. Returns `None` if there are no values
+pub fn percentile<T: Copy>(sorted: &[T], pct: f64) -> Option<T> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len()) - 1).copied()
+}
+
+/// Accumulates per-operation statistics from captured messages. Invocations are paired with
+/// their responses using the invocation ID
+#[derive(Debug, Default)]
+pub struct CaptureStats {
+    operations: HashMap<(String, String, String), OperationStats>,
+    /// Invocations that have not seen a response yet, keyed by invocation ID
+    pending: HashMap<String, ((String, String, String), time::OffsetDateTime)>,
+}
+
+impl CaptureStats {
+    /// Reads all messages from the reader and computes their statistics
+    pub async fn from_reader(reader: &mut CaptureReader) -> Result<Vec<OperationStats>> {
+        let mut stats = CaptureStats::default();
+        while let Some(msg) = reader.next_message().await? {
+            stats.add_message(&msg);
+        }
+        Ok(stats.finish())
+    }
+
+    /// Records a captured invocation or response. Other messages are ignored
+    pub fn add_message(&mut self, msg: &SerializableMessage) {
+        if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            let key = (inv.origin.public_key, inv.target.public_key, inv.operation);
+            let entry = self
+                .operations
+                .entry(key.clone())
+                .or_insert_with(|| OperationStats {
+                    origin: key.0.clone(),
+                    target: key.1.clone(),
+                    operation: key.2.clone(),
+                    ..Default::default()
+                });
+            entry.count += 1;
+            entry.payload_sizes.push(
+                inv.content_length
+                    .map(|len| len as usize)
+                    .unwrap_or(inv.msg.len()),
+            );
+            self.pending.insert(inv.id, (key, msg.published));
+        } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            let Some((key, published)) = self.pending.remove(&resp.invocation_id) else {
+                return;
+            };
+            if let Some(entry) = self.operations.get_mut(&key) {
+                entry.responses += 1;
+                if resp.error.map(|e| !e.is_empty()).unwrap_or(false) {
+                    entry.errors += 1;
+                }
+                if let Ok(latency) = (msg.published - published).try_into() {
+                    entry.latencies.push(latency);
+                }
+            }
+        }
+    }
+
+    /// Returns the statistics of every operation, sorted by origin, target and operation
+    pub fn finish(self) -> Vec<OperationStats> {
+        let mut operations = self
+            .operations
+            .into_values()
+            .map(|mut stats| {
+                stats.payload_sizes.sort_unstable();
+                stats.latencies.sort_unstable();
+                stats
+            })
+            .collect::<Vec<_>>();
+        operations.sort_by(|a, b| {
+            (&a.origin, &a.target, &a.operation).cmp(&(&b.origin, &b.target, &b.operation))
+        });
+        operations
+    }
+}
+
+/// Returns the names of the actors and providers in the inventory, keyed by public key
+pub fn entity_names(inventory: &[HostInventory]) -> HashMap<String, String> {
+    inventory
+        .iter()
+        .flat_map(|host| {
+            host.actors
+                .iter()
+                .filter_map(|actor| Some((actor.id.clone(), actor.name.clone()?)))
+                .chain(
+                    host.providers
+                        .iter()
+                        .filter_map(|prov| Some((prov.id.clone(), prov.name.clone()?))),
+                )
+        })
+        .collect()
+}
+
+/// Origin, target and operation of an invocation, used to align invocations between captures
+pub type OperationKey = (String, String, String);
+
+/// A single captured invocation reduced to what is compared between captures
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CallRecord {
+    request_shape: String,
+    response_shape: Option<String>,
+    error: Option<bool>,
+}
+
+/// Reads all invocations from the reader, grouped by operation in the order they were captured
+async fn read_calls(reader: &mut CaptureReader) -> Result<HashMap<OperationKey, Vec<CallRecord>>> {
+    let mut calls: HashMap<OperationKey, Vec<CallRecord>> = HashMap::new();
+    let mut pending: HashMap<String, (OperationKey, usize)> = HashMap::new();
+    while let Some(msg) = reader.next_message().await? {
+        if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            let key = (inv.origin.public_key, inv.target.public_key, inv.operation);
+            let records = calls.entry(key.clone()).or_default();
+            records.push(CallRecord {
+                request_shape: payload_shape(inv.msg),
+                response_shape: None,
+                error: None,
+            });
+            pending.insert(inv.id, (key, records.len() - 1));
+        } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            let Some((key, index)) = pending.remove(&resp.invocation_id) else {
+                continue;
+            };
+            if let Some(record) = calls.get_mut(&key).and_then(|r| r.get_mut(index)) {
+                let error = resp.error.map(|e| !e.is_empty()).unwrap_or(false);
+                record.error = Some(error);
+                if !error {
+                    record.response_shape = Some(payload_shape(resp.msg));
+                }
+            }
+        }
+    }
+    Ok(calls)
+}
+
+/// Describes the structure of a payload, decoded with [`ObservedMessage::parse`], without its
+/// values. Objects keep their keys, arrays are described by their distinct element shapes, and
+/// everything else is replaced by its type name. Payloads that can't be decoded are `binary`
+pub fn payload_shape(payload: Vec<u8>) -> String {
+    fn shape(value: &serde_json::Value) -> serde_json::Value {
+        use serde_json::Value;
+        match value {
+            Value::Null => Value::from("null"),
+            Value::Bool(_) => Value::from("bool"),
+            Value::Number(_) => Value::from("number"),
+            Value::String(_) => Value::from("string"),
+            Value::Array(items) => {
+                let mut shapes: Vec<Value> = Vec::new();
+                for item in items.iter().map(shape) {
+                    if !shapes.contains(&item) {
+                        shapes.push(item);
+                    }
+                }
+                Value::Array(shapes)
+            }
+            Value::Object(map) => {
+                Value::Object(map.iter().map(|(k, v)| (k.clone(), shape(v))).collect())
+            }
+        }
+    }
+    match ObservedMessage::parse(payload) {
+        ObservedMessage::Raw(_) => "binary".to_string(),
+        parsed => shape(&parsed.to_json()).to_string(),
+    }
+}
+
+/// A payload shape that differs between aligned invocations of two captures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShapeChange {
+    /// Position of the first invocation of the operation with this change
+    pub index: usize,
+    /// Number of aligned invocations with this change
+    pub count: usize,
+    pub before: String,
+    pub after: String,
+}
+
+/// How an operation differs between two captures
+#[derive(Debug, Clone, Default)]
+pub struct OperationDiff {
+    pub origin: String,
+    pub target: String,
+    pub operation: String,
+    /// Number of invocations in the first capture
+    pub before_count: usize,
+    /// Number of invocations in the second capture
+    pub after_count: usize,
+    pub request_shapes: Vec<ShapeChange>,
+    pub response_shapes: Vec<ShapeChange>,
+    /// Fraction of captured responses that were errors in the first capture, if any responses
+    /// were captured
+    pub before_error_rate: Option<f64>,
+    /// Fraction of captured responses that were errors in the second capture, if any responses
+    /// were captured
+    pub after_error_rate: Option<f64>,
+}
+
+impl OperationDiff {
+    /// The operation only appears in the second capture
+    pub fn is_new(&self) -> bool {
+        self.before_count == 0
+    }
+
+    /// The operation only appears in the first capture
+    pub fn is_missing(&self) -> bool {
+        self.after_count == 0
+    }
+
+    pub fn error_rate_changed(&self) -> bool {
+        self.before_error_rate != self.after_error_rate
+    }
+
+    /// Returns true if anything about the operation differs between the captures
+    pub fn has_changes(&self) -> bool {
+        self.before_count != self.after_count
+            || !self.request_shapes.is_empty()
+            || !self.response_shapes.is_empty()
+            || self.error_rate_changed()
+    }
+}
+
+/// Compares two captures. Invocations are aligned by operation and then by the order they were
+/// captured in, so the nth invocation of an operation in the first capture is compared with the
+/// nth invocation of the same operation in the second
+pub async fn diff_captures(
+    before: &mut CaptureReader,
+    after: &mut CaptureReader,
+) -> Result<Vec<OperationDiff>> {
+    Ok(diff_calls(
+        read_calls(before).await?,
+        read_calls(after).await?,
+    ))
+}
+
+fn diff_calls(
+    mut before: HashMap<OperationKey, Vec<CallRecord>>,
+    mut after: HashMap<OperationKey, Vec<CallRecord>>,
+) -> Vec<OperationDiff> {
+    let mut keys = before
+        .keys()
+        .chain(after.keys())
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .map(|key| {
+            let before = before.remove(&key).unwrap_or_default();
+            let after = after.remove(&key).unwrap_or_default();
+            let mut request_shapes = Vec::new();
+            let mut response_shapes = Vec::new();
+            for (index, (b, a)) in before.iter().zip(after.iter()).enumerate() {
+                record_shape_change(
+                    &mut request_shapes,
+                    index,
+                    &b.request_shape,
+                    &a.request_shape,
+                );
+                if let (Some(b), Some(a)) = (&b.response_shape, &a.response_shape) {
+                    record_shape_change(&mut response_shapes, index, b, a);
+                }
+            }
+            OperationDiff {
+                before_count: before.len(),
+                after_count: after.len(),
+                request_shapes,
+                response_shapes,
+                before_error_rate: error_rate(&before),
+                after_error_rate: error_rate(&after),
+                origin: key.0,
+                target: key.1,
+                operation: key.2,
+            }
+        })
+        .collect()
+}
+
+fn record_shape_change(changes: &mut Vec<ShapeChange>, index: usize, before: &str, after: &str) {
+    if before == after {
+        return;
+    }
+    match changes
+        .iter_mut()
+        .find(|change| change.before == before && change.after == after)
+    {
+        Some(change) => change.count += 1,
+        None => changes.push(ShapeChange {
+            index,
+            count: 1,
+            before: before.to_owned(),
+            after: after.to_owned(),
+        }),
+    }
+}
+
+fn error_rate(records: &[CallRecord]) -> Option<f64> {
+    let responses = records.iter().filter_map(|r| r.error).collect::<Vec<_>>();
+    if responses.is_empty() {
+        return None;
+    }
+    Some(responses.iter().filter(|e| **e).count() as f64 / responses.len() as f64)
+}
+
+pub struct WriteCapture {
+    builder: tokio_tar::Builder<GzipEncoder<File>>,
+    current_index: usize,
+    redactor: Option<Redactor>,
+    /// The path to write the encrypted capture to once it is finished, and the key to encrypt it
+    /// for
+    encryption: Option<(PathBuf, CurveKey)>,
+}
+
+impl WriteCapture {
+    /// Create a new WriteCapture that will write the capture tarball to the given path with the
+    /// expected inventory
+    pub async fn start(inventory: Vec<HostInventory>, path: impl AsRef<Path>) -> Result<Self> {
+        Self::start_with_file(inventory, File::create(path).await?).await
+    }
+
+    /// Create a new WriteCapture like [`WriteCapture::start`], but encrypt the capture for the
+    /// recipient's curve key. Until the capture is finished, it is written to an anonymous
+    /// temporary file so no unencrypted data is left on disk
+    pub async fn start_encrypted(
+        inventory: Vec<HostInventory>,
+        path: impl AsRef<Path>,
+        recipient: CurveKey,
+    ) -> Result<Self> {
+        let file = File::from_std(tempfile::tempfile()?);
+        let mut capture = Self::start_with_file(inventory, file).await?;
+        capture.encryption = Some((path.as_ref().to_path_buf(), recipient));
+        Ok(capture)
+    }
+
+    async fn start_with_file(inventory: Vec<HostInventory>, file: File) -> Result<Self> {
+        let encoder = GzipEncoder::new(file);
+        let mut builder = tokio_tar::Builder::new(encoder);
+        // We always start by encoding the inventory first
+        let inventory_data = serde_json::to_vec(&inventory)?;
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(inventory_data.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, INVENTORY_FILE, Cursor::new(inventory_data))
+            .await?;
+        Ok(Self {
+            builder,
+            current_index: 0,
+            redactor: None,
+            encryption: None,
+        })
+    }
+
+    /// Redact the payloads of all messages added to the capture with the given redactor
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Adds the link definitions in the lattice to the capture. This should be called before any
+    /// messages are added
+    pub async fn add_links(&mut self, links: Vec<LinkDefinition>) -> Result<()> {
+        let data = serde_json::to_vec(&links)?;
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        self.builder
+            .append_data(&mut header, LINKS_FILE, Cursor::new(data))
+            .await?;
+        Ok(())
+    }
+
+    /// Adds an observed message to the capture
+    pub async fn add_message(&mut self, msg: SerializableMessage) -> Result<()> {
+        let msg = match &self.redactor {
+            Some(redactor) => redactor.redact_message(msg),
+            None => msg,
+        };
+        // NOTE(thomastaylor312): If encoding in json becomes a bottleneck, we can switch to a more
+        // efficient format, but I figured this could be easier for people to read if someone
+        // unpacks the message themselves
+        let data = serde_json::to_vec(&msg)?;
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        // Name of the file is an incrementing index and the timestamp of the message
+        let path = PathBuf::from(MESSAGES_DIR).join(format!(
+            "{}-{}.json",
+            self.current_index,
+            msg.published
+                .format(&time::format_description::well_known::Rfc3339)?
+        ));
+        self.builder
+            .append_data(&mut header, path, Cursor::new(data))
+            .await?;
+        self.current_index += 1;
+        Ok(())
+    }
+
+    /// Marks the tarball write as complete and flushes the underlying writer to disk
+    pub async fn finish(self) -> Result<()> {
+        let mut encoder = self.builder.into_inner().await?;
+        encoder.flush().await?;
+        encoder.shutdown().await?;
+        if let Some((path, recipient)) = self.encryption {
+            let mut file = encoder.into_inner();
+            file.seek(SeekFrom::Start(0)).await?;
+            let out = File::create(path).await?;
+            crypto::encrypt(BufReader::new(file), BufWriter::new(out), &recipient).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTOR_ID: &str = "MDPDJEYIAK6MACO67PRFGOSSLODBISK4SCEYDY3HEOY4P5CVJN6UCWUK";
+    const OTHER_ACTOR_ID: &str = "MBCFOPM6JW2APJLXJD3Z5O4CN7CPYJ2B4FTKLJUR5YR5MITIU7HD3WD5";
+    const PROVIDER_ID: &str = "VAG3QITQQ2ODAOWB5TTQSDJ53XK3SHBEIFNK4AYJ5RKAX2UNSCAPHA5M";
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tarball = tempdir.path().join("capture.tar.gz");
+        let mut capture = WriteCapture::start(
+            vec![HostInventory {
+                host_id: "test".to_string(),
+                ..Default::default()
+            }],
+            &tarball,
+        )
+        .await
+        .expect("Should be able to start a capture");
+        capture
+            .add_message(SerializableMessage {
+                subject: "first".to_string(),
+                reply: None,
+                payload: bytes::Bytes::from("test"),
+                description: None,
+                length: 5,
+                published: time::OffsetDateTime::now_utc(),
+            })
+            .await
+            .expect("Should be able to add a message");
+        capture
+            .add_message(SerializableMessage {
+                subject: "second".to_string(),
+                reply: None,
+                payload: bytes::Bytes::from("test"),
+                description: None,
+                length: 6,
+                published: time::OffsetDateTime::now_utc(),
+            })
+            .await
+            .expect("Should be able to add a message");
+
+        capture
+            .finish()
+            .await
+            .expect("Should be able to finish a capture");
+
+        let capture = ReadCapture::load(&tarball)
+            .await
+            .expect("Should be able to load a capture");
+
+        assert_eq!(
+            capture.inventory.len(),
+            1,
+            "Should have the correct inventory"
+        );
+        assert_eq!(
+            capture.inventory[0].host_id, "test",
+            "Should have the correct inventory"
+        );
+        assert_eq!(
+            capture.messages.len(),
+            2,
+            "Should have the right amount of messages"
+        );
+        assert_eq!(
+            capture.messages[0].subject, "first",
+            "Should have the right ordering"
+        );
+        assert_eq!(
+            capture.messages[1].subject, "second",
+            "Should have the right ordering"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filtered_reader() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tarball = tempdir.path().join("capture.tar.gz");
+        let start = time::OffsetDateTime::now_utc();
+        let mut capture = WriteCapture::start(Vec::new(), &tarball).await.unwrap();
+        for (i, subject) in [
+            "wasmbus.rpc.default.MACTOR",
+            "wasmbus.rpc.default.VPROVIDER.default",
+            "wasmbus.rpc.other.MACTOR",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            capture
+                .add_message(SerializableMessage {
+                    subject: subject.to_string(),
+                    reply: None,
+                    payload: bytes::Bytes::from("test"),
+                    description: None,
+                    length: 4,
+                    published: start + time::Duration::seconds(i as i64),
+                })
+                .await
+                .unwrap();
+        }
+        capture.finish().await.unwrap();
+
+        let read = |filter: CaptureFilter| {
+            let tarball = tarball.clone();
+            async move {
+                CaptureReader::open(&tarball)
+                    .await
+                    .unwrap()
+                    .with_filter(filter)
+                    .into_stream()
+                    .map_ok(|msg| msg.subject)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(read(CaptureFilter::default()).await.len(), 3);
+        assert_eq!(
+            read(CaptureFilter {
+                subject: Some("wasmbus.rpc.default.>".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![
+                "wasmbus.rpc.default.MACTOR",
+                "wasmbus.rpc.default.VPROVIDER.default"
+            ]
+        );
+        assert_eq!(
+            read(CaptureFilter {
+                subject: Some("wasmbus.rpc.*.MACTOR".to_string()),
+                start: Some(start + time::Duration::seconds(1)),
+                ..Default::default()
+            })
+            .await,
+            vec!["wasmbus.rpc.other.MACTOR"]
+        );
+        assert!(read(CaptureFilter {
+            end: Some(start - time::Duration::seconds(1)),
+            ..Default::default()
+        })
+        .await
+        .is_empty());
+    }
+
+    fn invocation_message(id: &str, origin: &str, target: &str) -> SerializableMessage {
+        let mut inv = Invocation::default();
+        inv.id = id.to_string();
+        inv.origin.public_key = origin.to_string();
+        inv.target.public_key = target.to_string();
+        if origin.starts_with('V') {
+            inv.origin.link_name = "default".to_string();
+            inv.origin.contract_id = "wasmcloud:httpserver".to_string();
+        }
+        if target.starts_with('V') {
+            inv.target.link_name = "default".to_string();
+            inv.target.contract_id = "wasmcloud:keyvalue".to_string();
+        }
+        message(
+            rmp_serde::to_vec_named(&inv).unwrap(),
+            time::OffsetDateTime::now_utc(),
+        )
+    }
+
+    fn response_message(invocation_id: &str) -> SerializableMessage {
+        let mut resp = InvocationResponse::default();
+        resp.invocation_id = invocation_id.to_string();
+        message(
+            rmp_serde::to_vec_named(&resp).unwrap(),
+            time::OffsetDateTime::now_utc(),
+        )
+    }
+
+    #[test]
+    fn test_filter_endpoints() {
+        let actor: ModuleId = ACTOR_ID.parse().unwrap();
+        let provider: ServiceId = PROVIDER_ID.parse().unwrap();
+        let actor_filter = CaptureFilter {
+            actor_id: Some(actor),
+            ..Default::default()
+        };
+        let provider_filter = CaptureFilter {
+            provider_id: Some(provider),
+            ..Default::default()
+        };
+
+        // Calls to or from the actor match, whether the other end is an actor or a provider
+        for (origin, target) in [
+            (ACTOR_ID, OTHER_ACTOR_ID),
+            (OTHER_ACTOR_ID, ACTOR_ID),
+            (PROVIDER_ID, ACTOR_ID),
+            (ACTOR_ID, PROVIDER_ID),
+        ] {
+            assert!(actor_filter.matches(&invocation_message("1", origin, target)));
+        }
+        assert!(!actor_filter.matches(&invocation_message("1", OTHER_ACTOR_ID, PROVIDER_ID)));
+
+        assert!(provider_filter.matches(&invocation_message("1", PROVIDER_ID, ACTOR_ID)));
+        assert!(provider_filter.matches(&invocation_message("1", OTHER_ACTOR_ID, PROVIDER_ID)));
+        assert!(!provider_filter.matches(&invocation_message("1", ACTOR_ID, OTHER_ACTOR_ID)));
+
+        // Messages that aren't invocations, such as lattice events, are only filtered by time
+        // and subject
+        let event = SerializableMessage {
+            subject: format!("{EVENT_SUBJECT_PREFIX}.default"),
+            ..message(b"{}".to_vec(), time::OffsetDateTime::now_utc())
+        };
+        assert!(actor_filter.matches(&event));
+        assert!(!CaptureFilter {
+            subject: Some("wasmbus.rpc.>".to_string()),
+            ..actor_filter
+        }
+        .matches(&event));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_reader_responses() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tarball = tempdir.path().join("capture.tar.gz");
+        let mut capture = WriteCapture::start(Vec::new(), &tarball).await.unwrap();
+        for msg in [
+            invocation_message("1", PROVIDER_ID, ACTOR_ID),
+            invocation_message("2", PROVIDER_ID, OTHER_ACTOR_ID),
+            response_message("2"),
+            response_message("1"),
+            response_message("1"),
+        ] {
+            capture.add_message(msg).await.unwrap();
+        }
+        capture.finish().await.unwrap();
+
+        let mut reader = CaptureReader::open(&tarball)
+            .await
+            .unwrap()
+            .with_filter(CaptureFilter {
+                actor_id: Some(ACTOR_ID.parse().unwrap()),
+                ..Default::default()
+            });
+        let mut kept = Vec::new();
+        while let Some(msg) = reader.next_message().await.unwrap() {
+            if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+                kept.push(format!("invocation {}", inv.id));
+            } else {
+                let resp = rmp_serde::from_slice::<InvocationResponse>(&msg.payload).unwrap();
+                kept.push(format!("response {}", resp.invocation_id));
+            }
+        }
+        // Only the first response to the kept invocation is returned
+        assert_eq!(kept, vec!["invocation 1", "response 1"]);
+    }
+
+    #[test]
+    fn test_subject_matches() {
+        assert!(subject_matches(
+            "wasmbus.rpc.default.>",
+            "wasmbus.rpc.default.M1.x"
+        ));
+        assert!(subject_matches(
+            "wasmbus.*.default.M1",
+            "wasmbus.rpc.default.M1"
+        ));
+        assert!(!subject_matches(
+            "wasmbus.rpc.default.>",
+            "wasmbus.rpc.default"
+        ));
+        assert!(!subject_matches("wasmbus.rpc.*", "wasmbus.rpc.default.M1"));
+        assert!(!subject_matches(
+            "wasmbus.rpc.default.M1",
+            "wasmbus.rpc.default"
+        ));
+    }
+
+    fn message(payload: Vec<u8>, published: time::OffsetDateTime) -> SerializableMessage {
+        SerializableMessage {
+            subject: "wasmbus.rpc.default.MACTOR".to_string(),
+            reply: None,
+            length: payload.len(),
+            payload: payload.into(),
+            description: None,
+            published,
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let start = time::OffsetDateTime::now_utc();
+        let mut stats = CaptureStats::default();
+        for (i, size) in [10usize, 20, 30].into_iter().enumerate() {
+            let mut inv = Invocation::default();
+            inv.origin.public_key = "VPROVIDER".to_string();
+            inv.origin.contract_id = "wasmcloud:httpserver".to_string();
+            inv.origin.link_name = "default".to_string();
+            inv.target.public_key = "MACTOR".to_string();
+            inv.operation = "HttpServer.HandleRequest".to_string();
+            inv.id = i.to_string();
+            inv.msg = vec![0; size];
+            let published = start + time::Duration::milliseconds(i as i64 * 100);
+            stats.add_message(&message(rmp_serde::to_vec_named(&inv).unwrap(), published));
+
+            // Only the first two invocations get a response, and the second one fails
+            if i < 2 {
+                let mut resp = InvocationResponse::default();
+                resp.invocation_id = i.to_string();
+                resp.error = (i == 1).then(|| "boom".to_string());
+                stats.add_message(&message(
+                    rmp_serde::to_vec_named(&resp).unwrap(),
+                    published + time::Duration::milliseconds(5 * (i as i64 + 1)),
+                ));
+            }
+        }
+        let operations = stats.finish();
+        assert_eq!(operations.len(), 1);
+        let op = &operations[0];
+        assert_eq!(
+            (op.origin.as_str(), op.target.as_str()),
+            ("VPROVIDER", "MACTOR")
+        );
+        assert_eq!((op.count, op.responses, op.errors), (3, 2, 1));
+        assert_eq!(op.payload_sizes, vec![10, 20, 30]);
+        assert_eq!(op.payload_percentile(50.0), Some(20));
+        assert_eq!(
+            op.latencies,
+            vec![
+                std::time::Duration::from_millis(5),
+                std::time::Duration::from_millis(10)
+            ]
+        );
+        assert_eq!(
+            op.latency_percentile(99.0),
+            Some(std::time::Duration::from_millis(10))
+        );
+    }
+
+    fn record(request: &str, response: Option<&str>, error: Option<bool>) -> CallRecord {
+        CallRecord {
+            request_shape: request.to_string(),
+            response_shape: response.map(str::to_string),
+            error,
+        }
+    }
+
+    #[test]
+    fn test_payload_shape() {
+        let payload = rmp_serde::to_vec_named(&serde_json::json!({
+            "id": 7,
+            "name": "widget",
+            "tags": ["a", "b"],
+            "extra": null,
+        }))
+        .unwrap();
+        assert_eq!(
+            payload_shape(payload),
+            r#"{"extra":"null","id":"number","name":"string","tags":["string"]}"#
+        );
+        assert_eq!(payload_shape(vec![0xc1]), "binary");
+    }
+
+    #[test]
+    fn test_diff_calls() {
+        let key = |op: &str| ("MORIGIN".to_string(), "MTARGET".to_string(), op.to_string());
+        let before = HashMap::from([
+            (
+                key("Kept"),
+                vec![
+                    record("{\"a\":\"number\"}", Some("\"string\""), Some(false)),
+                    record("{\"a\":\"number\"}", Some("\"string\""), Some(false)),
+                ],
+            ),
+            (key("Removed"), vec![record("\"null\"", None, None)]),
+        ]);
+        let after = HashMap::from([
+            (
+                key("Kept"),
+                vec![
+                    record("{\"a\":\"string\"}", Some("\"string\""), Some(false)),
+                    record("{\"a\":\"string\"}", None, Some(true)),
+                ],
+            ),
+            (key("Added"), vec![record("\"null\"", None, None)]),
+        ]);
+        let diff = diff_calls(before, after);
+        let ops = diff
+            .iter()
+            .map(|d| d.operation.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["Added", "Kept", "Removed"]);
+        assert!(diff[0].is_new() && diff[0].has_changes());
+        assert!(diff[2].is_missing());
+
+        let kept = &diff[1];
+        assert!(!kept.is_new() && !kept.is_missing());
+        assert_eq!(
+            kept.request_shapes,
+            vec![ShapeChange {
+                index: 0,
+                count: 2,
+                before: "{\"a\":\"number\"}".to_string(),
+                after: "{\"a\":\"string\"}".to_string(),
+            }]
+        );
+        assert!(kept.response_shapes.is_empty());
+        assert_eq!(kept.before_error_rate, Some(0.0));
+        assert_eq!(kept.after_error_rate, Some(0.5));
+        assert!(kept.error_rate_changed());
+    }
+
+    #[tokio::test]
+    async fn test_links_and_events() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tarball = tempdir.path().join("capture.tar.gz");
+        let mut capture = WriteCapture::start(Vec::new(), &tarball).await.unwrap();
+        let mut link = LinkDefinition::default();
+        link.actor_id = "MACTOR".to_string();
+        link.provider_id = "VPROVIDER".to_string();
+        link.link_name = "default".to_string();
+        link.contract_id = "wasmcloud:httpserver".to_string();
+        capture.add_links(vec![link]).await.unwrap();
+        let event = serde_json::json!({
+            "specversion": "1.0",
+            "type": "com.wasmcloud.lattice.actor_started",
+            "source": "NHOST",
+            "id": "1",
+            "data": {"public_key": "MACTOR", "image_ref": "localhost:5000/echo:0.1.0"},
+        });
+        let payload = serde_json::to_vec(&event).unwrap();
+        capture
+            .add_message(SerializableMessage {
+                subject: "wasmbus.evt.default".to_string(),
+                reply: None,
+                length: payload.len(),
+                payload: payload.into(),
+                description: None,
+                published: time::OffsetDateTime::now_utc(),
+            })
+            .await
+            .unwrap();
+        capture.finish().await.unwrap();
+
+        let mut reader = CaptureReader::open(&tarball).await.unwrap();
+        assert_eq!(reader.links().len(), 1);
+        assert_eq!(reader.links()[0].actor_id, "MACTOR");
+        let msg = reader.next_message().await.unwrap().unwrap();
+        let event = LatticeEvent::from_message(&msg).expect("Should parse the event");
+        assert_eq!(event.kind, "actor_started");
+        assert_eq!(event.source, "NHOST");
+        assert_eq!(event.summary(), "MACTOR from localhost:5000/echo:0.1.0");
+        assert!(reader.next_message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_capture() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tarball = tempdir.path().join("capture.tar.gz");
+        let recipient = CurveKey::new().unwrap();
+        let mut capture = WriteCapture::start_encrypted(
+            Vec::new(),
+            &tarball,
+            CurveKey::from_public_key(&recipient.public_key()).unwrap(),
+        )
+        .await
+        .unwrap()
+        .with_redactor(Redactor::new(&[], &["secret".to_string()]).unwrap());
+        capture
+            .add_message(message(
+                rmp_serde::to_vec("a secret message").unwrap(),
+                time::OffsetDateTime::now_utc(),
+            ))
+            .await
+            .unwrap();
+        capture.finish().await.unwrap();
+
+        assert!(
+            CaptureReader::open(&tarball).await.is_err(),
+            "Encrypted captures should need a key"
+        );
+        let mut reader = CaptureReader::open_with_key(&tarball, Some(&recipient))
+            .await
+            .unwrap();
+        let msg = reader.next_message().await.unwrap().unwrap();
+        let payload: String = rmp_serde::from_slice(&msg.payload).unwrap();
+        assert_eq!(payload, format!("a {REDACTED} message"));
+    }
+}
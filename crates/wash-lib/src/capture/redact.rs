@@ -0,0 +1,504 @@
+//! Redaction of sensitive data from captured payloads before they are written to a capture file
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use rmpv::Value as MsgpackValue;
+use serde_cbor::Value as CborValue;
+use serde_json::Value as JsonValue;
+use wasmbus_rpc::core::{Invocation, InvocationResponse};
+
+use super::SerializableMessage;
+
+/// Value that redacted data is replaced with
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    /// Every field of an object or item of an array
+    Wildcard,
+}
+
+/// Removes data from decoded payloads, either at JSON paths (e.g. `$.user.email` or
+/// `$.items[*].card`) or wherever string values, or binary values that are valid UTF-8 (such as
+/// the body of an `HttpRequest`), match a regex. Payloads that were changed are re-encoded with the
+/// codec they were decoded with, so invocations keep their structure but their signatures no
+/// longer match the payload
+#[derive(Debug, Default, Clone)]
+pub struct Redactor {
+    paths: Vec<Vec<PathSegment>>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(paths: &[String], patterns: &[String]) -> Result<Self> {
+        Ok(Redactor {
+            paths: paths
+                .iter()
+                .map(|path| parse_path(path))
+                .collect::<Result<_>>()?,
+            patterns: patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .with_context(|| format!("Invalid redaction regex '{pattern}'"))
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Redacts the payload of an invocation, an invocation response or any other message
+    pub fn redact_message(&self, mut msg: SerializableMessage) -> SerializableMessage {
+        if self.is_empty() {
+            return msg;
+        }
+        let payload = if let Ok(mut inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            inv.msg = self.redact_payload(inv.msg);
+            inv.content_length = Some(inv.msg.len() as u64);
+            rmp_serde::to_vec_named(&inv).ok()
+        } else if let Ok(mut resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            resp.msg = self.redact_payload(resp.msg);
+            resp.content_length = Some(resp.msg.len() as u64);
+            rmp_serde::to_vec_named(&resp).ok()
+        } else if let Ok(mut value) = serde_json::from_slice::<JsonValue>(&msg.payload) {
+            // Lattice events and other JSON messages stay JSON
+            self.redact_json(&mut value)
+                .then(|| serde_json::to_vec(&value).ok())
+                .flatten()
+        } else {
+            Some(self.redact_payload(msg.payload.to_vec()))
+        };
+        if let Some(payload) = payload {
+            msg.length = payload.len();
+            msg.payload = payload.into();
+        }
+        msg
+    }
+
+    /// Redacts a msgpack or CBOR encoded payload, returning it unchanged if nothing matched
+    pub fn redact_payload(&self, payload: Vec<u8>) -> Vec<u8> {
+        if let Some(mut value) = decode_msgpack(&payload) {
+            if !self.redact_value(&mut value) {
+                return payload;
+            }
+            let mut encoded = Vec::new();
+            return match rmpv::encode::write_value(&mut encoded, &value) {
+                Ok(()) => encoded,
+                Err(_) => payload,
+            };
+        }
+        if let Ok(mut value) = serde_cbor::from_slice::<CborValue>(&payload) {
+            if !self.redact_value(&mut value) {
+                return payload;
+            }
+            return serde_cbor::to_vec(&value).unwrap_or(payload);
+        }
+        match std::str::from_utf8(&payload) {
+            Ok(text) => {
+                let mut text = text.to_owned();
+                if !self.redact_str(&mut text) {
+                    return payload;
+                }
+                text.into_bytes()
+            }
+            Err(_) => payload,
+        }
+    }
+
+    /// Redacts the value in place, returning true if anything was redacted
+    pub fn redact_json(&self, value: &mut JsonValue) -> bool {
+        self.redact_value(value)
+    }
+
+    fn redact_value<V: Redactable>(&self, value: &mut V) -> bool {
+        let mut changed = false;
+        for path in self.paths.iter() {
+            changed |= redact_path(value, path);
+        }
+        if !self.patterns.is_empty() {
+            changed |= self.redact_strings(value);
+        }
+        changed
+    }
+
+    fn redact_strings<V: Redactable>(&self, value: &mut V) -> bool {
+        let changed = value.redact_text(&|s| self.redact_str(s));
+        value
+            .children()
+            .into_iter()
+            .fold(changed, |changed, child| {
+                self.redact_strings(child) | changed
+            })
+    }
+
+    fn redact_str(&self, s: &mut String) -> bool {
+        let mut changed = false;
+        for pattern in self.patterns.iter() {
+            if pattern.is_match(s) {
+                *s = pattern.replace_all(s, REDACTED).into_owned();
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Decodes a msgpack payload, as long as it is a single complete value
+fn decode_msgpack(payload: &[u8]) -> Option<MsgpackValue> {
+    let mut remaining = payload;
+    let value = rmpv::decode::read_value(&mut remaining).ok()?;
+    remaining.is_empty().then_some(value)
+}
+
+fn redact_path<V: Redactable>(value: &mut V, path: &[PathSegment]) -> bool {
+    let Some((segment, rest)) = path.split_first() else {
+        value.redact();
+        return true;
+    };
+    value
+        .select(segment)
+        .into_iter()
+        .fold(false, |changed, v| redact_path(v, rest) | changed)
+}
+
+/// A decoded payload that can be redacted in place. Payloads are redacted as the value type of
+/// their codec, so they can be re-encoded the same way and binary fields stay binary
+trait Redactable: Sized {
+    /// Replaces the value with [`REDACTED`], keeping binary values binary
+    fn redact(&mut self);
+    /// The values of an object or array selected by the path segment
+    fn select(&mut self, segment: &PathSegment) -> Vec<&mut Self>;
+    /// Every value of an object or array
+    fn children(&mut self) -> Vec<&mut Self>;
+    /// Applies `redact` to the text of a string, or of a binary value that is valid UTF-8,
+    /// returning true if it changed anything
+    fn redact_text(&mut self, redact: &dyn Fn(&mut String) -> bool) -> bool;
+}
+
+/// Applies `redact` to binary data if it is valid UTF-8
+fn redact_utf8(data: &mut Vec<u8>, redact: &dyn Fn(&mut String) -> bool) -> bool {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return false;
+    };
+    let mut text = text.to_owned();
+    if !redact(&mut text) {
+        return false;
+    }
+    *data = text.into_bytes();
+    true
+}
+
+impl Redactable for JsonValue {
+    fn redact(&mut self) {
+        *self = JsonValue::String(REDACTED.to_string());
+    }
+
+    fn select(&mut self, segment: &PathSegment) -> Vec<&mut Self> {
+        match (segment, self) {
+            (PathSegment::Key(key), JsonValue::Object(map)) => {
+                map.get_mut(key).into_iter().collect()
+            }
+            (PathSegment::Index(i), JsonValue::Array(items)) => {
+                items.get_mut(*i).into_iter().collect()
+            }
+            // Numeric segments like `items.0` may also be object keys
+            (PathSegment::Index(i), JsonValue::Object(map)) => {
+                map.get_mut(&i.to_string()).into_iter().collect()
+            }
+            (PathSegment::Wildcard, value) => value.children(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn children(&mut self) -> Vec<&mut Self> {
+        match self {
+            JsonValue::Object(map) => map.values_mut().collect(),
+            JsonValue::Array(items) => items.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn redact_text(&mut self, redact: &dyn Fn(&mut String) -> bool) -> bool {
+        match self {
+            JsonValue::String(s) => redact(s),
+            _ => false,
+        }
+    }
+}
+
+impl Redactable for MsgpackValue {
+    fn redact(&mut self) {
+        *self = match self {
+            MsgpackValue::Binary(_) => MsgpackValue::Binary(REDACTED.as_bytes().to_vec()),
+            _ => MsgpackValue::from(REDACTED),
+        };
+    }
+
+    fn select(&mut self, segment: &PathSegment) -> Vec<&mut Self> {
+        let key = match segment {
+            PathSegment::Key(key) => key.clone(),
+            // Numeric segments like `items.0` may also be object keys
+            PathSegment::Index(i) => i.to_string(),
+            PathSegment::Wildcard => return self.children(),
+        };
+        match (segment, self) {
+            (PathSegment::Index(i), MsgpackValue::Array(items)) => {
+                items.get_mut(*i).into_iter().collect()
+            }
+            (_, MsgpackValue::Map(entries)) => entries
+                .iter_mut()
+                .filter(|(k, _)| k.as_str() == Some(key.as_str()))
+                .map(|(_, v)| v)
+                .take(1)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn children(&mut self) -> Vec<&mut Self> {
+        match self {
+            MsgpackValue::Map(entries) => entries.iter_mut().map(|(_, v)| v).collect(),
+            MsgpackValue::Array(items) => items.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn redact_text(&mut self, redact: &dyn Fn(&mut String) -> bool) -> bool {
+        match self {
+            MsgpackValue::String(s) => {
+                let Some(text) = s.as_str() else {
+                    return false;
+                };
+                let mut text = text.to_owned();
+                if !redact(&mut text) {
+                    return false;
+                }
+                *s = text.into();
+                true
+            }
+            MsgpackValue::Binary(data) => redact_utf8(data, redact),
+            _ => false,
+        }
+    }
+}
+
+impl Redactable for CborValue {
+    fn redact(&mut self) {
+        *self = match self {
+            CborValue::Bytes(_) => CborValue::Bytes(REDACTED.as_bytes().to_vec()),
+            _ => CborValue::Text(REDACTED.to_string()),
+        };
+    }
+
+    fn select(&mut self, segment: &PathSegment) -> Vec<&mut Self> {
+        match (segment, self) {
+            (PathSegment::Key(key), CborValue::Map(map)) => map
+                .get_mut(&CborValue::Text(key.clone()))
+                .into_iter()
+                .collect(),
+            (PathSegment::Index(i), CborValue::Array(items)) => {
+                items.get_mut(*i).into_iter().collect()
+            }
+            // Numeric segments like `items.0` may also be object keys
+            (PathSegment::Index(i), CborValue::Map(map)) => map
+                .get_mut(&CborValue::Text(i.to_string()))
+                .into_iter()
+                .collect(),
+            (PathSegment::Wildcard, value) => value.children(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn children(&mut self) -> Vec<&mut Self> {
+        match self {
+            CborValue::Map(map) => map.values_mut().collect(),
+            CborValue::Array(items) => items.iter_mut().collect(),
+            CborValue::Tag(_, value) => vec![value.as_mut()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn redact_text(&mut self, redact: &dyn Fn(&mut String) -> bool) -> bool {
+        match self {
+            CborValue::Text(s) => redact(s),
+            CborValue::Bytes(data) => redact_utf8(data, redact),
+            _ => false,
+        }
+    }
+}
+
+/// Parses a JSON path like `$.user.email`, `items[*].card` or `items.0.card`
+fn parse_path(path: &str) -> Result<Vec<PathSegment>> {
+    let trimmed = path.trim().trim_start_matches('$');
+    let mut segments = Vec::new();
+    for part in trimmed.split('.').filter(|p| !p.is_empty()) {
+        let (key, mut indexes) = match part.find('[') {
+            Some(start) => part.split_at(start),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(match key {
+                "*" => PathSegment::Wildcard,
+                key => key
+                    .parse()
+                    .map(PathSegment::Index)
+                    .unwrap_or_else(|_| PathSegment::Key(key.to_string())),
+            });
+        }
+        while let Some(rest) = indexes.strip_prefix('[') {
+            let Some((index, remaining)) = rest.split_once(']') else {
+                bail!("Invalid redaction path '{path}', missing ']'");
+            };
+            segments.push(match index.trim_matches(|c| c == '\'' || c == '"') {
+                "*" => PathSegment::Wildcard,
+                index => index
+                    .parse()
+                    .map(PathSegment::Index)
+                    .unwrap_or_else(|_| PathSegment::Key(index.to_string())),
+            });
+            indexes = remaining;
+        }
+        if !indexes.is_empty() {
+            bail!("Invalid redaction path '{path}'");
+        }
+    }
+    if segments.is_empty() {
+        bail!("Invalid redaction path '{path}', it must select at least one field");
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::httpserver::{HttpRequest, HttpResponse};
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("$.items[*].card").unwrap(),
+            vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Wildcard,
+                PathSegment::Key("card".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_path("users.0['email']").unwrap(),
+            vec![
+                PathSegment::Key("users".to_string()),
+                PathSegment::Index(0),
+                PathSegment::Key("email".to_string()),
+            ]
+        );
+        assert!(parse_path("$").is_err());
+        assert!(parse_path("items[0").is_err());
+    }
+
+    #[test]
+    fn test_redact_payload() {
+        let redactor = Redactor::new(
+            &["$.user.email".to_string(), "$.items[*].card".to_string()],
+            &[r"\d{3}-\d{2}-\d{4}".to_string()],
+        )
+        .unwrap();
+        let payload = rmp_serde::to_vec_named(&json!({
+            "user": {"email": "jane@example.com", "name": "Jane"},
+            "items": [{"card": "4111", "sku": "a"}, {"card": "4242", "sku": "b"}],
+            "note": "ssn 123-45-6789",
+        }))
+        .unwrap();
+        let redacted: JsonValue = rmp_serde::from_slice(&redactor.redact_payload(payload)).unwrap();
+        assert_eq!(
+            redacted,
+            json!({
+                "user": {"email": REDACTED, "name": "Jane"},
+                "items": [{"card": REDACTED, "sku": "a"}, {"card": REDACTED, "sku": "b"}],
+                "note": format!("ssn {REDACTED}"),
+            })
+        );
+
+        // Payloads without matches are left untouched
+        let untouched = rmp_serde::to_vec_named(&json!({"name": "Jane"})).unwrap();
+        assert_eq!(redactor.redact_payload(untouched.clone()), untouched);
+    }
+
+    #[test]
+    fn test_redact_invocation() {
+        let redactor = Redactor::new(&["$.password".to_string()], &[]).unwrap();
+        let mut inv = Invocation::default();
+        inv.msg = rmp_serde::to_vec_named(&json!({"password": "hunter2"})).unwrap();
+        let payload = rmp_serde::to_vec_named(&inv).unwrap();
+        let msg = redactor.redact_message(SerializableMessage {
+            subject: "wasmbus.rpc.default.MACTOR".to_string(),
+            reply: None,
+            length: payload.len(),
+            payload: payload.into(),
+            description: None,
+            published: time::OffsetDateTime::now_utc(),
+        });
+        let inv: Invocation = rmp_serde::from_slice(&msg.payload).unwrap();
+        let body: JsonValue = rmp_serde::from_slice(&inv.msg).unwrap();
+        assert_eq!(body, json!({"password": REDACTED}));
+        assert_eq!(msg.length, msg.payload.len());
+    }
+
+    #[test]
+    fn test_redact_httpserver_body() {
+        let redactor = Redactor::new(&[], &[r"hunter\d".to_string()]).unwrap();
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/login".to_string(),
+            body: br#"{"user":"jane","password":"hunter2"}"#.to_vec(),
+            ..Default::default()
+        };
+        let mut inv = Invocation::default();
+        inv.msg = request.to_bytes().unwrap();
+        let payload = rmp_serde::to_vec_named(&inv).unwrap();
+        let msg = redactor.redact_message(SerializableMessage {
+            subject: "wasmbus.rpc.default.MACTOR".to_string(),
+            reply: None,
+            length: payload.len(),
+            payload: payload.into(),
+            description: None,
+            published: time::OffsetDateTime::now_utc(),
+        });
+        let inv: Invocation = rmp_serde::from_slice(&msg.payload).unwrap();
+        // The body stays binary, so the request still decodes as an HttpRequest
+        let redacted: HttpRequest = rmp_serde::from_slice(&inv.msg).unwrap();
+        assert_eq!(
+            redacted.body,
+            format!(r#"{{"user":"jane","password":"{REDACTED}"}}"#).into_bytes()
+        );
+        assert_eq!(redacted.path, "/login");
+        assert_eq!(inv.content_length, Some(inv.msg.len() as u64));
+    }
+
+    #[test]
+    fn test_redact_keeps_codec() {
+        let redactor = Redactor::new(&["$.token".to_string()], &["jane".to_string()]).unwrap();
+
+        let payload = serde_cbor::to_vec(&json!({"token": "abc", "name": "jane"})).unwrap();
+        let redacted: JsonValue =
+            serde_cbor::from_slice(&redactor.redact_payload(payload)).unwrap();
+        assert_eq!(redacted, json!({"token": REDACTED, "name": REDACTED}));
+
+        // Binary values redacted by path stay binary
+        let payload = rmp_serde::to_vec_named(&HttpResponse {
+            status_code: 200,
+            body: b"abc".to_vec(),
+            ..Default::default()
+        })
+        .unwrap();
+        let redactor = Redactor::new(&["$.body".to_string()], &[]).unwrap();
+        let redacted = HttpResponse::from_bytes(&redactor.redact_payload(payload)).unwrap();
+        assert_eq!(redacted.body, REDACTED.as_bytes());
+        assert_eq!(redacted.status_code, 200);
+    }
+}
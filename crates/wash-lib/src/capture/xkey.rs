@@ -0,0 +1,85 @@
+//! Encoding of curve keys as nkeys xkeys: base32 of a prefix byte, the raw key and a CRC-16
+//! checksum.
+//!
+//! TODO: this is a shim for `nkeys::XKey`, which only exists from nkeys 0.4 on. Delete it and
+//! use `XKey::from_public_key`/`XKey::from_seed` once the workspace can move to nkeys 0.4 (see
+//! the module documentation of `capture::crypto`)
+use anyhow::{bail, Context, Result};
+
+const PREFIX_BYTE_SEED: u8 = 18 << 3;
+const PREFIX_BYTE_CURVE: u8 = 23 << 3;
+
+/// Encodes a raw public key as an xkey public key (`X...`)
+pub(super) fn encode_public_key(public: &[u8; 32]) -> String {
+    let mut raw = vec![PREFIX_BYTE_CURVE];
+    raw.extend_from_slice(public);
+    encode(raw)
+}
+
+/// Encodes a raw seed as an xkey seed (`SX...`)
+pub(super) fn encode_seed(seed: &[u8; 32]) -> String {
+    let mut raw = vec![
+        PREFIX_BYTE_SEED | PREFIX_BYTE_CURVE >> 5,
+        (PREFIX_BYTE_CURVE & 31) << 3,
+    ];
+    raw.extend_from_slice(seed);
+    encode(raw)
+}
+
+/// Decodes an xkey public key (`X...`) into the raw key
+pub(super) fn decode_public_key(public_key: &str) -> Result<[u8; 32]> {
+    let raw = decode(public_key).context("Invalid curve public key")?;
+    if raw.len() != 33 || raw[0] != PREFIX_BYTE_CURVE {
+        bail!("Invalid curve public key, expected a key starting with 'X'");
+    }
+    let mut public = [0u8; 32];
+    public.copy_from_slice(&raw[1..]);
+    Ok(public)
+}
+
+/// Decodes an xkey seed (`SX...`) into the raw seed
+pub(super) fn decode_seed(seed: &str) -> Result<[u8; 32]> {
+    let raw = decode(seed).context("Invalid curve seed")?;
+    if raw.len() != 34
+        || raw[0] != PREFIX_BYTE_SEED | PREFIX_BYTE_CURVE >> 5
+        || raw[1] != (PREFIX_BYTE_CURVE & 31) << 3
+    {
+        bail!("Invalid curve seed, expected a seed starting with 'SX'");
+    }
+    let mut decoded = [0u8; 32];
+    decoded.copy_from_slice(&raw[2..]);
+    Ok(decoded)
+}
+
+fn encode(mut raw: Vec<u8>) -> String {
+    let crc = crc16(&raw);
+    raw.extend_from_slice(&crc.to_le_bytes());
+    data_encoding::BASE32_NOPAD.encode(&raw)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    let mut raw = data_encoding::BASE32_NOPAD.decode(value.as_bytes())?;
+    if raw.len() < 3 {
+        bail!("key is too short");
+    }
+    let crc = raw.split_off(raw.len() - 2);
+    if crc16(&raw).to_le_bytes() != crc[..] {
+        bail!("checksum mismatch");
+    }
+    Ok(raw)
+}
+
+/// CRC-16/XMODEM, as used by nkeys
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        let mut crc = crc ^ ((*byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
@@ -6,17 +6,27 @@ use async_nats::jetstream::{
     stream::Config,
 };
 use clap::{Parser, Subcommand};
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
+use serde_json::json;
+use term_table::{
+    row::Row,
+    table_cell::{Alignment, TableCell},
+    Table,
+};
 use tokio::io::{stdin, stdout, AsyncReadExt, AsyncWriteExt};
 use tokio::time::Instant;
-use wasmbus_rpc::core::Invocation;
+use wasmbus_rpc::core::{Invocation, InvocationResponse};
 
-use super::{CliConnectionOpts, CommandOutput};
+use super::{CliConnectionOpts, CommandOutput, OutputKind};
 use crate::config::WashConnectionOptions;
 use crate::{
-    capture::{ReadCapture, WriteCapture},
+    capture::{
+        diff_captures, entity_names, export_capture, CaptureFilter, CaptureReader, CaptureStats,
+        CurveKey, ExportFormat, LatticeEvent, Redactor, SerializableMessage, ShapeChange,
+        WriteCapture, EVENT_SUBJECT_PREFIX, RESPONSE_SUBJECT_PREFIX,
+    },
     id::{ModuleId, ServiceId},
-    spier::{ObservedInvocation, ObservedMessage},
+    spier::{InvocationStatus, ObservedInvocation, ObservedMessage, DEFAULT_RESPONSE_TIMEOUT},
 };
 
 pub const CAPTURE_STREAM_NAME: &str = "wash-capture";
@@ -24,6 +34,7 @@ pub const CAPTURE_STREAM_NAME: &str = "wash-capture";
 #[derive(Debug, Parser, Clone)]
 pub struct CaptureCommand {
     /// Enable wash capture. This will setup a NATS JetStream stream to capture all invocations
+    /// and lattice control events
     #[clap(name = "enable", long = "enable", conflicts_with = "disable")]
     pub enable: bool,
 
@@ -36,17 +47,179 @@ pub struct CaptureCommand {
     #[clap(name = "window_size", long = "window-size", default_value = "60")]
     pub window_size_minutes: u64,
 
+    /// When enabling, also capture invocation responses so they can be compared during replay.
+    /// Responses are sent to the inbox of the invoker, so they can't be captured by the stream
+    /// directly. Instead, this command keeps running in the foreground after enabling capture and
+    /// records the response to every captured invocation until it is interrupted. Responses are
+    /// only recorded while it runs, so keep it running for as long as responses should be
+    /// captured; invocations captured after it stops have no responses. Recording subscribes to
+    /// every reply inbox on the NATS server (`_INBOX.>`), not just those of this lattice, which
+    /// can be a lot of traffic on a busy server
+    #[clap(long = "include-responses", requires = "enable")]
+    pub include_responses: bool,
+
+    /// When enabling, only capture invocations sent to this actor instead of all invocations in
+    /// the lattice. May be specified more than once, and combined with --provider-id
+    #[clap(
+        long = "actor-id",
+        value_parser,
+        number_of_values = 1,
+        requires = "enable"
+    )]
+    pub actor_ids: Vec<ModuleId>,
+
+    /// When enabling, only capture invocations sent to this provider instead of all invocations
+    /// in the lattice. May be specified more than once, and combined with --actor-id
+    #[clap(
+        long = "provider-id",
+        value_parser,
+        number_of_values = 1,
+        requires = "enable"
+    )]
+    pub provider_ids: Vec<ServiceId>,
+
+    /// Only capture messages published at or after this time (RFC 3339, e.g.
+    /// 2023-06-01T12:00:00Z). Defaults to the oldest message in the stream
+    #[clap(
+        long = "start",
+        value_parser = parse_timestamp,
+        conflicts_with_all = ["enable", "disable", "last_minutes"]
+    )]
+    pub start: Option<time::OffsetDateTime>,
+
+    /// Only capture messages published at or before this time (RFC 3339, e.g.
+    /// 2023-06-01T12:05:00Z). Defaults to the time the capture is started
+    #[clap(
+        long = "end",
+        value_parser = parse_timestamp,
+        conflicts_with_all = ["enable", "disable"]
+    )]
+    pub end: Option<time::OffsetDateTime>,
+
+    /// Only capture messages published in the last N minutes
+    #[clap(long = "last", conflicts_with_all = ["enable", "disable"])]
+    pub last_minutes: Option<u64>,
+
+    /// Replace the value at this JSON path (e.g. `$.user.email` or `$.items[*].card`) in every
+    /// decoded payload with "[REDACTED]" before it is written to the capture file. May be
+    /// specified more than once
+    #[clap(
+        long = "redact-path",
+        number_of_values = 1,
+        conflicts_with_all = ["enable", "disable"]
+    )]
+    pub redact_paths: Vec<String>,
+
+    /// Replace every match of this regex in the string values of decoded payloads with
+    /// "[REDACTED]" before it is written to the capture file. May be specified more than once
+    #[clap(
+        long = "redact-regex",
+        number_of_values = 1,
+        conflicts_with_all = ["enable", "disable"]
+    )]
+    pub redact_patterns: Vec<String>,
+
+    /// Encrypt the capture file for this curve public key (starting with 'X'), as created by
+    /// `wash capture keygen`. Only the holder of the matching seed can read the capture, with
+    /// `--seed`. The encrypted file uses a wash specific format: it is not compatible with nkeys
+    /// xkey seal/open, so it can't be decrypted with other xkey tooling
+    #[clap(long = "recipient", conflicts_with_all = ["enable", "disable"])]
+    pub recipient: Option<String>,
+
     #[clap(flatten)]
     pub opts: CliConnectionOpts,
 
-    /// Replay through a stream of captured invocations
+    /// Subcommand to run instead of enabling, disabling or downloading a capture
     #[clap(subcommand)]
-    pub replay: Option<CaptureSubcommand>,
+    pub subcommand: Option<CaptureSubcommand>,
 }
 
 #[derive(Debug, Subcommand, Clone)]
 pub enum CaptureSubcommand {
-    Replay(CaptureReplayCommand),
+    /// Replay a capture, printing the captured invocations and lattice events or re-publishing
+    /// the invocations to a lattice
+    Replay(Box<CaptureReplayCommand>),
+    /// Report invocation counts, payload sizes, errors and latencies per operation
+    Stats(CaptureStatsCommand),
+    /// Compare the invocations of two captures, such as before and after changing an actor
+    Diff(CaptureDiffCommand),
+    /// Export a capture to JSON Lines or, for httpserver traffic, a HAR file
+    Export(CaptureExportCommand),
+    /// Show the size, message count and age of the capture stream
+    Status(CaptureStatusCommand),
+    /// Generate a curve key pair for encrypting captures with --recipient. Captures encrypted for
+    /// the key can only be decrypted by wash, not by other xkey tooling
+    Keygen(CaptureKeygenCommand),
+}
+
+/// The seed used to read captures that were encrypted with --recipient
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureKeyOpts {
+    /// Curve seed (starting with 'SX') to decrypt the capture with, if it was encrypted
+    #[clap(long = "seed", env = "WASH_CAPTURE_SEED", hide_env_values = true)]
+    pub seed: Option<String>,
+}
+
+impl CaptureKeyOpts {
+    /// Opens the capture file, decrypting it with the seed if one was given
+    async fn open(&self, path: impl AsRef<std::path::Path>) -> Result<CaptureReader> {
+        let key = self.seed.as_deref().map(CurveKey::from_seed).transpose()?;
+        CaptureReader::open_with_key(path, key.as_ref()).await
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureKeygenCommand {}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureStatusCommand {
+    #[clap(flatten)]
+    pub opts: CliConnectionOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureExportCommand {
+    /// The file path to the capture file to export
+    #[clap(name = "capturefile")]
+    pub capture_file_path: PathBuf,
+
+    /// Format to export to. 'jsonl' writes one decoded message per line, 'har' writes the
+    /// wasmcloud:httpserver requests and responses as an HTTP Archive
+    #[clap(long = "format", default_value = "jsonl")]
+    pub format: ExportFormat,
+
+    /// The file to write the export to
+    #[clap(short = 'd', long = "destination")]
+    pub destination: PathBuf,
+
+    #[clap(flatten)]
+    pub key: CaptureKeyOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureDiffCommand {
+    /// The capture file to compare against, usually taken before a change
+    #[clap(name = "before")]
+    pub before: PathBuf,
+
+    /// The capture file to compare, usually taken after a change. Error rates and response
+    /// shapes are only compared if both captures were enabled with --include-responses
+    #[clap(name = "after")]
+    pub after: PathBuf,
+
+    #[clap(flatten)]
+    pub key: CaptureKeyOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CaptureStatsCommand {
+    /// The file path to the capture file to read from. Error counts and latencies are only
+    /// available if the capture was enabled with --include-responses
+    #[clap(name = "capturefile")]
+    pub capture_file_path: PathBuf,
+
+    #[clap(flatten)]
+    pub key: CaptureKeyOpts,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -63,6 +236,45 @@ pub struct CaptureReplayCommand {
     #[clap(name = "provider_id", long = "provider-id", value_parser)]
     pub provider_id: Option<ServiceId>,
 
+    /// Only replay messages published at or after this time (RFC 3339, e.g.
+    /// 2023-06-01T12:00:00Z)
+    #[clap(name = "start", long = "start", value_parser = parse_timestamp)]
+    pub start: Option<time::OffsetDateTime>,
+
+    /// Only replay messages published at or before this time (RFC 3339, e.g.
+    /// 2023-06-01T12:05:00Z)
+    #[clap(name = "end", long = "end", value_parser = parse_timestamp)]
+    pub end: Option<time::OffsetDateTime>,
+
+    /// Only replay messages with a subject matching this NATS subject, which may contain the `*`
+    /// and `>` wildcards
+    #[clap(name = "subject", long = "subject")]
+    pub subject: Option<String>,
+
+    /// Re-publish the captured invocations to a live lattice instead of printing them, comparing
+    /// each new response with the captured one (when the capture includes responses). The
+    /// invocations are sent to the lattice prefix given with --lattice-prefix, or to the lattice
+    /// they were captured from. Exits with a non-zero status if any response diverged or any
+    /// invocation failed
+    #[clap(name = "publish", long = "publish", conflicts_with = "interactive")]
+    pub publish: bool,
+
+    /// When publishing, how much faster than the original timing to send invocations. 1 keeps the
+    /// original timing, 10 sends them ten times faster, and 0 sends them without waiting
+    #[clap(
+        name = "speed",
+        long = "speed",
+        default_value_t = 1.0,
+        requires = "publish"
+    )]
+    pub speed: f64,
+
+    #[clap(flatten)]
+    pub opts: CliConnectionOpts,
+
+    #[clap(flatten)]
+    pub key: CaptureKeyOpts,
+
     /// Whether or not to step through the replay one message at a time
     #[clap(name = "interactive", long = "interactive")]
     pub interactive: bool,
@@ -72,47 +284,51 @@ pub struct CaptureReplayCommand {
     pub capture_file_path: PathBuf,
 }
 
-pub async fn handle_replay_command(cmd: CaptureReplayCommand) -> Result<CommandOutput> {
-    let capture = ReadCapture::load(cmd.capture_file_path).await?;
-
-    let filtered = capture.messages.into_iter().filter_map(|msg| {
-        let mut inv: Invocation = rmp_serde::from_slice(&msg.payload).ok()?;
-
-        if let Some(actor_id) = &cmd.actor_id {
-            if (inv.origin.is_actor() && inv.origin.public_key != actor_id.as_ref())
-                || (inv.target.is_actor() && inv.target.public_key != actor_id.as_ref())
-            {
-                return None;
-            }
-        }
+pub async fn handle_replay_command(
+    cmd: CaptureReplayCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    let filter = CaptureFilter {
+        start: cmd.start,
+        end: cmd.end,
+        subject: cmd.subject.clone(),
+        actor_id: cmd.actor_id.clone(),
+        provider_id: cmd.provider_id.clone(),
+    };
+    if cmd.publish {
+        return publish_replay(cmd, filter, output_kind).await;
+    }
+    let mut reader = cmd
+        .key
+        .open(&cmd.capture_file_path)
+        .await?
+        .with_filter(filter);
 
-        if let Some(provider_id) = &cmd.provider_id {
-            if (inv.origin.is_provider() && inv.origin.public_key != provider_id.as_ref())
-                || (inv.target.is_provider() && inv.target.public_key != provider_id.as_ref())
-            {
-                return None;
-            }
+    if !reader.links().is_empty() {
+        println!("\nLinks at capture start:");
+        for link in reader.links() {
+            println!(
+                "  {} -> {} ({}, link {})",
+                link.actor_id, link.provider_id, link.contract_id, link.link_name
+            );
         }
-
-        let body = inv.msg;
-        inv.msg = Vec::new();
-        let from = inv.origin.public_key();
-        let to = inv.target.public_key();
-
-        Some((
-            ObservedInvocation {
-                invocation: inv,
-                timestamp: chrono::Local::now(),
-                from,
-                to,
-                message: ObservedMessage::parse(body),
-            },
-            msg.published,
-        ))
-    });
+    }
 
     let mut out = stdout();
-    for (msg, published) in filtered {
+    while let Some(msg) = reader.next_message().await? {
+        if let Some(event) = LatticeEvent::from_message(&msg) {
+            println!(
+                "\n[{}]\nEvent: {}  Host: {}\n{}",
+                event.published,
+                event.kind,
+                event.source,
+                event.summary()
+            );
+            continue;
+        }
+        let Some((msg, published)) = observe_message(msg) else {
+            continue;
+        };
         println!(
             r#"
 [{}]
@@ -136,25 +352,462 @@ Message: {}"#,
     Ok(CommandOutput::default())
 }
 
+/// Handles the stats subcommand, reporting statistics per origin, target and operation
+pub async fn handle_stats_command(cmd: CaptureStatsCommand) -> Result<CommandOutput> {
+    let mut reader = cmd.key.open(&cmd.capture_file_path).await?;
+    let operations = CaptureStats::from_reader(&mut reader).await?;
+    let names = entity_names(reader.inventory());
+    let name = |id: &String| names.get(id).unwrap_or(id).to_owned();
+    let ms = |d: Option<Duration>| d.map(|d| d.as_secs_f64() * 1000.0);
+
+    let mut table = Table::new();
+    super::configure_table_style(&mut table);
+    table.add_row(Row::new(
+        [
+            "FROM",
+            "TO",
+            "OPERATION",
+            "COUNT",
+            "ERRORS",
+            "SIZE P50",
+            "SIZE MAX",
+            "LAT P50",
+            "LAT P90",
+            "LAT P99",
+        ]
+        .into_iter()
+        .map(|h| TableCell::new_with_alignment(h, 1, Alignment::Left))
+        .collect::<Vec<_>>(),
+    ));
+    let fmt_ms = |v: Option<f64>| v.map_or("N/A".to_string(), |v| format!("{v:.2}ms"));
+    for op in operations.iter() {
+        table.add_row(Row::new(
+            [
+                name(&op.origin),
+                name(&op.target),
+                op.operation.clone(),
+                op.count.to_string(),
+                if op.responses > 0 {
+                    op.errors.to_string()
+                } else {
+                    "N/A".to_string()
+                },
+                op.payload_percentile(50.0).unwrap_or_default().to_string(),
+                op.payload_sizes
+                    .last()
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string(),
+                fmt_ms(ms(op.latency_percentile(50.0))),
+                fmt_ms(ms(op.latency_percentile(90.0))),
+                fmt_ms(ms(op.latency_percentile(99.0))),
+            ]
+            .into_iter()
+            .map(|v| TableCell::new_with_alignment(v, 1, Alignment::Left))
+            .collect::<Vec<_>>(),
+        ));
+    }
+
+    let json_ops = operations
+        .iter()
+        .map(|op| {
+            json!({
+                "origin": op.origin,
+                "origin_name": names.get(&op.origin),
+                "target": op.target,
+                "target_name": names.get(&op.target),
+                "operation": op.operation,
+                "count": op.count,
+                "responses": op.responses,
+                "errors": op.errors,
+                "payload_size": {
+                    "min": op.payload_sizes.first(),
+                    "p50": op.payload_percentile(50.0),
+                    "p90": op.payload_percentile(90.0),
+                    "max": op.payload_sizes.last(),
+                    "total": op.payload_sizes.iter().sum::<usize>(),
+                },
+                "latency_ms": {
+                    "p50": ms(op.latency_percentile(50.0)),
+                    "p90": ms(op.latency_percentile(90.0)),
+                    "p99": ms(op.latency_percentile(99.0)),
+                    "max": ms(op.latencies.last().copied()),
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    if !operations.is_empty() && operations.iter().all(|op| op.responses == 0) {
+        eprintln!(
+            "Warning: the capture has no responses, so error counts and latencies are unavailable. Responses are only recorded while `wash capture --enable --include-responses` keeps running"
+        );
+    }
+    let mut map = std::collections::HashMap::new();
+    map.insert("operations".to_string(), json!(json_ops));
+    Ok(CommandOutput::new(table.render(), map))
+}
+
+/// Handles the diff subcommand, reporting the operations that differ between two captures
+pub async fn handle_diff_command(cmd: CaptureDiffCommand) -> Result<CommandOutput> {
+    let mut before = cmd.key.open(&cmd.before).await?;
+    let mut after = cmd.key.open(&cmd.after).await?;
+    let diff = diff_captures(&mut before, &mut after).await?;
+    let mut names = entity_names(before.inventory());
+    names.extend(entity_names(after.inventory()));
+    let name = |id: &String| names.get(id).unwrap_or(id).to_owned();
+    let rate = |r: Option<f64>| r.map_or("N/A".to_string(), |r| format!("{:.1}%", r * 100.0));
+
+    let mut text = String::new();
+    for op in diff.iter().filter(|op| op.has_changes()) {
+        let label = format!(
+            "{} -> {} {}",
+            name(&op.origin),
+            name(&op.target),
+            op.operation
+        );
+        if op.is_new() {
+            text.push_str(&format!(
+                "+ {label} (new, {} invocations)\n",
+                op.after_count
+            ));
+            continue;
+        }
+        if op.is_missing() {
+            text.push_str(&format!(
+                "- {label} (missing, {} invocations)\n",
+                op.before_count
+            ));
+            continue;
+        }
+        text.push_str(&format!(
+            "~ {label} ({} -> {} invocations)\n",
+            op.before_count, op.after_count
+        ));
+        for (kind, changes) in [
+            ("request", &op.request_shapes),
+            ("response", &op.response_shapes),
+        ] {
+            for change in changes {
+                text.push_str(&format!(
+                    "    {kind} shape changed in {} invocations, first at #{}:\n      before: {}\n      after:  {}\n",
+                    change.count,
+                    change.index + 1,
+                    change.before,
+                    change.after
+                ));
+            }
+        }
+        if op.error_rate_changed() {
+            text.push_str(&format!(
+                "    error rate changed: {} -> {}\n",
+                rate(op.before_error_rate),
+                rate(op.after_error_rate)
+            ));
+        }
+    }
+    if text.is_empty() {
+        text.push_str("No differences found between the captures");
+    }
+
+    let shape_json = |changes: &Vec<ShapeChange>| {
+        changes
+            .iter()
+            .map(|c| json!({"index": c.index, "count": c.count, "before": c.before, "after": c.after}))
+            .collect::<Vec<_>>()
+    };
+    let json_ops = diff
+        .iter()
+        .map(|op| {
+            json!({
+                "origin": op.origin,
+                "origin_name": names.get(&op.origin),
+                "target": op.target,
+                "target_name": names.get(&op.target),
+                "operation": op.operation,
+                "status": if op.is_new() {
+                    "new"
+                } else if op.is_missing() {
+                    "missing"
+                } else if op.has_changes() {
+                    "changed"
+                } else {
+                    "unchanged"
+                },
+                "before_count": op.before_count,
+                "after_count": op.after_count,
+                "request_shape_changes": shape_json(&op.request_shapes),
+                "response_shape_changes": shape_json(&op.response_shapes),
+                "before_error_rate": op.before_error_rate,
+                "after_error_rate": op.after_error_rate,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut map = std::collections::HashMap::new();
+    map.insert("operations".to_string(), json!(json_ops));
+    Ok(CommandOutput::new(text, map))
+}
+
+/// Handles the export subcommand, writing the capture to the output file in the chosen format
+pub async fn handle_export_command(cmd: CaptureExportCommand) -> Result<CommandOutput> {
+    let mut reader = cmd.key.open(&cmd.capture_file_path).await?;
+    let file = tokio::fs::File::create(&cmd.destination).await?;
+    let count = export_capture(&mut reader, cmd.format, tokio::io::BufWriter::new(file)).await?;
+    let records = match cmd.format {
+        ExportFormat::JsonLines => "messages",
+        ExportFormat::Har => "HTTP requests",
+    };
+    let mut map = std::collections::HashMap::new();
+    map.insert("count".to_string(), json!(count));
+    map.insert("destination".to_string(), json!(cmd.destination));
+    Ok(CommandOutput::new(
+        format!(
+            "Exported {count} {records} to {}",
+            cmd.destination.display()
+        ),
+        map,
+    ))
+}
+
+/// The result of re-publishing a single captured invocation
+#[derive(Debug, PartialEq)]
+enum ReplayOutcome {
+    /// The new response is the same as the captured one
+    Matched,
+    /// The new response differs from the captured one in the listed ways
+    Diverged(Vec<String>),
+    /// A response was received, but the capture has no response to compare it with
+    NotCaptured,
+    /// The invocation could not be sent or no response was received
+    Failed(String),
+}
+
+/// Re-publishes the filtered invocations of a capture to a live lattice. The outcome of each
+/// invocation is printed as it is replayed with text output, or listed under `invocations` with
+/// JSON output. The returned output is unsuccessful if any response diverged or any invocation
+/// failed
+async fn publish_replay(
+    cmd: CaptureReplayCommand,
+    filter: CaptureFilter,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    if cmd.speed < 0.0 || !cmd.speed.is_finite() {
+        anyhow::bail!("--speed must be zero or a positive number");
+    }
+    let captured_responses = captured_responses(&cmd.capture_file_path, &cmd.key, &filter).await?;
+    let target_prefix = cmd.opts.lattice_prefix.clone();
+    let timeout = Duration::from_millis(cmd.opts.timeout_ms);
+    let wco: WashConnectionOptions = cmd.opts.try_into()?;
+    let nats_client = wco.into_nats_client().await?;
+
+    let mut reader = cmd
+        .key
+        .open(&cmd.capture_file_path)
+        .await?
+        .with_filter(filter);
+    let mut previous: Option<time::OffsetDateTime> = None;
+    let mut counts = std::collections::BTreeMap::<&str, u64>::new();
+    let mut invocations = Vec::new();
+    while let Some(msg) = reader.next_message().await? {
+        let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) else {
+            continue;
+        };
+        if let (Some(previous), true) = (previous, cmd.speed > 0.0) {
+            let gap = (msg.published - previous).as_seconds_f64() / cmd.speed;
+            if gap > 0.0 {
+                tokio::time::sleep(Duration::from_secs_f64(gap)).await;
+            }
+        }
+        previous = Some(msg.published);
+
+        let subject = match &target_prefix {
+            Some(prefix) => rewrite_lattice_prefix(&msg.subject, prefix),
+            None => msg.subject.clone(),
+        };
+        let outcome =
+            match tokio::time::timeout(timeout, nats_client.request(subject, msg.payload.clone()))
+                .await
+            {
+                Err(_) => ReplayOutcome::Failed("timed out waiting for a response".to_string()),
+                Ok(Err(e)) => ReplayOutcome::Failed(e.to_string()),
+                Ok(Ok(reply)) => {
+                    match rmp_serde::from_slice::<InvocationResponse>(&reply.payload) {
+                        Err(e) => ReplayOutcome::Failed(format!("unable to parse response: {e}")),
+                        Ok(replayed) => match captured_responses.get(&inv.id) {
+                            Some(captured) => match compare_responses(captured, &replayed) {
+                                differences if differences.is_empty() => ReplayOutcome::Matched,
+                                differences => ReplayOutcome::Diverged(differences),
+                            },
+                            None => ReplayOutcome::NotCaptured,
+                        },
+                    }
+                }
+            };
+
+        let (key, detail) = match &outcome {
+            ReplayOutcome::Matched => ("matched", "matched".to_string()),
+            ReplayOutcome::Diverged(differences) => (
+                "diverged",
+                format!("DIVERGED\n  {}", differences.join("\n  ")),
+            ),
+            ReplayOutcome::NotCaptured => ("not_captured", "no captured response".to_string()),
+            ReplayOutcome::Failed(e) => ("failed", format!("FAILED: {e}")),
+        };
+        *counts.entry(key).or_default() += 1;
+        if output_kind == OutputKind::Json {
+            let mut entry = json!({
+                "published": msg
+                    .published
+                    .format(&time::format_description::well_known::Rfc3339)?,
+                "from": inv.origin.public_key,
+                "to": inv.target.public_key,
+                "operation": inv.operation,
+                "outcome": key,
+            });
+            match outcome {
+                ReplayOutcome::Diverged(differences) => entry["differences"] = json!(differences),
+                ReplayOutcome::Failed(e) => entry["error"] = json!(e),
+                ReplayOutcome::Matched | ReplayOutcome::NotCaptured => {}
+            }
+            invocations.push(entry);
+        } else {
+            println!(
+                "[{}] {} -> {} {}: {detail}",
+                msg.published, inv.origin.public_key, inv.target.public_key, inv.operation
+            );
+        }
+    }
+
+    let total: u64 = counts.values().sum();
+    let success = counts.get("diverged").unwrap_or(&0) + counts.get("failed").unwrap_or(&0) == 0;
+    let text = format!(
+        "Replayed {total} invocations: {} matched, {} diverged, {} without a captured response, {} failed",
+        counts.get("matched").unwrap_or(&0),
+        counts.get("diverged").unwrap_or(&0),
+        counts.get("not_captured").unwrap_or(&0),
+        counts.get("failed").unwrap_or(&0),
+    );
+    let mut map: std::collections::HashMap<String, serde_json::Value> = counts
+        .into_iter()
+        .map(|(key, count)| (key.to_string(), count.into()))
+        .collect();
+    map.insert("replayed".to_string(), total.into());
+    map.insert("invocations".to_string(), invocations.into());
+    Ok(CommandOutput::new(text, map).with_success(success))
+}
+
+/// Collects the captured responses to the invocations that match the filter, keyed by
+/// invocation ID
+async fn captured_responses(
+    path: &PathBuf,
+    key: &CaptureKeyOpts,
+    filter: &CaptureFilter,
+) -> Result<std::collections::HashMap<String, InvocationResponse>> {
+    let mut ids = std::collections::HashSet::new();
+    let mut responses = std::collections::HashMap::new();
+    let mut reader = key.open(path).await?;
+    while let Some(msg) = reader.next_message().await? {
+        if let Ok(inv) = rmp_serde::from_slice::<Invocation>(&msg.payload) {
+            if filter.matches(&msg) {
+                ids.insert(inv.id);
+            }
+        } else if let Ok(resp) = rmp_serde::from_slice::<InvocationResponse>(&msg.payload) {
+            // Responses are always published after their invocation
+            if ids.remove(&resp.invocation_id) {
+                responses.insert(resp.invocation_id.clone(), resp);
+            }
+        }
+    }
+    Ok(responses)
+}
+
+/// Replaces the lattice prefix in a `wasmbus.rpc.{prefix}.{...}` subject
+fn rewrite_lattice_prefix(subject: &str, prefix: &str) -> String {
+    let mut tokens = subject.splitn(4, '.').collect::<Vec<_>>();
+    if tokens.len() == 4 && tokens[0] == "wasmbus" && tokens[1] == "rpc" {
+        tokens[2] = prefix;
+        tokens.join(".")
+    } else {
+        subject.to_string()
+    }
+}
+
+/// Returns a description of each difference between a captured and a replayed response
+fn compare_responses(captured: &InvocationResponse, replayed: &InvocationResponse) -> Vec<String> {
+    let mut differences = Vec::new();
+    let error = |resp: &InvocationResponse| resp.error.clone().filter(|e| !e.is_empty());
+    if error(captured) != error(replayed) {
+        differences.push(format!(
+            "error changed from {:?} to {:?}",
+            error(captured),
+            error(replayed)
+        ));
+    }
+    if captured.msg != replayed.msg {
+        differences.push(format!(
+            "response changed from {} to {}",
+            ObservedMessage::parse(captured.msg.clone()).to_json(),
+            ObservedMessage::parse(replayed.msg.clone()).to_json()
+        ));
+    }
+    differences
+}
+
+/// Parses a captured message as an invocation, returning it along with its publish time
+fn observe_message(msg: SerializableMessage) -> Option<(ObservedInvocation, time::OffsetDateTime)> {
+    let mut inv: Invocation = rmp_serde::from_slice(&msg.payload).ok()?;
+    let body = inv.msg;
+    inv.msg = Vec::new();
+    let payload_size = body.len();
+    let from = inv.origin.public_key();
+    let to = inv.target.public_key();
+
+    Some((
+        ObservedInvocation {
+            invocation: inv,
+            timestamp: chrono::Local::now(),
+            from,
+            to,
+            message: ObservedMessage::parse(body),
+            payload_size,
+            status: InvocationStatus::Unknown,
+            response: None,
+            latency: None,
+        },
+        msg.published,
+    ))
+}
+
+/// Parses an RFC 3339 timestamp
+fn parse_timestamp(value: &str) -> Result<time::OffsetDateTime> {
+    time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .map_err(|e| anyhow::anyhow!("invalid timestamp '{value}', expected RFC 3339: {e}"))
+}
+
 /// Handles the spy command, printing all output to stdout until the command is interrupted
 pub async fn handle_command(cmd: CaptureCommand) -> Result<CommandOutput> {
     let wco: WashConnectionOptions = cmd.opts.try_into()?;
     let nats_client = wco.clone().into_nats_client().await?;
     let ctl_client = wco.clone().into_ctl_client(None).await?;
     let js_context = if let Some(domain) = wco.js_domain {
-        async_nats::jetstream::with_domain(nats_client, domain)
+        async_nats::jetstream::with_domain(nats_client.clone(), domain)
     } else {
-        async_nats::jetstream::new(nats_client)
+        async_nats::jetstream::new(nats_client.clone())
     };
 
+    let lattice_id = wco.lattice_prefix.as_deref().unwrap_or("default");
     if cmd.enable {
         let window_size = Duration::from_secs(cmd.window_size_minutes * 60);
-        return enable(
-            js_context,
-            wco.lattice_prefix.as_deref().unwrap_or("default"),
-            window_size,
-        )
-        .await;
+        let subjects = capture_subjects(
+            lattice_id,
+            &cmd.actor_ids,
+            &cmd.provider_ids,
+            cmd.include_responses,
+        );
+        let enabled = enable(js_context.clone(), lattice_id, window_size, subjects).await?;
+        if !cmd.include_responses {
+            return Ok(enabled);
+        }
+        println!("{}", enabled.text);
+        return record_responses(js_context, nats_client, lattice_id).await;
     } else if cmd.disable {
         return disable(
             js_context,
@@ -163,21 +816,224 @@ pub async fn handle_command(cmd: CaptureCommand) -> Result<CommandOutput> {
         .await;
     }
 
-    capture(
-        js_context,
-        ctl_client,
-        wco.lattice_prefix.as_deref().unwrap_or("default"),
+    let start = match cmd.last_minutes {
+        Some(minutes) => {
+            Some(time::OffsetDateTime::now_utc() - time::Duration::minutes(minutes.try_into()?))
+        }
+        None => cmd.start,
+    };
+    let options = CaptureOptions {
+        start,
+        end: cmd.end,
+        redactor: Redactor::new(&cmd.redact_paths, &cmd.redact_patterns)?,
+        recipient: cmd
+            .recipient
+            .as_deref()
+            .map(CurveKey::from_public_key)
+            .transpose()?,
+    };
+    capture(js_context, ctl_client, lattice_id, options).await
+}
+
+/// Handles the keygen subcommand, generating a key pair for encrypted captures
+pub async fn handle_keygen_command(_cmd: CaptureKeygenCommand) -> Result<CommandOutput> {
+    let key = CurveKey::new()?;
+    let public_key = key.public_key();
+    let seed = key.seed()?;
+    let mut map = std::collections::HashMap::new();
+    map.insert("public_key".to_string(), json!(public_key));
+    map.insert("seed".to_string(), json!(seed));
+    Ok(CommandOutput::new(
+        format!(
+            "Public Key: {public_key}\nSeed: {seed}\n\nEncrypt captures with `wash capture --recipient {public_key}` and read them with --seed or WASH_CAPTURE_SEED. Keep the seed secret. Encrypted captures can only be decrypted by wash, not by other xkey tooling"
+        ),
+        map,
+    ))
+}
+
+/// Returns the subjects the capture stream should listen on. Invocations are published on the
+/// subject of their target, so filtering by actors and providers only captures the invocations
+/// sent to them. Lattice control events are always captured, and responses recorded by
+/// `--include-responses` if requested
+pub fn capture_subjects(
+    lattice_id: &str,
+    actor_ids: &[ModuleId],
+    provider_ids: &[ServiceId],
+    include_responses: bool,
+) -> Vec<String> {
+    let mut subjects = Vec::new();
+    if actor_ids.is_empty() && provider_ids.is_empty() {
+        subjects.push(format!("wasmbus.rpc.{lattice_id}.>"));
+    }
+    subjects.extend(
+        actor_ids
+            .iter()
+            .map(|id| format!("wasmbus.rpc.{lattice_id}.{id}")),
+    );
+    // Providers are invoked on `wasmbus.rpc.{lattice}.{provider_id}.{link_name}`
+    subjects.extend(
+        provider_ids
+            .iter()
+            .map(|id| format!("wasmbus.rpc.{lattice_id}.{id}.*")),
+    );
+    subjects.push(format!("{EVENT_SUBJECT_PREFIX}.{lattice_id}"));
+    if include_responses {
+        subjects.push(format!("{RESPONSE_SUBJECT_PREFIX}.{lattice_id}"));
+    }
+    subjects
+}
+
+/// Records the responses to the invocations captured by the stream of the lattice, republishing
+/// them on the response subject of the lattice so they are stored by the stream. Runs until the
+/// connection to NATS is closed.
+///
+/// Like the spier, this subscribes to `_INBOX.>`, so every request/reply response on the NATS
+/// server is delivered to the recorder, whichever lattice it belongs to. Only responses to
+/// captured invocations are republished, but on a busy server the subscription itself can be a
+/// lot of extra traffic for as long as the recorder runs
+async fn record_responses(
+    ctx: async_nats::jetstream::Context,
+    nats_client: async_nats::Client,
+    lattice_id: &str,
+) -> Result<CommandOutput> {
+    let response_subject = format!("{RESPONSE_SUBJECT_PREFIX}.{lattice_id}");
+    let mut stream = ctx
+        .get_stream(stream_name(lattice_id))
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    let subjects = stream
+        .info()
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        .config
+        .subjects
+        .clone();
+    if !subjects.contains(&response_subject) {
+        anyhow::bail!(
+            "Capture was enabled for lattice {lattice_id} without --include-responses. Run `wash capture --disable` and enable it again to capture responses"
+        );
+    }
+
+    let invocation_subs = futures::future::try_join_all(
+        subjects
+            .iter()
+            .filter(|subject| subject.starts_with("wasmbus.rpc."))
+            .map(|subject| nats_client.subscribe(subject.clone())),
     )
-    .await
+    .await?;
+    let mut invocations = futures::stream::select_all(invocation_subs);
+    // Responses are sent to an inbox of the invoker. Subscribing to every inbox up front, like
+    // the spier does, rather than to each reply subject once its invocation is seen, ensures
+    // responses that are sent before such a subscription would be set up aren't missed. Only
+    // responses to pending invocations are recorded
+    let mut responses = nats_client.subscribe("_INBOX.>".to_string()).await?;
+    let mut pending: std::collections::HashMap<String, Instant> = Default::default();
+    let mut expiry = tokio::time::interval(DEFAULT_RESPONSE_TIMEOUT);
+    let mut recorded: u64 = 0;
+
+    println!("Recording responses to captured invocations. Press Ctrl-C to stop");
+    loop {
+        tokio::select! {
+            msg = invocations.next() => {
+                let Some(msg) = msg else {
+                    break;
+                };
+                if let Some(reply) = msg.reply {
+                    pending.insert(reply, Instant::now());
+                }
+            }
+            Some(msg) = responses.next() => {
+                if pending.remove(msg.subject.as_str()).is_some()
+                    && rmp_serde::from_slice::<InvocationResponse>(&msg.payload).is_ok()
+                {
+                    nats_client
+                        .publish(response_subject.clone(), msg.payload)
+                        .await?;
+                    recorded += 1;
+                }
+            }
+            _ = expiry.tick() => {
+                pending.retain(|_, started| started.elapsed() < DEFAULT_RESPONSE_TIMEOUT);
+            }
+        }
+    }
+
+    Ok(CommandOutput::new(
+        format!("Recorded {recorded} responses"),
+        [("recorded".to_string(), json!(recorded))].into(),
+    ))
+}
+
+/// Handles the status subcommand, showing the state of the capture stream for the lattice
+pub async fn handle_status_command(cmd: CaptureStatusCommand) -> Result<CommandOutput> {
+    let wco: WashConnectionOptions = cmd.opts.try_into()?;
+    let nats_client = wco.clone().into_nats_client().await?;
+    let js_context = if let Some(domain) = wco.js_domain {
+        async_nats::jetstream::with_domain(nats_client, domain)
+    } else {
+        async_nats::jetstream::new(nats_client)
+    };
+    let lattice_id = wco.lattice_prefix.as_deref().unwrap_or("default");
+    let mut stream = js_context
+        .get_stream(stream_name(lattice_id))
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Capture is not enabled for lattice {lattice_id}. Run `wash capture --enable` first"
+            )
+        })?;
+    let info = stream.info().await.map_err(|e| anyhow::anyhow!("{e:?}"))?;
+    let state = &info.state;
+    let oldest = (state.messages > 0).then_some(state.first_timestamp);
+    let newest = (state.messages > 0).then_some(state.last_timestamp);
+
+    let mut text = format!(
+        "Capture stream {} for lattice {lattice_id}\n  Subjects: {}\n  Window: {} minutes\n  Messages: {}\n  Size: {} bytes",
+        info.config.name,
+        info.config.subjects.join(", "),
+        info.config.max_age.as_secs() / 60,
+        state.messages,
+        state.bytes,
+    );
+    if let (Some(oldest), Some(newest)) = (oldest, newest) {
+        text.push_str(&format!(
+            "\n  Oldest message: {oldest}\n  Newest message: {newest}"
+        ));
+    }
+
+    let mut map = std::collections::HashMap::new();
+    map.insert("stream".to_string(), json!(info.config.name));
+    map.insert("lattice_id".to_string(), json!(lattice_id));
+    map.insert("subjects".to_string(), json!(info.config.subjects));
+    map.insert(
+        "window_minutes".to_string(),
+        json!(info.config.max_age.as_secs() / 60),
+    );
+    map.insert("messages".to_string(), json!(state.messages));
+    map.insert("bytes".to_string(), json!(state.bytes));
+    map.insert(
+        "oldest_message".to_string(),
+        json!(oldest
+            .map(|t| t.format(&time::format_description::well_known::Rfc3339))
+            .transpose()?),
+    );
+    map.insert(
+        "newest_message".to_string(),
+        json!(newest
+            .map(|t| t.format(&time::format_description::well_known::Rfc3339))
+            .transpose()?),
+    );
+    Ok(CommandOutput::new(text, map))
 }
 
 pub async fn enable(
     ctx: async_nats::jetstream::Context,
     lattice_id: &str,
     window_size: Duration,
+    subjects: Vec<String>,
 ) -> Result<CommandOutput> {
     // Until we get concrete errors, we should check for the stream and if it exists return a nice message that we're already enabled
-    if ctx.get_stream(CAPTURE_STREAM_NAME).await.is_ok() {
+    if ctx.get_stream(stream_name(lattice_id)).await.is_ok() {
         return Ok(CommandOutput::from_key_and_text(
             "message",
             format!("Capture is already enabled for lattice {lattice_id}"),
@@ -189,7 +1045,7 @@ pub async fn enable(
         max_age: window_size,
         // This needs to be set or it breaks invocations
         no_ack: true,
-        subjects: vec![format!("wasmbus.rpc.{}.>", lattice_id)],
+        subjects,
         ..Default::default()
     })
     .await
@@ -215,24 +1071,58 @@ pub async fn disable(
     ))
 }
 
+/// Options controlling which messages a capture contains and how its file is written
+#[derive(Default)]
+pub struct CaptureOptions {
+    /// Only capture messages published at or after this time
+    pub start: Option<time::OffsetDateTime>,
+    /// Only capture messages published at or before this time
+    pub end: Option<time::OffsetDateTime>,
+    /// Redactions applied to every message before it is written
+    pub redactor: Redactor,
+    /// Encrypt the capture file for this key
+    pub recipient: Option<CurveKey>,
+}
+
 pub async fn capture(
     ctx: async_nats::jetstream::Context,
     ctl_client: wasmcloud_control_interface::Client,
     lattice_id: &str,
+    options: CaptureOptions,
 ) -> Result<CommandOutput> {
+    let CaptureOptions {
+        start,
+        end,
+        redactor,
+        recipient,
+    } = options;
+    if let (Some(start), Some(end)) = (start, end) {
+        if start > end {
+            anyhow::bail!("The capture start time must be before the end time");
+        }
+    }
     let stream = ctx.get_stream(stream_name(lattice_id)).await.map_err(|e| {
         anyhow::anyhow!("Unable to find stream. Have you run `wash capture --enable`? Error: {e:?}")
     })?;
 
     // Timestamp for cutoff of messages to capture
-    let capture_start_time = time::OffsetDateTime::now_utc();
+    let now = time::OffsetDateTime::now_utc();
+    let capture_end_time = end.map_or(now, |end| end.min(now));
 
     let inventory = get_all_inventory(&ctl_client).await?;
+    let links = ctl_client
+        .query_links()
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        .links;
 
     let consumer = stream
         .create_consumer(ConsumerConfig {
             description: Some("Wash capture consumer".to_string()),
-            deliver_policy: DeliverPolicy::All,
+            deliver_policy: match start {
+                Some(start_time) => DeliverPolicy::ByStartTime { start_time },
+                None => DeliverPolicy::All,
+            },
             ack_policy: AckPolicy::None,
             ..Default::default()
         })
@@ -255,7 +1145,14 @@ pub async fn capture(
         chrono::Local::now().to_rfc3339(),
         lattice_id
     );
-    let mut capture = WriteCapture::start(inventory, &filename).await?;
+    let mut capture = match recipient {
+        Some(recipient) => WriteCapture::start_encrypted(inventory, &filename, recipient).await?,
+        None => WriteCapture::start(inventory, &filename).await?,
+    };
+    if !redactor.is_empty() {
+        capture = capture.with_redactor(redactor);
+    }
+    capture.add_links(links).await?;
 
     loop {
         tokio::select! {
@@ -277,7 +1174,7 @@ pub async fn capture(
                     }
                 };
                 if let Ok(info) = msg.info() {
-                    if info.published > capture_start_time {
+                    if info.published > capture_end_time {
                         println!("Reached end of capture");
                         break;
                     }
@@ -329,3 +1226,69 @@ async fn get_all_inventory(
 fn stream_name(lattice_id: &str) -> String {
     format!("{}-{lattice_id}", CAPTURE_STREAM_NAME)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(msg: &[u8], error: Option<&str>) -> InvocationResponse {
+        let mut resp = InvocationResponse::default();
+        resp.msg = msg.to_vec();
+        resp.error = error.map(String::from);
+        resp
+    }
+
+    #[test]
+    fn test_compare_responses() {
+        let ok = response(&rmp_serde::to_vec(&"hello").unwrap(), None);
+        assert!(compare_responses(&ok, &ok).is_empty());
+        assert!(compare_responses(&ok, &response(&ok.msg, Some(""))).is_empty());
+        assert_eq!(
+            compare_responses(&ok, &response(&rmp_serde::to_vec(&"bye").unwrap(), None)),
+            vec!["response changed from \"hello\" to \"bye\""]
+        );
+        assert_eq!(
+            compare_responses(&ok, &response(&ok.msg, Some("boom"))),
+            vec!["error changed from None to Some(\"boom\")"]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_lattice_prefix() {
+        assert_eq!(
+            rewrite_lattice_prefix("wasmbus.rpc.default.MACTOR", "staging"),
+            "wasmbus.rpc.staging.MACTOR"
+        );
+        assert_eq!(
+            rewrite_lattice_prefix("wasmbus.rpc.default.VPROVIDER.default", "staging"),
+            "wasmbus.rpc.staging.VPROVIDER.default"
+        );
+        assert_eq!(
+            rewrite_lattice_prefix("_INBOX.abc", "staging"),
+            "_INBOX.abc"
+        );
+    }
+
+    #[test]
+    fn test_capture_subjects() {
+        assert_eq!(
+            capture_subjects("default", &[], &[], false),
+            vec!["wasmbus.rpc.default.>", "wasmbus.evt.default"]
+        );
+        let actor: ModuleId = "MBCFOPM6JW2APJLXJD3Z5O4CN7CPYJ2B4FTKLJUR5YR5MITIU7HD3WD5"
+            .parse()
+            .unwrap();
+        let provider: ServiceId = "VAG3QITQQ2ODAOWB5TTQSDJ53XK3SHBEIFNK4AYJ5RKAX2UNSCAPHA5M"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            capture_subjects("dev", &[actor.clone()], &[provider.clone()], true),
+            vec![
+                format!("wasmbus.rpc.dev.{actor}"),
+                format!("wasmbus.rpc.dev.{provider}.*"),
+                "wasmbus.evt.dev".to_string(),
+                "wash.capture.responses.dev".to_string(),
+            ]
+        );
+    }
+}
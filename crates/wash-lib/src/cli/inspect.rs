@@ -1,5 +1,5 @@
-use super::{cached_oci_file, CommandOutput, OutputKind};
-use crate::registry::{get_oci_artifact, OciPullOptions};
+use super::{registry::TrustPolicyOpts, CommandOutput, OutputKind};
+use crate::registry::{cache::OciCache, get_oci_artifact, OciPullOptions};
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use provider_archive::*;
@@ -57,6 +57,9 @@ pub struct InspectCliCommand {
     /// skip the local OCI cache
     #[clap(long = "no-cache")]
     pub no_cache: bool,
+
+    #[clap(flatten)]
+    pub trust: TrustPolicyOpts,
 }
 
 /// Attempts to inspect a provider archive or signed actor module
@@ -70,16 +73,17 @@ pub async fn handle_command(
         let mut f = File::open(command.target.clone())?;
         f.read_to_end(&mut buf)?;
     } else {
-        let cache_file = (!command.no_cache).then(|| cached_oci_file(&command.target.clone()));
+        let cache = (!command.no_cache).then(OciCache::default_cache);
         buf = get_oci_artifact(
             command.target.clone(),
-            cache_file,
+            cache.as_ref(),
             OciPullOptions {
                 digest: command.digest.clone(),
                 allow_latest: command.allow_latest,
                 user: command.user.clone(),
                 password: command.password.clone(),
                 insecure: command.insecure,
+                trust_policy: command.trust.clone().into_policy()?,
             },
         )
         .await?;
@@ -89,7 +93,7 @@ pub async fn handle_command(
         // Inspect an actor module
         let module_name = command.target.clone();
         let jwt_only = command.jwt_only;
-        let caps = get_caps(command.clone(), &buf).await?;
+        let caps = get_caps(&buf).await?;
         let token =
             caps.ok_or_else(|| anyhow!("No capabilities discovered in : {}", module_name))?;
         if jwt_only {
@@ -101,7 +105,7 @@ pub async fn handle_command(
             Ok(out)
         }
     } else {
-        handle_provider_archive(command.clone(), &buf).await
+        handle_provider_archive(&buf).await
     }
 }
 
@@ -114,8 +118,7 @@ fn is_wasm(input: &[u8]) -> bool {
 }
 
 /// Extracts claims for a given OCI artifact
-async fn get_caps(cmd: InspectCliCommand, artifact_bytes: &[u8]) -> Result<Option<Token<Actor>>> {
-    let _cache_path = (!cmd.no_cache).then(|| cached_oci_file(&cmd.target));
+async fn get_caps(artifact_bytes: &[u8]) -> Result<Option<Token<Actor>>> {
     // Extract will return an error if it encounters an invalid hash in the claims
     Ok(wascap::wasm::extract_claims(artifact_bytes)?)
 }
@@ -266,11 +269,7 @@ where
 }
 
 /// Inspects a provider archive
-pub(crate) async fn handle_provider_archive(
-    cmd: InspectCliCommand,
-    artifact_bytes: &[u8],
-) -> Result<CommandOutput> {
-    let _cache_file = (!cmd.no_cache).then(|| cached_oci_file(&cmd.target));
+pub(crate) async fn handle_provider_archive(artifact_bytes: &[u8]) -> Result<CommandOutput> {
     let artifact = ProviderArchive::try_load(artifact_bytes)
         .await
         .map_err(|e| anyhow!("{}", e))?;
@@ -380,6 +379,8 @@ mod test {
             "name",
             "--jwt-only",
             "--no-cache",
+            "--required-tag",
+            "prod",
         ])
         .unwrap();
         let InspectCliCommand {
@@ -391,6 +392,7 @@ mod test {
             password,
             insecure,
             no_cache,
+            trust,
         } = inspect_long.command;
         assert_eq!(target, LOCAL);
         assert_eq!(digest.unwrap(), "sha256:blah");
@@ -400,6 +402,7 @@ mod test {
         assert_eq!(password.unwrap(), "secret");
         assert!(jwt_only);
         assert!(no_cache);
+        assert_eq!(trust.required_tags, vec!["prod"]);
 
         let inspect_short: Cmd = Parser::try_parse_from([
             "inspect",
@@ -425,6 +428,7 @@ mod test {
             password,
             insecure,
             no_cache,
+            trust,
         } = inspect_short.command;
         assert_eq!(target, REMOTE);
         assert_eq!(digest.unwrap(), "sha256:blah");
@@ -434,6 +438,7 @@ mod test {
         assert_eq!(password.unwrap(), "secret");
         assert!(jwt_only);
         assert!(no_cache);
+        assert!(trust.required_tags.is_empty());
 
         let cmd: Cmd = Parser::try_parse_from([
             "inspect",
@@ -460,6 +465,7 @@ mod test {
             password,
             insecure,
             no_cache,
+            trust,
         } = cmd.command;
         assert_eq!(target, SUBSCRIBER_OCI);
         assert_eq!(
@@ -472,6 +478,7 @@ mod test {
         assert!(insecure);
         assert!(jwt_only);
         assert!(no_cache);
+        assert!(trust.required_tags.is_empty());
 
         let short_cmd: Cmd = Parser::try_parse_from([
             "inspect",
@@ -498,6 +505,7 @@ mod test {
             password,
             insecure,
             no_cache,
+            trust,
         } = short_cmd.command;
         assert_eq!(target, SUBSCRIBER_OCI);
         assert_eq!(
@@ -510,5 +518,6 @@ mod test {
         assert!(insecure);
         assert!(jwt_only);
         assert!(no_cache);
+        assert!(trust.required_tags.is_empty());
     }
 }
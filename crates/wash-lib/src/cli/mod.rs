@@ -83,6 +83,9 @@ impl std::fmt::Display for OutputParseErr {
 pub struct CommandOutput {
     pub map: std::collections::HashMap<String, serde_json::Value>,
     pub text: String,
+    /// Whether the command succeeded. Commands that ran to completion but found failures, like a
+    /// test suite with failing tests, still render their output but exit with a non-zero code
+    pub success: bool,
 }
 
 impl CommandOutput {
@@ -93,9 +96,16 @@ impl CommandOutput {
         CommandOutput {
             map,
             text: text.into(),
+            success: true,
         }
     }
 
+    /// Marks the output as a failure when `success` is false, so wash exits with a non-zero code
+    pub fn with_success(mut self, success: bool) -> Self {
+        self.success = success;
+        self
+    }
+
     /// shorthand to create a new CommandOutput with a single key-value pair for JSON, and simply the text for text output.
     pub fn from_key_and_text<K: Into<String>, S: Into<String>>(key: K, text: S) -> Self {
         let text_string: String = text.into();
@@ -104,6 +114,7 @@ impl CommandOutput {
         CommandOutput {
             map,
             text: text_string,
+            success: true,
         }
     }
 }
@@ -116,7 +127,11 @@ impl From<String> for CommandOutput {
             "result".to_string(),
             serde_json::Value::String(text.clone()),
         );
-        CommandOutput { map, text }
+        CommandOutput {
+            map,
+            text,
+            success: true,
+        }
     }
 }
 
@@ -132,6 +147,7 @@ impl Default for CommandOutput {
         CommandOutput {
             map: std::collections::HashMap::new(),
             text: "".to_string(),
+            success: true,
         }
     }
 }
@@ -379,20 +395,4 @@ fn empty_table_style() -> term_table::TableStyle {
     }
 }
 
-pub const OCI_CACHE_DIR: &str = "wasmcloud_ocicache";
-
-/// Given an oci reference, returns a path to a cache file for an artifact
-pub fn cached_oci_file(img: &str) -> PathBuf {
-    let path = std::env::temp_dir();
-    let path = path.join(OCI_CACHE_DIR);
-    let _ = ::std::fs::create_dir_all(&path);
-    // should produce a file like wasmcloud_azurecr_io_kvcounter_v1.bin
-    let mut path = path.join(img_name_to_file_name(img));
-    path.set_extension("bin");
-
-    path
-}
-
-fn img_name_to_file_name(img: &str) -> String {
-    img.replace([':', '/', '.'], "_")
-}
+pub use crate::registry::cache::OCI_CACHE_DIR;
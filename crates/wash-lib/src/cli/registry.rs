@@ -1,5 +1,14 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
 use clap::{Parser, Subcommand};
 
+use crate::{
+    config::context_dir,
+    context::{fs::ContextDir, ContextManager},
+    registry::policy::TrustPolicy,
+};
+
 #[derive(Parser, Debug, Clone)]
 pub struct AuthOpts {
     /// OCI username, if omitted anonymous authentication will be used
@@ -25,6 +34,41 @@ pub struct AuthOpts {
     pub insecure: bool,
 }
 
+#[derive(Parser, Debug, Clone, Default)]
+pub struct TrustPolicyOpts {
+    /// Public key of an account trusted to sign pulled artifacts. May be specified more than
+    /// once. If no trust options are given, the trust policy of the default context is used
+    #[clap(long = "trusted-issuer", number_of_values = 1)]
+    pub trusted_issuers: Vec<String>,
+
+    /// Tag that pulled actors must be signed with. May be specified more than once
+    #[clap(long = "required-tag", number_of_values = 1)]
+    pub required_tags: Vec<String>,
+}
+
+impl TrustPolicyOpts {
+    /// Returns the policy given on the command line, falling back to the trust policy of the
+    /// default context
+    pub fn into_policy(self) -> Result<TrustPolicy> {
+        let policy = TrustPolicy {
+            trusted_issuers: self.trusted_issuers,
+            required_tags: self.required_tags,
+        };
+        if !policy.is_empty() {
+            return Ok(policy);
+        }
+        let dir = context_dir(None)?;
+        if !dir.is_dir() {
+            return Ok(policy);
+        }
+        let ctx_dir = ContextDir::new(dir)?;
+        if ctx_dir.default_context()?.is_none() {
+            return Ok(policy);
+        }
+        Ok(ctx_dir.load_default_context()?.trust_policy)
+    }
+}
+
 #[derive(Debug, Clone, Subcommand)]
 pub enum RegistryCommand {
     /// Pull an artifact from an OCI compliant registry
@@ -36,6 +80,33 @@ pub enum RegistryCommand {
     /// Ping (test url) to see if the OCI url has an artifact
     #[clap(name = "ping")]
     Ping(RegistryPingCommand),
+    /// List the tags of a repository in an OCI compliant registry, sorted by semantic version
+    #[clap(name = "tags")]
+    Tags(RegistryTagsCommand),
+    /// List the repositories in an OCI compliant registry, if the registry supports it
+    #[clap(name = "catalog")]
+    Catalog(RegistryCatalogCommand),
+    /// Store credentials for a registry, used when no user and password are given
+    #[clap(name = "login")]
+    Login(RegistryLoginCommand),
+    /// Remove the stored credentials for a registry
+    #[clap(name = "logout")]
+    Logout(RegistryLogoutCommand),
+    /// Copy an artifact to another reference, possibly in another registry, keeping its manifest
+    /// and annotations
+    #[clap(name = "copy")]
+    Copy(RegistryCopyCommand),
+    /// Export artifacts to an OCI image layout bundle, for use on machines without access to
+    /// the registry
+    #[clap(name = "export")]
+    Export(RegistryExportCommand),
+    /// Import an OCI image layout bundle into a registry, such as a local registry on a network
+    /// without access to the original registries
+    #[clap(name = "import")]
+    Import(RegistryImportCommand),
+    /// Show the manifest, config and annotations of an artifact without downloading its layers
+    #[clap(name = "inspect")]
+    Inspect(RegistryInspectCommand),
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -44,7 +115,8 @@ pub struct RegistryPullCommand {
     #[clap(name = "url")]
     pub url: String,
 
-    /// File destination of artifact
+    /// File destination of artifact. When pulling an application bundle, the directory to write
+    /// its manifest and artifacts to (defaults to the current directory)
     #[clap(long = "destination")]
     pub destination: Option<String>,
 
@@ -58,6 +130,9 @@ pub struct RegistryPullCommand {
 
     #[clap(flatten)]
     pub opts: AuthOpts,
+
+    #[clap(flatten)]
+    pub trust: TrustPolicyOpts,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -66,7 +141,8 @@ pub struct RegistryPushCommand {
     #[clap(name = "url")]
     pub url: String,
 
-    /// Path to artifact to push
+    /// Path to artifact to push. A wadm manifest is pushed as an application bundle, along with
+    /// every local artifact it references
     #[clap(name = "artifact")]
     pub artifact: String,
 
@@ -95,3 +171,170 @@ pub struct RegistryPingCommand {
     #[clap(flatten)]
     pub opts: AuthOpts,
 }
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryTagsCommand {
+    /// URL of the repository, e.g. wasmcloud.azurecr.io/echo. A tag in the URL is ignored
+    #[clap(name = "url")]
+    pub url: String,
+
+    #[clap(flatten)]
+    pub opts: AuthOpts,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryCatalogCommand {
+    /// Registry to list the repositories of, e.g. localhost:5000
+    #[clap(name = "registry")]
+    pub registry: String,
+
+    #[clap(flatten)]
+    pub opts: AuthOpts,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryLoginCommand {
+    /// Registry to log in to, e.g. ghcr.io or localhost:5000
+    #[clap(name = "registry")]
+    pub registry: String,
+
+    /// Username for the registry
+    #[clap(short = 'u', long = "user", env = "WASH_REG_USER")]
+    pub user: String,
+
+    /// Password or token for the registry. If omitted, it is read from stdin with
+    /// --password-stdin or prompted for
+    #[clap(
+        short = 'p',
+        long = "password",
+        env = "WASH_REG_PASSWORD",
+        hide_env_values = true,
+        conflicts_with = "password_stdin"
+    )]
+    pub password: Option<String>,
+
+    /// Read the password from stdin
+    #[clap(long = "password-stdin")]
+    pub password_stdin: bool,
+
+    /// Store the credentials without checking them against the registry
+    #[clap(long = "no-verify")]
+    pub no_verify: bool,
+
+    /// Allow insecure (HTTP) registry connections
+    #[clap(long = "insecure")]
+    pub insecure: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryLogoutCommand {
+    /// Registry to remove the stored credentials of
+    #[clap(name = "registry")]
+    pub registry: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryCopyCommand {
+    /// URL of the artifact to copy
+    #[clap(name = "source")]
+    pub source: String,
+
+    /// URL to copy the artifact to
+    #[clap(name = "destination")]
+    pub destination: String,
+
+    /// Additional tag to give the artifact in the destination repository. May be specified more
+    /// than once
+    #[clap(short = 't', long = "tag", number_of_values = 1)]
+    pub tags: Vec<String>,
+
+    /// Allow latest artifact tags
+    #[clap(long = "allow-latest")]
+    pub allow_latest: bool,
+
+    /// OCI username for the source registry, if omitted stored credentials or anonymous
+    /// authentication will be used
+    #[clap(
+        long = "source-user",
+        env = "WASH_REG_SOURCE_USER",
+        hide_env_values = true
+    )]
+    pub source_user: Option<String>,
+
+    /// OCI password for the source registry
+    #[clap(
+        long = "source-password",
+        env = "WASH_REG_SOURCE_PASSWORD",
+        hide_env_values = true
+    )]
+    pub source_password: Option<String>,
+
+    /// Allow insecure (HTTP) connections to the source registry
+    #[clap(long = "source-insecure")]
+    pub source_insecure: bool,
+
+    /// OCI username for the destination registry, if omitted stored credentials or anonymous
+    /// authentication will be used
+    #[clap(
+        long = "destination-user",
+        env = "WASH_REG_DESTINATION_USER",
+        hide_env_values = true
+    )]
+    pub destination_user: Option<String>,
+
+    /// OCI password for the destination registry
+    #[clap(
+        long = "destination-password",
+        env = "WASH_REG_DESTINATION_PASSWORD",
+        hide_env_values = true
+    )]
+    pub destination_password: Option<String>,
+
+    /// Allow insecure (HTTP) connections to the destination registry
+    #[clap(long = "destination-insecure")]
+    pub destination_insecure: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryExportCommand {
+    /// URLs of the artifacts to export
+    #[clap(name = "url", required = true)]
+    pub urls: Vec<String>,
+
+    /// Path to write the bundle to. Paths ending in .tar, .tar.gz or .tgz are written as a
+    /// tarball, anything else as a directory
+    #[clap(short = 'd', long = "destination")]
+    pub destination: PathBuf,
+
+    /// Allow latest artifact tags
+    #[clap(long = "allow-latest")]
+    pub allow_latest: bool,
+
+    #[clap(flatten)]
+    pub opts: AuthOpts,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryImportCommand {
+    /// Path to a bundle directory or tarball created with `wash reg export`
+    #[clap(name = "bundle")]
+    pub bundle: PathBuf,
+
+    /// Registry to push the artifacts to, keeping their repositories and tags (e.g.
+    /// localhost:5000)
+    #[clap(long = "registry")]
+    pub registry: String,
+
+    #[clap(flatten)]
+    pub opts: AuthOpts,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct RegistryInspectCommand {
+    /// URL of artifact
+    #[clap(name = "url")]
+    pub url: String,
+
+    #[clap(flatten)]
+    pub opts: AuthOpts,
+}
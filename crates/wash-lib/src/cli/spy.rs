@@ -1,9 +1,14 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use clap::Parser;
 use futures::StreamExt;
 
-use super::{CliConnectionOpts, CommandOutput};
-use crate::{config::WashConnectionOptions, spier::Spier};
+use super::{CliConnectionOpts, CommandOutput, OutputKind};
+use crate::{
+    config::WashConnectionOptions,
+    spier::{Spier, SpyFilter, DEFAULT_RESPONSE_TIMEOUT},
+};
 
 #[derive(Debug, Parser, Clone)]
 pub struct SpyCommand {
@@ -11,41 +16,117 @@ pub struct SpyCommand {
     /// by checking if the actor_name or call_alias fields from the actor's claims contains the
     /// given string. If more than one matches, then an error will be returned indicating the
     /// options to choose from
-    #[clap(name = "actor")]
-    pub actor: String,
+    #[clap(name = "actor", required_unless_present = "lattice")]
+    pub actor: Option<String>,
+
+    /// Spy on every invocation in the lattice instead of a single actor. Use the filter options
+    /// to narrow down which invocations are displayed
+    #[clap(long = "lattice", conflicts_with = "actor")]
+    pub lattice: bool,
+
+    /// Only display invocations to or from this actor ID or name. May be specified more than once
+    #[clap(long = "filter-actor", number_of_values = 1)]
+    pub filter_actors: Vec<String>,
+
+    /// Only display invocations to or from this provider ID or name. May be specified more than
+    /// once
+    #[clap(long = "filter-provider", number_of_values = 1)]
+    pub filter_providers: Vec<String>,
+
+    /// Only display invocations to or from providers with this contract ID (e.g.
+    /// wasmcloud:httpserver). May be specified more than once
+    #[clap(long = "contract-id", number_of_values = 1)]
+    pub contract_ids: Vec<String>,
+
+    /// Only display invocations of operations matching this pattern, where `*` matches any
+    /// number of characters (e.g. 'HttpServer.*'). May be specified more than once
+    #[clap(long = "operation", number_of_values = 1)]
+    pub operations: Vec<String>,
+
+    /// Only display invocations with a payload of at least this many bytes
+    #[clap(long = "min-payload-size")]
+    pub min_payload_size: Option<usize>,
+
+    /// Only display invocations with a payload of at most this many bytes
+    #[clap(long = "max-payload-size")]
+    pub max_payload_size: Option<usize>,
+
+    /// How long to wait for the response to an invocation before displaying it as timed out.
+    /// Responses are collected by subscribing to every reply inbox on the NATS server (`_INBOX.>`),
+    /// not just those of this lattice, which can be a lot of traffic on a busy server. Set to 0 to
+    /// skip that subscription and display invocations as soon as they arrive, without responses
+    #[clap(
+        long = "response-timeout-ms",
+        default_value_t = DEFAULT_RESPONSE_TIMEOUT.as_millis() as u64
+    )]
+    pub response_timeout_ms: u64,
 
     #[clap(flatten)]
     pub opts: CliConnectionOpts,
 }
 
-/// Handles the spy command, printing all output to stdout until the command is interrupted
-pub async fn handle_command(cmd: SpyCommand) -> Result<CommandOutput> {
+/// Handles the spy command, printing all output to stdout until the command is interrupted. With
+/// JSON output, each invocation is printed as a single line JSON object
+pub async fn handle_command(cmd: SpyCommand, output_kind: OutputKind) -> Result<CommandOutput> {
     let wco: WashConnectionOptions = cmd.opts.try_into()?;
     let ctl_client = wco.clone().into_ctl_client(None).await?;
     let nats_client = wco.into_nats_client().await?;
 
-    let mut spier = Spier::new(&cmd.actor, &ctl_client, &nats_client).await?;
+    let filter = SpyFilter {
+        actors: cmd.filter_actors,
+        providers: cmd.filter_providers,
+        contract_ids: cmd.contract_ids,
+        operations: cmd.operations,
+        min_payload_size: cmd.min_payload_size,
+        max_payload_size: cmd.max_payload_size,
+    };
+    let mut spier = match cmd.actor {
+        Some(actor) => Spier::new_with_filter(&actor, filter, &ctl_client, &nats_client).await?,
+        None => Spier::new_lattice(filter, &ctl_client, &nats_client).await?,
+    };
+    spier.set_response_timeout(Duration::from_millis(cmd.response_timeout_ms));
 
-    println!("Spying on actor {}\n", spier.actor_id());
+    if output_kind == OutputKind::Text {
+        match spier.actor_id() {
+            Some(actor_id) => println!("Spying on actor {actor_id}\n"),
+            None => println!("Spying on all invocations in the lattice\n"),
+        }
+    }
 
     while let Some(msg) = spier.next().await {
+        if output_kind == OutputKind::Json {
+            println!("{}", msg.to_json());
+            continue;
+        }
+        let latency = msg
+            .latency
+            .map(|l| format!(" ({:.3}ms)", l.as_secs_f64() * 1000.0))
+            .unwrap_or_default();
         println!(
             r#"
 [{}]
 From: {:<25}To: {:<25}Host: {}
 
 Operation: {}
-Message: {}"#,
+Message: {}
+Status: {}{}"#,
             msg.timestamp,
             msg.from,
             msg.to,
             msg.invocation.host_id,
             msg.invocation.operation,
-            msg.message
+            msg.message,
+            msg.status,
+            latency
         );
+        if let Some(response) = &msg.response {
+            println!("Response: {response}");
+        }
     }
 
-    println!("Message subscribers closed");
+    if output_kind == OutputKind::Text {
+        println!("Message subscribers closed");
+    }
 
     Ok(CommandOutput::default())
 }
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use crate::id::ModuleId;
 
@@ -27,7 +27,9 @@ pub enum FindIdError {
 ///
 /// If the string is a valid actor ID, it will be returned unchanged. Resolution works by checking
 /// if the actor_name or call_alias fields from the actor's claims contains the given string. If
-/// more than one matches, then an error will be returned indicating the options to choose from
+/// exactly one actor's name or call alias is equal to the given string, that actor is chosen.
+/// Otherwise, if more than one matches, then an error will be returned indicating the options to
+/// choose from
 pub async fn find_actor_id(
     value: &str,
     ctl_client: &wasmcloud_control_interface::Client,
@@ -36,15 +38,24 @@ pub async fn find_actor_id(
         return Ok((id, None));
     }
 
-    // Case insensitive searching here to make things nicer
-    let value = value.to_lowercase();
     // If it wasn't an ID, get the claims
     let claims = ctl_client
         .get_claims()
         .await
         .map_err(|e| FindIdError::Error(anyhow::anyhow!("Unable to get claims: {}", e)))?;
+    match_actor_claims(value, &claims.claims)
+}
+
+/// Finds the actor whose claims match the given name or call alias. Matching is case
+/// insensitive and matches on substrings, unless exactly one actor has a name or call alias
+/// equal to the given value, in which case that actor is chosen
+fn match_actor_claims(
+    value: &str,
+    claims: &[HashMap<String, String>],
+) -> Result<(ModuleId, Option<String>), FindIdError> {
+    // Case insensitive searching here to make things nicer
+    let value = value.to_lowercase();
     let all_matches = claims
-        .claims
         .iter()
         .filter_map(|v| {
             let id = v
@@ -56,24 +67,33 @@ pub async fn find_actor_id(
                 Ok(id) => id,
                 Err(_) => return None,
             };
-            (v.get(CLAIMS_CALL_ALIAS)
+            let call_alias = v
+                .get(CLAIMS_CALL_ALIAS)
+                .map(|s| s.to_lowercase())
+                .unwrap_or_default();
+            let name = v
+                .get(CLAIMS_NAME)
                 .map(|s| s.to_lowercase())
-                .unwrap_or_default()
-                .contains(&value)
-                || v.get(CLAIMS_NAME)
-                    .map(|s| s.to_ascii_lowercase())
-                    .unwrap_or_default()
-                    .contains(&value))
-            .then(|| (id, v.get(CLAIMS_NAME).map(|s| s.to_string())))
+                .unwrap_or_default();
+            let exact = call_alias == value || name == value;
+            (call_alias.contains(&value) || name.contains(&value))
+                .then(|| (id, v.get(CLAIMS_NAME).map(|s| s.to_string()), exact))
         })
         .collect::<Vec<_>>();
+    let exact_matches = all_matches
+        .iter()
+        .filter(|(_, _, exact)| *exact)
+        .collect::<Vec<_>>();
+    if let [(id, friendly_name, _)] = exact_matches.as_slice() {
+        return Ok((id.clone(), friendly_name.clone()));
+    }
     if all_matches.is_empty() {
         Err(FindIdError::NoMatches)
     } else if all_matches.len() > 1 {
         Err(FindIdError::MultipleMatches(
             all_matches
                 .into_iter()
-                .map(|(id, friendly_name)| {
+                .map(|(id, friendly_name, _)| {
                     if let Some(name) = friendly_name {
                         format!("{} ({})", id, name)
                     } else {
@@ -84,6 +104,54 @@ pub async fn find_actor_id(
         ))
     } else {
         // SAFETY: We know we have exactly one match at this point
-        Ok(all_matches.into_iter().next().unwrap())
+        let (id, friendly_name, _) = all_matches.into_iter().next().unwrap();
+        Ok((id, friendly_name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ECHO_ID: &str = "MDPDJEYIAK6MACO67PRFGOSSLODBISK4SCEYDY3HEOY4P5CVJN6UCWUK";
+    const ECHO_MESSAGING_ID: &str = "MBCFOPM6JW2APJLXJD3Z5O4CN7CPYJ2B4FTKLJUR5YR5MITIU7HD3WD5";
+
+    fn claims(id: &str, name: &str, call_alias: Option<&str>) -> HashMap<String, String> {
+        let mut claims = HashMap::from([
+            (CLAIMS_SUBJECT.to_string(), id.to_string()),
+            (CLAIMS_NAME.to_string(), name.to_string()),
+        ]);
+        if let Some(alias) = call_alias {
+            claims.insert(CLAIMS_CALL_ALIAS.to_string(), alias.to_string());
+        }
+        claims
+    }
+
+    #[test]
+    fn test_match_actor_claims() {
+        let all = vec![
+            claims(ECHO_ID, "Echo", Some("wasmcloud/echo")),
+            claims(ECHO_MESSAGING_ID, "Echo Messaging", None),
+        ];
+
+        // Exact name match wins over substring matches
+        let (id, name) = match_actor_claims("echo", &all).unwrap();
+        assert_eq!(id.to_string(), ECHO_ID);
+        assert_eq!(name.as_deref(), Some("Echo"));
+
+        let (id, _) = match_actor_claims("messaging", &all).unwrap();
+        assert_eq!(id.to_string(), ECHO_MESSAGING_ID);
+
+        let (id, _) = match_actor_claims("WASMCLOUD/ECHO", &all).unwrap();
+        assert_eq!(id.to_string(), ECHO_ID);
+
+        match match_actor_claims("ech", &all) {
+            Err(FindIdError::MultipleMatches(matches)) => assert_eq!(matches.len(), 2),
+            other => panic!("expected multiple matches, got {other:?}"),
+        }
+        assert!(matches!(
+            match_actor_claims("kvcounter", &all),
+            Err(FindIdError::NoMatches)
+        ));
     }
 }
@@ -13,6 +13,7 @@ use crate::{
         DEFAULT_NATS_TIMEOUT_MS,
     },
     id::ClusterSeed,
+    registry::policy::TrustPolicy,
 };
 
 use crate::context::fs::{load_context, ContextDir};
@@ -93,6 +94,11 @@ pub struct WashContext {
     /// rpc timeout in milliseconds
     #[serde(default = "default_timeout_ms")]
     pub rpc_timeout: u64,
+
+    /// Claims that artifacts pulled from registries must have, unless overridden on the command
+    /// line
+    #[serde(default, skip_serializing_if = "TrustPolicy::is_empty")]
+    pub trust_policy: TrustPolicy,
 }
 
 impl WashContext {
@@ -124,6 +130,7 @@ impl Default for WashContext {
             rpc_seed: None,
             rpc_credsfile: None,
             rpc_timeout: DEFAULT_NATS_TIMEOUT_MS,
+            trust_policy: TrustPolicy::default(),
         }
     }
 }
@@ -1,8 +1,13 @@
 //! Remove cached wasmCloud files like OCI artifacts or downloaded binaries
 
-use std::{env, fs, io::Result, path::PathBuf};
+use std::{
+    env, fs,
+    io::Result,
+    path::{Path, PathBuf},
+};
 
 use crate::config::{downloads_dir, model_cache_dir};
+use crate::registry::cache::OCI_CACHE_DIR;
 
 /// A type that allows you to clean up (i.e. drain) a set of caches and folders used by wasmcloud
 #[derive(Debug, Clone)]
@@ -28,12 +33,12 @@ impl IntoIterator for &Drain {
         let paths = match self {
             Drain::All => vec![
                 /* Lib    */ env::temp_dir().join("wasmcloudcache"),
-                /* Oci    */ env::temp_dir().join("wasmcloud_ocicache"),
+                /* Oci    */ env::temp_dir().join(OCI_CACHE_DIR),
                 /* Smithy */ model_cache_dir().unwrap_or_default(),
                 /* Downloads */ downloads_dir().unwrap_or_default(),
             ],
             Drain::Lib => vec![env::temp_dir().join("wasmcloudcache")],
-            Drain::Oci => vec![env::temp_dir().join("wasmcloud_ocicache")],
+            Drain::Oci => vec![env::temp_dir().join(OCI_CACHE_DIR)],
             Drain::Smithy => vec![model_cache_dir().unwrap_or_default()],
             Drain::Downloads => vec![downloads_dir().unwrap_or_default()],
         };
@@ -50,6 +55,29 @@ impl Drain {
             .map(remove_dir_contents)
             .collect::<Result<Vec<PathBuf>>>()
     }
+
+    /// Returns the paths that would be cleaned along with the total size in bytes of the files
+    /// in each of them
+    pub fn usage(&self) -> Result<Vec<(PathBuf, u64)>> {
+        self.into_iter()
+            .filter(|path| path.exists())
+            .map(|path| dir_size(&path).map(|size| (path, size)))
+            .collect()
+    }
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
 }
 
 fn remove_dir_contents(path: PathBuf) -> Result<PathBuf> {
@@ -93,4 +121,17 @@ mod test {
             "Directory should be empty"
         );
     }
+
+    #[test]
+    fn test_dir_size() {
+        let tempdir = tempfile::tempdir().expect("Unable to create tempdir");
+
+        let subdir = tempdir.path().join("foobar");
+        fs::create_dir(&subdir).unwrap();
+        fs::write(subdir.join("baz"), b"12345").unwrap();
+        fs::write(tempdir.path().join("baz"), b"123").unwrap();
+
+        assert_eq!(dir_size(tempdir.path()).unwrap(), 8);
+        assert_eq!(dir_size(&subdir).unwrap(), 5);
+    }
 }
@@ -0,0 +1,116 @@
+//! Types of the `wasmcloud:httpserver` interface, used to build requests for and decode responses
+//! from actors that handle HTTP requests
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Contract ID of the httpserver capability
+pub const HTTPSERVER_CONTRACT: &str = "wasmcloud:httpserver";
+/// Operation invoked on httpserver actors
+pub const HANDLE_REQUEST: &str = "HttpServer.HandleRequest";
+
+/// Request sent to httpserver actors, as defined by the `wasmcloud:httpserver` interface
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    #[serde(rename = "queryString", default)]
+    pub query_string: String,
+    #[serde(default)]
+    pub header: HashMap<String, Vec<String>>,
+    #[serde(default, with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+/// Response returned by httpserver actors, as defined by the `wasmcloud:httpserver` interface
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpResponse {
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+    #[serde(default)]
+    pub header: HashMap<String, Vec<String>>,
+    #[serde(default, with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Encodes the request the way the httpserver provider sends it
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        wasmbus_rpc::common::serialize(self).context("Unable to encode HttpRequest")
+    }
+}
+
+impl HttpResponse {
+    /// Decodes a response returned by an httpserver actor
+    pub fn from_bytes(response: &[u8]) -> Result<Self> {
+        wasmbus_rpc::common::deserialize(response).with_context(|| {
+            format!(
+                "Error interpreting response as HttpResponse. Response: {}",
+                String::from_utf8_lossy(response)
+            )
+        })
+    }
+
+    /// Body as JSON if the response says it is JSON, otherwise a string if it is valid UTF-8,
+    /// otherwise an array of bytes
+    pub fn body_json(&self) -> serde_json::Value {
+        if matches!(content_type(&self.header), Some(content_type) if content_type.contains("json"))
+        {
+            if let Ok(value) = serde_json::from_slice(&self.body) {
+                return value;
+            }
+        }
+        match std::str::from_utf8(&self.body) {
+            Ok(body) => json!(body),
+            Err(_) => json!(self.body),
+        }
+    }
+}
+
+/// Returns the first value of the `content-type` header, in any casing
+pub fn content_type(headers: &HashMap<String, Vec<String>>) -> Option<&str> {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_response_roundtrip() {
+        let response = HttpResponse {
+            status_code: 201,
+            header: HashMap::from([(
+                "Content-Type".to_string(),
+                vec!["application/json".to_string()],
+            )]),
+            body: br#"{"id":7}"#.to_vec(),
+        };
+        let bytes = wasmbus_rpc::common::serialize(&response).unwrap();
+        let decoded = HttpResponse::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, response);
+        assert_eq!(content_type(&decoded.header), Some("application/json"));
+        assert_eq!(decoded.body_json(), json!({"id": 7}));
+
+        let text = HttpResponse {
+            status_code: 404,
+            body: b"not found".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(text.body_json(), json!("not found"));
+
+        // Requests from older providers may omit fields
+        let request: HttpRequest = rmp_serde::from_slice(
+            &rmp_serde::to_vec_named(&json!({"method": "GET", "path": "/"})).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(request.path, "/");
+        assert!(request.body.is_empty());
+    }
+}
@@ -34,6 +34,7 @@ pub mod common;
 pub mod config;
 pub mod context;
 pub mod drain;
+pub mod httpserver;
 pub mod id;
 pub mod keys;
 pub mod registry;
@@ -0,0 +1,581 @@
+//! Application bundles: a wadm manifest and the actors and providers it references, pushed to a
+//! repository as a single OCI image index.
+//!
+//! Every local artifact referenced by the manifest is pushed to the repository of the bundle by
+//! digest, without a tag, and the manifest is rewritten to reference the pushed artifacts by
+//! digest. The rewritten manifest is pushed the same way as an artifact of its own, and an image
+//! index listing the manifest and the pushed components is pushed under the tag of the bundle, so
+//! a bundle only ever adds its own tag to the repository. Since everything in the index is
+//! referenced by digest, the tag of the bundle pins a complete application version.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use oci_distribution::{
+    client::{Client, Config, ImageLayer},
+    manifest::{
+        ImageIndexEntry, OciDescriptor, OciImageIndex, OciImageManifest, OciManifest,
+        OCI_IMAGE_INDEX_MEDIA_TYPE,
+    },
+    secrets::RegistryAuth,
+    Reference,
+};
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+
+use super::{
+    artifact_image, canonical_json, explicit_tag, oci_client, pull_artifact, pull_image_artifact,
+    pull_verified_blob, push_image, resolve_auth, validate_artifact, OciPullOptions,
+    OciPushOptions, SupportedArtifacts,
+};
+
+/// Media type of the layer holding the wadm manifest of a bundle
+pub const WADM_MANIFEST_MEDIA_TYPE: &str = "application/vnd.wasmcloud.wadm.manifest.layer.v1+yaml";
+/// Media type of the config of the wadm manifest artifact
+pub const WADM_MANIFEST_CONFIG_MEDIA_TYPE: &str = "application/vnd.wasmcloud.wadm.manifest.config";
+/// Annotation on index entries telling whether the entry is the `manifest` or a `component`
+pub const BUNDLE_ROLE_ANNOTATION: &str = "com.wasmcloud.bundle.role";
+/// Annotation on component index entries holding the name of the component in the manifest
+pub const BUNDLE_COMPONENT_ANNOTATION: &str = "com.wasmcloud.bundle.component";
+/// Annotation on the index holding the name of the application
+pub const BUNDLE_NAME_ANNOTATION: &str = "com.wasmcloud.bundle.name";
+/// Annotation on the index holding the version of the application, if the manifest has one
+pub const BUNDLE_VERSION_ANNOTATION: &str = "com.wasmcloud.bundle.version";
+
+const MANIFEST_ROLE: &str = "manifest";
+const COMPONENT_ROLE: &str = "component";
+const WASM_FILE_EXTENSION: &str = ".wasm";
+const PROVIDER_ARCHIVE_FILE_EXTENSION: &str = ".par.gz";
+
+/// A component artifact of an application bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppBundleComponent {
+    /// Name of the component in the wadm manifest
+    pub name: String,
+    /// Reference of the artifact, pinned to its digest
+    pub reference: String,
+    /// The local file the artifact was pushed from, or written to when pulling
+    pub path: Option<PathBuf>,
+}
+
+/// The result of pushing or pulling an application bundle
+#[derive(Debug, Clone)]
+pub struct AppBundle {
+    /// Reference of the bundle
+    pub reference: String,
+    /// Digest of the bundle's image index
+    pub digest: String,
+    /// Name of the application
+    pub name: String,
+    /// Version of the application, if the manifest has one
+    pub version: Option<String>,
+    /// Path the wadm manifest was written to, when pulling
+    pub manifest_path: Option<PathBuf>,
+    /// Components pushed with, or pulled from, the bundle. Components that reference artifacts in
+    /// other repositories are left as they are and not listed
+    pub components: Vec<AppBundleComponent>,
+}
+
+/// Returns true if the data is a wadm application manifest
+pub fn is_wadm_manifest(data: &[u8]) -> bool {
+    match serde_yaml::from_slice::<Value>(data) {
+        Ok(manifest) => {
+            manifest.get("kind").and_then(Value::as_str) == Some("Application")
+                && manifest
+                    .get("apiVersion")
+                    .and_then(Value::as_str)
+                    .map(|version| version.starts_with("core.oam.dev/"))
+                    .unwrap_or(false)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Pushes the wadm manifest at `manifest_path` and every local artifact it references as an
+/// application bundle to `url`. Component images that are paths (optionally prefixed with
+/// `file://`, relative to the manifest) to existing files are pushed, anything else is left as is
+pub async fn push_app_bundle(
+    url: &str,
+    manifest_path: impl AsRef<Path>,
+    options: OciPushOptions,
+) -> Result<AppBundle> {
+    let image: Reference = url.to_lowercase().parse()?;
+    let tag = image.tag().unwrap_or("latest");
+    if tag == "latest" && !options.allow_latest {
+        bail!("Pushing artifacts with tag 'latest' is prohibited");
+    }
+    if options.config.is_some() {
+        bail!("A config can't be given when pushing an application bundle");
+    }
+
+    let manifest_path = manifest_path.as_ref();
+    let mut manifest: Value = serde_yaml::from_slice(&tokio::fs::read(manifest_path).await?)
+        .with_context(|| format!("Unable to parse {}", manifest_path.display()))?;
+    let (name, version) = app_name_and_version(&manifest)?;
+    let manifest_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut client = oci_client(options.insecure);
+    let auth = resolve_auth(&image, options.user, options.password).await;
+    let mut entries = Vec::new();
+    let mut components = Vec::new();
+    for (component, component_image) in component_images(&manifest) {
+        let Some(path) = local_artifact_path(&component_image, manifest_dir) else {
+            continue;
+        };
+        let (layers, config) = artifact_image(tokio::fs::read(&path).await?, b"{}".to_vec())
+            .await
+            .with_context(|| format!("Unable to push component {component}"))?;
+        let descriptor = push_untagged(&mut client, &image, &auth, &layers, config).await?;
+        let pinned = format!(
+            "{}/{}@{}",
+            image.registry(),
+            image.repository(),
+            descriptor.digest
+        );
+        set_component_image(&mut manifest, &component, &pinned);
+        entries.push(ImageIndexEntry {
+            media_type: descriptor.media_type,
+            digest: descriptor.digest,
+            size: descriptor.size,
+            platform: None,
+            annotations: Some(HashMap::from([
+                (
+                    BUNDLE_ROLE_ANNOTATION.to_string(),
+                    COMPONENT_ROLE.to_string(),
+                ),
+                (BUNDLE_COMPONENT_ANNOTATION.to_string(), component.clone()),
+            ])),
+        });
+        components.push(AppBundleComponent {
+            name: component,
+            reference: pinned,
+            path: Some(path),
+        });
+    }
+
+    let manifest_layers = vec![ImageLayer {
+        data: serde_yaml::to_string(&manifest)?.into_bytes(),
+        media_type: WADM_MANIFEST_MEDIA_TYPE.to_string(),
+        annotations: None,
+    }];
+    let manifest_config = Config {
+        data: b"{}".to_vec(),
+        media_type: WADM_MANIFEST_CONFIG_MEDIA_TYPE.to_string(),
+        annotations: None,
+    };
+    let descriptor = push_untagged(
+        &mut client,
+        &image,
+        &auth,
+        &manifest_layers,
+        manifest_config,
+    )
+    .await?;
+    entries.insert(
+        0,
+        ImageIndexEntry {
+            media_type: descriptor.media_type,
+            digest: descriptor.digest,
+            size: descriptor.size,
+            platform: None,
+            annotations: Some(HashMap::from([(
+                BUNDLE_ROLE_ANNOTATION.to_string(),
+                MANIFEST_ROLE.to_string(),
+            )])),
+        },
+    );
+
+    let mut annotations = options.annotations.unwrap_or_default();
+    annotations.insert(BUNDLE_NAME_ANNOTATION.to_string(), name.clone());
+    if let Some(version) = version.as_ref() {
+        annotations.insert(BUNDLE_VERSION_ANNOTATION.to_string(), version.clone());
+    }
+    let index = OciImageIndex {
+        schema_version: 2,
+        media_type: Some(OCI_IMAGE_INDEX_MEDIA_TYPE.to_string()),
+        manifests: entries,
+        annotations: Some(annotations),
+    };
+    let digest = format!(
+        "sha256:{}",
+        sha256_hex(&canonical_json(&OciManifest::ImageIndex(index.clone()))?)
+    );
+    client.push_manifest_list(&image, &auth, index).await?;
+
+    Ok(AppBundle {
+        reference: image.whole(),
+        digest,
+        name,
+        version,
+        manifest_path: None,
+        components,
+    })
+}
+
+/// The content an image reference points at, told apart by its manifest
+pub enum PulledReference {
+    /// A single actor or provider artifact
+    Artifact(Vec<u8>),
+    /// An application bundle, whose manifest and components are pulled with
+    /// [`BundleIndex::pull_into`]
+    Bundle(BundleIndex),
+}
+
+/// The image index of an application bundle
+pub struct BundleIndex {
+    image: Reference,
+    index: OciImageIndex,
+    digest: String,
+    client: Client,
+    auth: RegistryAuth,
+    options: OciPullOptions,
+}
+
+/// Fetches the manifest of `url` and pulls the artifact it points at, unless it is the image index
+/// of an application bundle, in which case nothing else is pulled until
+/// [`BundleIndex::pull_into`] is called with a destination directory
+pub async fn pull_reference(url: &str, options: OciPullOptions) -> Result<PulledReference> {
+    let image: Reference = url.to_lowercase().parse()?;
+    if !options.allow_latest && image.digest().is_none() {
+        match explicit_tag(url)?.as_deref() {
+            Some("latest") => bail!("Pulling artifacts with tag 'latest' is prohibited. This can be overriden with the flag '--allow-latest'."),
+            None => bail!("Registry URLs must have explicit tag. To default missing tags to 'latest', use the flag '--allow-latest'."),
+            _ => (),
+        }
+    }
+
+    let mut client = oci_client(options.insecure);
+    let auth = resolve_auth(&image, options.user.clone(), options.password.clone()).await;
+    let (index, digest) = match client.pull_manifest(&image, &auth).await? {
+        (OciManifest::Image(manifest), digest) => {
+            let artifact =
+                pull_image_artifact(&client, &image, &manifest, &digest, &options).await?;
+            return Ok(PulledReference::Artifact(artifact));
+        }
+        (OciManifest::ImageIndex(index), digest) => (index, digest),
+    };
+    if !index
+        .manifests
+        .iter()
+        .any(|entry| entry_annotation(entry, BUNDLE_ROLE_ANNOTATION) == Some(MANIFEST_ROLE))
+    {
+        bail!(
+            "{} is an image index, but not an application bundle",
+            image.whole()
+        );
+    }
+    if let Some(expected) = super::normalize_digest(options.digest.clone()) {
+        if expected != digest {
+            bail!("Image digest did not match provided digest, aborting");
+        }
+    }
+    Ok(PulledReference::Bundle(BundleIndex {
+        image,
+        index,
+        digest,
+        client,
+        auth,
+        options,
+    }))
+}
+
+impl BundleIndex {
+    /// Pulls the bundle into the `destination` directory, writing the wadm manifest as
+    /// `<name>.yaml` and every component as `<component>.wasm` or `<component>.par.gz`.
+    /// Components are pulled by digest and checked against the trust policy in the options
+    pub async fn pull_into(self, destination: impl AsRef<Path>) -> Result<AppBundle> {
+        let BundleIndex {
+            image,
+            index,
+            digest,
+            mut client,
+            auth,
+            options,
+        } = self;
+        let Some(manifest_entry) = index
+            .manifests
+            .iter()
+            .find(|entry| entry_annotation(entry, BUNDLE_ROLE_ANNOTATION) == Some(MANIFEST_ROLE))
+        else {
+            bail!("The bundle has no wadm manifest");
+        };
+
+        let destination = destination.as_ref();
+        tokio::fs::create_dir_all(destination).await?;
+
+        let manifest_ref = Reference::with_digest(
+            image.registry().to_string(),
+            image.repository().to_string(),
+            manifest_entry.digest.clone(),
+        );
+        let manifest = match client.pull_manifest(&manifest_ref, &auth).await? {
+            (OciManifest::Image(manifest), _) => manifest,
+            (OciManifest::ImageIndex(_), _) => {
+                bail!("The manifest of the bundle is an image index")
+            }
+        };
+        let Some(layer) = manifest
+            .layers
+            .iter()
+            .find(|layer| layer.media_type == WADM_MANIFEST_MEDIA_TYPE)
+        else {
+            bail!("The bundle has no wadm manifest");
+        };
+        let manifest_data = pull_verified_blob(&client, &manifest_ref, layer).await?;
+        let (name, version) = app_name_and_version(&serde_yaml::from_slice(&manifest_data)?)?;
+        let manifest_path = destination.join(format!("{}.yaml", file_name(&name)));
+        tokio::fs::write(&manifest_path, &manifest_data).await?;
+
+        let mut components = Vec::new();
+        for entry in index.manifests.iter() {
+            if entry_annotation(entry, BUNDLE_ROLE_ANNOTATION) != Some(COMPONENT_ROLE) {
+                continue;
+            }
+            let component = entry_annotation(entry, BUNDLE_COMPONENT_ANNOTATION)
+                .unwrap_or(&entry.digest)
+                .to_string();
+            let reference = format!(
+                "{}/{}@{}",
+                image.registry(),
+                image.repository(),
+                entry.digest
+            );
+            let (artifact, _) = pull_artifact(
+                reference.clone(),
+                OciPullOptions {
+                    digest: Some(entry.digest.clone()),
+                    allow_latest: true,
+                    ..options.clone()
+                },
+            )
+            .await
+            .with_context(|| format!("Unable to pull component {component}"))?;
+            let extension = match validate_artifact(&artifact).await? {
+                SupportedArtifacts::Wasm => WASM_FILE_EXTENSION,
+                SupportedArtifacts::Par => PROVIDER_ARCHIVE_FILE_EXTENSION,
+            };
+            let path = destination.join(format!("{}{extension}", file_name(&component)));
+            tokio::fs::write(&path, &artifact).await?;
+            components.push(AppBundleComponent {
+                name: component,
+                reference,
+                path: Some(path),
+            });
+        }
+
+        Ok(AppBundle {
+            reference: image.whole(),
+            digest,
+            name,
+            version,
+            manifest_path: Some(manifest_path),
+            components,
+        })
+    }
+}
+
+fn entry_annotation<'a>(entry: &'a ImageIndexEntry, key: &str) -> Option<&'a str> {
+    entry
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(key))
+        .map(String::as_str)
+}
+
+/// Returns the name of the application and its version annotation, if any
+fn app_name_and_version(manifest: &Value) -> Result<(String, Option<String>)> {
+    let metadata = manifest
+        .get("metadata")
+        .context("The wadm manifest has no metadata")?;
+    let name = metadata
+        .get("name")
+        .and_then(Value::as_str)
+        .context("The wadm manifest has no application name")?
+        .to_string();
+    let version = metadata
+        .get("annotations")
+        .and_then(|a| a.get("version"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    Ok((name, version))
+}
+
+/// Returns the name and image of every component in the manifest that has an image
+fn component_images(manifest: &Value) -> Vec<(String, String)> {
+    manifest
+        .get("spec")
+        .and_then(|spec| spec.get("components"))
+        .and_then(Value::as_sequence)
+        .map(|components| {
+            components
+                .iter()
+                .filter_map(|component| {
+                    let name = component.get("name")?.as_str()?;
+                    let image = component.get("properties")?.get("image")?.as_str()?;
+                    Some((name.to_string(), image.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Points the image of the named component at a new reference
+fn set_component_image(manifest: &mut Value, component: &str, image: &str) {
+    let Some(components) = manifest
+        .get_mut("spec")
+        .and_then(|spec| spec.get_mut("components"))
+        .and_then(Value::as_sequence_mut)
+    else {
+        return;
+    };
+    for properties in components
+        .iter_mut()
+        .filter(|c| c.get("name").and_then(Value::as_str) == Some(component))
+        .filter_map(|c| c.get_mut("properties"))
+    {
+        if let Some(properties) = properties.as_mapping_mut() {
+            properties.insert(Value::from("image"), Value::from(image));
+        }
+    }
+}
+
+/// Returns the path of a component image if it refers to an existing local file
+fn local_artifact_path(image: &str, manifest_dir: &Path) -> Option<PathBuf> {
+    let path = Path::new(image.strip_prefix("file://").unwrap_or(image));
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        manifest_dir.join(path)
+    };
+    path.is_file().then_some(path)
+}
+
+/// Pushes an image to the repository of the bundle by its digest, so it doesn't add a tag
+async fn push_untagged(
+    client: &mut Client,
+    bundle: &Reference,
+    auth: &RegistryAuth,
+    layers: &[ImageLayer],
+    config: Config,
+) -> Result<OciDescriptor> {
+    let manifest = OciImageManifest::build(layers, &config, None);
+    let digest = format!(
+        "sha256:{}",
+        sha256_hex(&canonical_json(&OciManifest::Image(manifest))?)
+    );
+    let image = Reference::with_digest(
+        bundle.registry().to_string(),
+        bundle.repository().to_string(),
+        digest,
+    );
+    push_image(client, &image, auth, layers, config, None).await
+}
+
+/// Replaces anything but ASCII letters, digits, `_`, `.` and `-` so the name is safe to use as a
+/// file name
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: v0.1.0
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: file://./build/echo_s.wasm
+    - name: httpserver
+      type: capability
+      properties:
+        image: wasmcloud.azurecr.io/httpserver:0.17.0
+        contract: wasmcloud:httpserver
+"#;
+
+    #[test]
+    fn test_manifest_components() {
+        assert!(is_wadm_manifest(MANIFEST.as_bytes()));
+        assert!(!is_wadm_manifest(b"\0asm"));
+        assert!(!is_wadm_manifest(b"kind: Deployment\napiVersion: apps/v1"));
+
+        let mut manifest: Value = serde_yaml::from_str(MANIFEST).unwrap();
+        assert_eq!(
+            app_name_and_version(&manifest).unwrap(),
+            ("echo".to_string(), Some("v0.1.0".to_string()))
+        );
+        assert_eq!(
+            component_images(&manifest),
+            vec![
+                ("echo".to_string(), "file://./build/echo_s.wasm".to_string()),
+                (
+                    "httpserver".to_string(),
+                    "wasmcloud.azurecr.io/httpserver:0.17.0".to_string()
+                ),
+            ]
+        );
+
+        set_component_image(&mut manifest, "echo", "localhost:5000/app@sha256:abc");
+        assert_eq!(
+            component_images(&manifest)[0].1,
+            "localhost:5000/app@sha256:abc"
+        );
+        // Other properties are kept
+        assert_eq!(
+            manifest["spec"]["components"][1]["properties"]["contract"].as_str(),
+            Some("wasmcloud:httpserver")
+        );
+    }
+
+    #[test]
+    fn test_local_artifact_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("build")).unwrap();
+        std::fs::write(tempdir.path().join("build/echo_s.wasm"), b"\0asm").unwrap();
+
+        assert_eq!(
+            local_artifact_path("file://./build/echo_s.wasm", tempdir.path()),
+            Some(tempdir.path().join("./build/echo_s.wasm"))
+        );
+        assert_eq!(
+            local_artifact_path("build/echo_s.wasm", tempdir.path()),
+            Some(tempdir.path().join("build/echo_s.wasm"))
+        );
+        assert_eq!(
+            local_artifact_path("wasmcloud.azurecr.io/echo:0.3.4", tempdir.path()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("echo"), "echo");
+        assert_eq!(file_name("http server/1"), "http_server_1");
+        assert_eq!(file_name("../etc"), "_etc");
+    }
+}
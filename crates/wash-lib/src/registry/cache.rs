@@ -0,0 +1,368 @@
+//! A content-addressed cache for artifacts pulled from OCI registries.
+//!
+//! Artifacts are stored by the sha256 digest of their content under `blobs/sha256`, and an index
+//! maps each image reference to the digest of its manifest and content. Contents are verified
+//! against their digest whenever they are read, and the least recently used artifacts are evicted
+//! once the cache grows beyond its size limit.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::lock::FileLock;
+
+/// Name of the cache directory in the system temporary directory
+pub const OCI_CACHE_DIR: &str = "wasmcloud_ocicache";
+/// Environment variable that sets the size limit of the default cache, in megabytes
+pub const OCI_CACHE_MAX_SIZE_ENV: &str = "WASH_OCI_CACHE_MAX_MB";
+/// Size limit of the default cache
+pub const DEFAULT_OCI_CACHE_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+const INDEX_FILE: &str = "index.json";
+const INDEX_LOCK_FILE: &str = "index.lock";
+const BLOBS_DIR: &str = "blobs";
+
+/// A cached artifact for an image reference
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Digest of the manifest the artifact was pulled with, if the registry returned one
+    pub manifest_digest: Option<String>,
+    /// Digest of the artifact content
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BlobInfo {
+    size: u64,
+    /// Seconds since the unix epoch when the blob was last read or written
+    last_used: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    #[serde(default)]
+    references: HashMap<String, CacheEntry>,
+    #[serde(default)]
+    blobs: HashMap<String, BlobInfo>,
+}
+
+/// Size and contents of the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheUsage {
+    /// Number of artifacts stored in the cache
+    pub artifacts: usize,
+    /// Number of references pointing at those artifacts
+    pub references: usize,
+    /// Total size of the artifacts in bytes
+    pub size: u64,
+    /// Size limit of the cache in bytes, if any
+    pub max_size: Option<u64>,
+}
+
+pub struct OciCache {
+    root: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl OciCache {
+    /// Opens the cache in the given directory without a size limit. Directories are created
+    /// when the first artifact is stored
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        OciCache {
+            root: root.as_ref().to_path_buf(),
+            max_size: None,
+        }
+    }
+
+    /// Opens the default cache in the system temporary directory, limited to
+    /// [`DEFAULT_OCI_CACHE_MAX_SIZE`] or the number of megabytes set in
+    /// [`OCI_CACHE_MAX_SIZE_ENV`]
+    pub fn default_cache() -> Self {
+        let max_size = std::env::var(OCI_CACHE_MAX_SIZE_ENV)
+            .ok()
+            .and_then(|mb| mb.trim().parse::<u64>().ok())
+            .map(|mb| mb * 1024 * 1024)
+            .unwrap_or(DEFAULT_OCI_CACHE_MAX_SIZE);
+        OciCache::new(std::env::temp_dir().join(OCI_CACHE_DIR)).with_max_size(max_size)
+    }
+
+    /// Evicts the least recently used artifacts whenever the cache grows beyond `max_size` bytes
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the entry for the image reference, if it has been cached
+    pub async fn entry(&self, reference: &str) -> Result<Option<CacheEntry>> {
+        Ok(self.load_index().await?.references.remove(reference))
+    }
+
+    /// Reads the cached artifact for the image reference. Returns `None` if the reference isn't
+    /// cached or its content no longer matches its digest, in which case the corrupt content is
+    /// removed
+    pub async fn get(&self, reference: &str) -> Result<Option<Vec<u8>>> {
+        let _lock = self.lock_index().await?;
+        let mut index = self.load_index().await?;
+        let Some(entry) = index.references.get(reference).cloned() else {
+            return Ok(None);
+        };
+        let data = match tokio::fs::read(self.blob_path(&entry.digest)).await {
+            Ok(data) if sha256_digest(&data) == entry.digest => data,
+            Ok(_) => {
+                log::warn!("Cached artifact for {reference} is corrupt, removing it");
+                self.remove_blob(&mut index, &entry.digest).await?;
+                self.save_index(&index).await?;
+                return Ok(None);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                index.references.remove(reference);
+                index.blobs.remove(&entry.digest);
+                self.save_index(&index).await?;
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(blob) = index.blobs.get_mut(&entry.digest) {
+            blob.last_used = now();
+        }
+        self.save_index(&index).await?;
+        Ok(Some(data))
+    }
+
+    /// Stores the artifact for the image reference, replacing whatever the reference pointed to
+    /// before, and evicts old artifacts if the cache is over its size limit
+    pub async fn put(
+        &self,
+        reference: &str,
+        manifest_digest: Option<String>,
+        data: &[u8],
+    ) -> Result<CacheEntry> {
+        let digest = sha256_digest(data);
+        // The blob is written under the lock, so another process evicting or replacing the same
+        // digest can't remove it between the write and the index update
+        let _lock = self.lock_index().await?;
+        write_atomic(&self.blob_path(&digest), data).await?;
+        let mut index = self.load_index().await?;
+        let entry = CacheEntry {
+            manifest_digest,
+            digest: digest.clone(),
+        };
+        let previous = index
+            .references
+            .insert(reference.to_string(), entry.clone());
+        index.blobs.insert(
+            digest.clone(),
+            BlobInfo {
+                size: data.len() as u64,
+                last_used: now(),
+            },
+        );
+        if let Some(previous) = previous.filter(|p| p.digest != digest) {
+            self.remove_if_unreferenced(&mut index, &previous.digest)
+                .await?;
+        }
+        self.evict(&mut index, &digest).await?;
+        self.save_index(&index).await?;
+        Ok(entry)
+    }
+
+    /// Returns the number and size of cached artifacts
+    pub async fn usage(&self) -> Result<CacheUsage> {
+        let index = self.load_index().await?;
+        Ok(CacheUsage {
+            artifacts: index.blobs.len(),
+            references: index.references.len(),
+            size: index.blobs.values().map(|b| b.size).sum(),
+            max_size: self.max_size,
+        })
+    }
+
+    /// Removes least recently used blobs (never `keep`) until the cache fits its size limit
+    async fn evict(&self, index: &mut CacheIndex, keep: &str) -> Result<()> {
+        let Some(max_size) = self.max_size else {
+            return Ok(());
+        };
+        let mut size: u64 = index.blobs.values().map(|b| b.size).sum();
+        if size <= max_size {
+            return Ok(());
+        }
+        let mut blobs = index
+            .blobs
+            .iter()
+            .filter(|(digest, _)| digest.as_str() != keep)
+            .map(|(digest, info)| (info.last_used, info.size, digest.clone()))
+            .collect::<Vec<_>>();
+        blobs.sort();
+        for (_, blob_size, digest) in blobs {
+            if size <= max_size {
+                break;
+            }
+            log::debug!("Evicting {digest} from the OCI cache");
+            self.remove_blob(index, &digest).await?;
+            size = size.saturating_sub(blob_size);
+        }
+        Ok(())
+    }
+
+    async fn remove_if_unreferenced(&self, index: &mut CacheIndex, digest: &str) -> Result<()> {
+        if !index.references.values().any(|e| e.digest == digest) {
+            self.remove_blob(index, digest).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the blob along with every reference pointing at it
+    async fn remove_blob(&self, index: &mut CacheIndex, digest: &str) -> Result<()> {
+        index.blobs.remove(digest);
+        index.references.retain(|_, entry| entry.digest != digest);
+        match tokio::fs::remove_file(self.blob_path(digest)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn blob_path(&self, digest: &str) -> PathBuf {
+        let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+        self.root.join(BLOBS_DIR).join(algorithm).join(hex)
+    }
+
+    async fn load_index(&self) -> Result<CacheIndex> {
+        let path = self.root.join(INDEX_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::warn!("OCI cache index is corrupt, starting over: {e}");
+                CacheIndex::default()
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CacheIndex::default()),
+            Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+        }
+    }
+
+    /// Locks the index. The lock is held while blobs are written and the index is loaded, modified
+    /// and saved so concurrent processes don't overwrite or remove each other's changes
+    async fn lock_index(&self) -> Result<FileLock> {
+        FileLock::acquire(self.root.join(INDEX_LOCK_FILE)).await
+    }
+
+    /// Saves the index. Callers that loaded the index to modify it must hold the index lock
+    async fn save_index(&self, index: &CacheIndex) -> Result<()> {
+        write_atomic(&self.root.join(INDEX_FILE), &serde_json::to_vec(index)?).await
+    }
+}
+
+/// Writes the file through a uniquely named temporary file in the same directory, so readers
+/// never see a partial file and concurrent writers never write to the same temporary file
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    tokio::fs::create_dir_all(dir).await?;
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tokio::fs::write(tmp.path(), data).await?;
+    tmp.persist(path)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = OciCache::new(tempdir.path());
+        assert_eq!(cache.get("localhost:5000/echo:0.1.0").await.unwrap(), None);
+
+        let entry = cache
+            .put(
+                "localhost:5000/echo:0.1.0",
+                Some("sha256:abc".to_string()),
+                b"echo v1",
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.digest, sha256_digest(b"echo v1"));
+        assert_eq!(
+            cache.get("localhost:5000/echo:0.1.0").await.unwrap(),
+            Some(b"echo v1".to_vec())
+        );
+
+        // Moving a tag replaces the content it points at
+        cache
+            .put("localhost:5000/echo:0.1.0", None, b"echo v2")
+            .await
+            .unwrap();
+        let usage = cache.usage().await.unwrap();
+        assert_eq!((usage.artifacts, usage.references), (1, 1));
+        assert_eq!(usage.size, 7);
+
+        // Corrupt content is detected and removed
+        tokio::fs::write(cache.blob_path(&sha256_digest(b"echo v2")), b"tampered")
+            .await
+            .unwrap();
+        assert_eq!(cache.get("localhost:5000/echo:0.1.0").await.unwrap(), None);
+        assert_eq!(cache.usage().await.unwrap().artifacts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cache_eviction() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache = OciCache::new(tempdir.path()).with_max_size(10);
+        cache.put("a:1", None, b"aaaa").await.unwrap();
+        cache.put("b:1", None, b"bbbb").await.unwrap();
+        // Both blobs share the same timestamp resolution, so make `a` the most recently used
+        let mut index = cache.load_index().await.unwrap();
+        index
+            .blobs
+            .get_mut(&sha256_digest(b"bbbb"))
+            .unwrap()
+            .last_used = 0;
+        cache.save_index(&index).await.unwrap();
+
+        cache.put("c:1", None, b"cccc").await.unwrap();
+        assert!(cache.get("a:1").await.unwrap().is_some());
+        assert!(cache.get("b:1").await.unwrap().is_none());
+        assert!(cache.get("c:1").await.unwrap().is_some());
+        assert_eq!(cache.usage().await.unwrap().size, 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cache_concurrent_puts() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let puts = (0..8).map(|i| {
+            let cache = OciCache::new(tempdir.path());
+            tokio::spawn(async move {
+                cache
+                    .put(&format!("echo:{i}"), None, format!("echo {i}").as_bytes())
+                    .await
+            })
+        });
+        for put in futures::future::join_all(puts).await {
+            put.unwrap().unwrap();
+        }
+        // No put lost the references stored by the others
+        let usage = OciCache::new(tempdir.path()).usage().await.unwrap();
+        assert_eq!((usage.artifacts, usage.references), (8, 8));
+        assert!(!tempdir.path().join(INDEX_LOCK_FILE).exists());
+    }
+}
@@ -0,0 +1,405 @@
+//! Registry credentials, either stored by `wash reg login` or read from the docker configuration
+//! (`~/.docker/config.json`), including docker credential helper programs
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::lock::FileLock;
+use crate::config::cfg_dir;
+
+const CREDENTIALS_FILE: &str = "credentials.json";
+const DOCKER_HUB: &str = "docker.io";
+/// The key docker uses for Docker Hub in its configuration and credential helpers
+const DOCKER_HUB_KEY: &str = "https://index.docker.io/v1/";
+
+/// A username and password (or token) for a registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// The subset of the docker configuration file that wash uses. The wash credential store uses
+/// the same format, so existing tooling can read it
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AuthConfig {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    auths: BTreeMap<String, AuthEntry>,
+    #[serde(
+        default,
+        rename = "credsStore",
+        skip_serializing_if = "Option::is_none"
+    )]
+    creds_store: Option<String>,
+    #[serde(
+        default,
+        rename = "credHelpers",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    cred_helpers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AuthEntry {
+    /// base64 encoded `username:password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+}
+
+impl AuthEntry {
+    fn credentials(&self) -> Option<Credentials> {
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            return Some(Credentials {
+                username: username.clone(),
+                password: password.clone(),
+            });
+        }
+        let decoded = data_encoding::BASE64
+            .decode(self.auth.as_ref()?.trim().as_bytes())
+            .ok()?;
+        let (username, password) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+        Some(Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+/// Output of `docker-credential-<helper> get`
+#[derive(Debug, Deserialize)]
+struct HelperCredentials {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+impl AuthConfig {
+    fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Unable to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AuthConfig::default()),
+            Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+        }
+    }
+
+    /// Returns the entry for the registry, matching keys with or without a scheme or path
+    fn entry(&self, registry: &str) -> Option<(&String, &AuthEntry)> {
+        self.auths
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+    }
+
+    fn helper(&self, registry: &str) -> Option<&String> {
+        self.cred_helpers
+            .iter()
+            .find(|(key, _)| normalize_registry(key) == registry)
+            .map(|(_, helper)| helper)
+    }
+}
+
+/// Stores registry credentials in a docker style configuration file, `~/.wash/credentials.json`
+/// by default
+pub struct CredentialStore {
+    path: PathBuf,
+}
+
+impl CredentialStore {
+    /// Opens the credential store at the given path. The file is created when credentials are
+    /// first saved
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        CredentialStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Opens the default credential store in the wash configuration directory
+    pub fn default_store() -> Result<Self> {
+        Ok(Self::new(cfg_dir()?.join(CREDENTIALS_FILE)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the stored credentials for the registry, if any
+    pub fn get(&self, registry: &str) -> Result<Option<Credentials>> {
+        let registry = normalize_registry(registry);
+        Ok(AuthConfig::load(&self.path)?
+            .entry(&registry)
+            .and_then(|(_, entry)| entry.credentials()))
+    }
+
+    /// Saves credentials for the registry, replacing any existing ones
+    pub fn save(&self, registry: &str, credentials: &Credentials) -> Result<()> {
+        let registry = normalize_registry(registry);
+        let _lock = self.lock()?;
+        let mut config = AuthConfig::load(&self.path)?;
+        config
+            .auths
+            .retain(|key, _| normalize_registry(key) != registry);
+        config.auths.insert(
+            registry,
+            AuthEntry {
+                auth: Some(data_encoding::BASE64.encode(
+                    format!("{}:{}", credentials.username, credentials.password).as_bytes(),
+                )),
+                ..Default::default()
+            },
+        );
+        self.write(&config)
+    }
+
+    /// Removes the credentials for the registry, returning whether there were any
+    pub fn remove(&self, registry: &str) -> Result<bool> {
+        let registry = normalize_registry(registry);
+        let _lock = self.lock()?;
+        let mut config = AuthConfig::load(&self.path)?;
+        let before = config.auths.len();
+        config
+            .auths
+            .retain(|key, _| normalize_registry(key) != registry);
+        if config.auths.len() == before {
+            return Ok(false);
+        }
+        self.write(&config)?;
+        Ok(true)
+    }
+
+    /// Lists the registries that have stored credentials
+    pub fn registries(&self) -> Result<Vec<String>> {
+        Ok(AuthConfig::load(&self.path)?.auths.into_keys().collect())
+    }
+
+    /// Locks the store, so concurrent processes saving or removing credentials don't overwrite
+    /// each other's changes
+    fn lock(&self) -> Result<FileLock> {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        FileLock::acquire_blocking(lock_path)
+    }
+
+    /// Writes the store to a temporary file that only the current user can read and renames it
+    /// over the store, so secrets are never readable by others, even briefly
+    fn write(&self, config: &AuthConfig) -> Result<()> {
+        let write = || -> Result<()> {
+            let parent = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            std::fs::create_dir_all(parent)?;
+            // Temporary files are created readable and writable only by the current user
+            let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+            tmp.write_all(&serde_json::to_vec_pretty(config)?)?;
+            tmp.as_file().sync_all()?;
+            tmp.persist(&self.path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("Unable to write {}", self.path.display()))
+    }
+}
+
+/// Returns the path of the docker configuration file, honoring `DOCKER_CONFIG`
+pub fn docker_config_path() -> Option<PathBuf> {
+    match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir).join("config.json")),
+        None => dirs::home_dir().map(|home| home.join(".docker").join("config.json")),
+    }
+}
+
+/// Finds credentials for the registry, first in the wash credential store and then in the
+/// docker configuration: a registry specific credential helper, the `auths` section and finally
+/// the default credential store (`credsStore`). Returns `None` if there are no credentials, in
+/// which case anonymous authentication should be used
+pub async fn resolve_credentials(registry: &str) -> Result<Option<Credentials>> {
+    if let Some(credentials) = CredentialStore::default_store()?.get(registry)? {
+        return Ok(Some(credentials));
+    }
+    match docker_config_path() {
+        Some(path) => resolve_docker_credentials(&path, registry).await,
+        None => Ok(None),
+    }
+}
+
+async fn resolve_docker_credentials(path: &Path, registry: &str) -> Result<Option<Credentials>> {
+    let registry = normalize_registry(registry);
+    let config = AuthConfig::load(path)?;
+    let server = match config.entry(&registry) {
+        Some((key, _)) => key.clone(),
+        None if registry == DOCKER_HUB => DOCKER_HUB_KEY.to_string(),
+        None => registry.clone(),
+    };
+    if let Some(helper) = config.helper(&registry) {
+        return credential_helper(helper, &server).await;
+    }
+    if let Some(credentials) = config
+        .entry(&registry)
+        .and_then(|(_, entry)| entry.credentials())
+    {
+        return Ok(Some(credentials));
+    }
+    match &config.creds_store {
+        Some(helper) => credential_helper(helper, &server).await,
+        None => Ok(None),
+    }
+}
+
+/// Runs `docker-credential-<helper> get` for the server. Helpers exit with an error when they
+/// have no credentials for the server, which is not treated as an error
+async fn credential_helper(helper: &str, server: &str) -> Result<Option<Credentials>> {
+    let program = format!("docker-credential-{helper}");
+    let mut child = match tokio::process::Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::warn!("Docker credential helper {program} was not found, ignoring it");
+            return Ok(None);
+        }
+        Err(e) => return Err(e).with_context(|| format!("Unable to run {program}")),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        log::debug!(
+            "{program} has no credentials for {server}: {}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+        return Ok(None);
+    }
+    let creds: HelperCredentials = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Unable to parse the output of {program}"))?;
+    if creds.username == "<token>" {
+        // Identity tokens have to be exchanged for a registry token using OAuth2, which the OCI
+        // client doesn't support
+        bail!("{program} returned an identity token for {server}, which is not supported");
+    }
+    Ok(Some(Credentials {
+        username: creds.username,
+        password: creds.secret,
+    }))
+}
+
+/// Normalizes a registry name or docker configuration key, removing any scheme and path and
+/// mapping the Docker Hub aliases to `docker.io`
+pub fn normalize_registry(registry: &str) -> String {
+    let registry = registry
+        .trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let registry = registry.split('/').next().unwrap_or(registry);
+    match registry {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => {
+            DOCKER_HUB.to_string()
+        }
+        registry => registry.to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_credential_store() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = CredentialStore::new(tempdir.path().join("credentials.json"));
+        assert_eq!(store.get("ghcr.io").unwrap(), None);
+
+        let credentials = Credentials {
+            username: "user".to_string(),
+            password: "pass:word".to_string(),
+        };
+        store.save("https://ghcr.io", &credentials).unwrap();
+        assert_eq!(store.get("ghcr.io").unwrap(), Some(credentials));
+        assert_eq!(store.registries().unwrap(), vec!["ghcr.io".to_string()]);
+        assert!(!tempdir.path().join("credentials.json.lock").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(store.remove("ghcr.io").unwrap());
+        assert!(!store.remove("ghcr.io").unwrap());
+        assert_eq!(store.get("ghcr.io").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_docker_config() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "aHViOnNlY3JldA=="},
+                    "localhost:5000": {"username": "local", "password": "pass"}
+                },
+                "credHelpers": {"example.azurecr.io": "wash-test-missing-helper"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_docker_credentials(&path, "docker.io")
+                .await
+                .unwrap(),
+            Some(Credentials {
+                username: "hub".to_string(),
+                password: "secret".to_string(),
+            })
+        );
+        assert_eq!(
+            resolve_docker_credentials(&path, "localhost:5000")
+                .await
+                .unwrap()
+                .unwrap()
+                .username,
+            "local"
+        );
+        // Missing helpers are ignored
+        assert_eq!(
+            resolve_docker_credentials(&path, "example.azurecr.io")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            resolve_docker_credentials(&path, "ghcr.io").await.unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_normalize_registry() {
+        assert_eq!(
+            normalize_registry("https://index.docker.io/v1/"),
+            "docker.io"
+        );
+        assert_eq!(normalize_registry("GHCR.io"), "ghcr.io");
+        assert_eq!(normalize_registry("localhost:5000"), "localhost:5000");
+    }
+}
@@ -0,0 +1,546 @@
+//! Offline bundles of OCI artifacts in the [OCI image
+//! layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md) format.
+//!
+//! A bundle is a directory (or a tarball of one) holding an `oci-layout` marker file, an
+//! `index.json` listing the manifests of every artifact along with the reference it was exported
+//! from, and the manifests, configs and layers themselves under `blobs/sha256`. Bundles can be
+//! pushed into a registry that hosts can reach, such as a local registry on a network without
+//! access to the original registries.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use async_compression::tokio::{bufread::GzipDecoder, write::GzipEncoder};
+use oci_distribution::{
+    client::{Config, ImageLayer},
+    manifest::{
+        ImageIndexEntry, OciDescriptor, OciImageIndex, OciImageManifest, OciManifest,
+        OCI_IMAGE_INDEX_MEDIA_TYPE, OCI_IMAGE_MEDIA_TYPE,
+    },
+    Reference, RegistryOperation,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+};
+use tokio_tar::{Archive, Builder, HeaderMode};
+
+use super::{
+    explicit_tag, oci_client, pull_verified_blob, resolve_auth, OciRegistryOptions, RegistryApi,
+};
+
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+const OCI_LAYOUT_VERSION: &str = "1.0.0";
+const INDEX_FILE: &str = "index.json";
+const BLOBS_DIR: &str = "blobs";
+
+/// Annotation holding the tag of an artifact, as defined by the OCI image spec
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+/// Annotation holding the full reference an artifact was exported from. This is the same
+/// annotation containerd uses, so bundles can also be imported with its tooling
+pub const IMAGE_NAME_ANNOTATION: &str = "io.containerd.image.name";
+
+/// Additional options for exporting OCI artifacts to a bundle
+#[derive(Default)]
+pub struct OciExportOptions {
+    /// By default, we do not allow latest tags in wasmCloud. This overrides that setting
+    pub allow_latest: bool,
+    /// Options for the registry the artifacts are pulled from
+    pub registry: OciRegistryOptions,
+}
+
+/// An artifact that was exported to or imported from a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutImage {
+    /// The reference of the artifact. When importing into a registry, this is the reference it
+    /// was pushed to
+    pub reference: String,
+    /// Digest of the artifact's manifest in the bundle
+    pub digest: String,
+    /// Total size of the artifact's config and layers in bytes
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LayoutMarker {
+    image_layout_version: String,
+}
+
+/// Pulls every reference and writes them to a bundle at `destination`. If the destination ends
+/// with `.tar`, `.tar.gz` or `.tgz`, the bundle is written as a (gzipped) tarball, otherwise as a
+/// directory. The destination must not exist yet
+pub async fn export_oci_layout(
+    references: &[String],
+    destination: impl AsRef<Path>,
+    options: OciExportOptions,
+) -> Result<Vec<LayoutImage>> {
+    let destination = destination.as_ref();
+    if tokio::fs::metadata(destination).await.is_ok() {
+        bail!("{} already exists", destination.display());
+    }
+    let mut images = Vec::with_capacity(references.len());
+    let format = TarballFormat::from_path(destination);
+    let tempdir = match format {
+        Some(_) => Some(tempfile::tempdir()?),
+        None => None,
+    };
+    let layout = OciLayout::create(
+        tempdir
+            .as_ref()
+            .map(|dir| dir.path())
+            .unwrap_or(destination),
+    )
+    .await?;
+
+    let mut entries = Vec::with_capacity(references.len());
+    for reference in references {
+        let (entry, image) = export_image(&layout, reference, &options).await?;
+        entries.push(entry);
+        images.push(image);
+    }
+    layout.write_index(entries).await?;
+
+    if let Some(format) = format {
+        layout.write_tarball(destination, format).await?;
+    }
+    Ok(images)
+}
+
+async fn export_image(
+    layout: &OciLayout,
+    reference: &str,
+    options: &OciExportOptions,
+) -> Result<(ImageIndexEntry, LayoutImage)> {
+    let image: Reference = reference.to_lowercase().parse()?;
+    let tag = explicit_tag(reference)?;
+    if !options.allow_latest {
+        match tag.as_deref() {
+            Some("latest") => bail!("Pulling artifacts with tag 'latest' is prohibited. This can be overriden with the flag '--allow-latest'."),
+            None if image.digest().is_none() => bail!("Registry URLs must have explicit tag. To default missing tags to 'latest', use the flag '--allow-latest'."),
+            _ => (),
+        }
+    }
+
+    // The manifest is kept exactly as the registry returned it, so the artifact keeps its digest
+    let (manifest_data, media_type) =
+        RegistryApi::with_credentials(image.registry(), options.registry.clone())
+            .await
+            .pull_manifest_raw(&image)
+            .await?;
+    let digest = sha256_digest(&manifest_data);
+    if matches!(image.digest(), Some(expected) if expected != digest) {
+        bail!("The manifest of {reference} did not match its digest (got {digest}), aborting");
+    }
+    let manifest = match serde_json::from_slice(&manifest_data)
+        .with_context(|| format!("Unable to parse the manifest of {reference}"))?
+    {
+        OciManifest::Image(manifest) => manifest,
+        OciManifest::ImageIndex(_) => {
+            bail!("{reference} is an image index, only single artifacts can be exported")
+        }
+    };
+
+    let mut client = oci_client(options.registry.insecure);
+    let auth = resolve_auth(
+        &image,
+        options.registry.user.clone(),
+        options.registry.password.clone(),
+    )
+    .await;
+    client.auth(&image, &auth, RegistryOperation::Pull).await?;
+    let mut size = 0;
+    for descriptor in std::iter::once(&manifest.config).chain(manifest.layers.iter()) {
+        let data = pull_verified_blob(&client, &image, descriptor).await?;
+        size += data.len() as u64;
+        layout.write_blob(&data).await?;
+    }
+    layout.write_blob(&manifest_data).await?;
+
+    let mut annotations = HashMap::from([(IMAGE_NAME_ANNOTATION.to_string(), image.whole())]);
+    if image.digest().is_none() {
+        annotations.insert(
+            REF_NAME_ANNOTATION.to_string(),
+            image.tag().unwrap_or("latest").to_string(),
+        );
+    }
+    let entry = ImageIndexEntry {
+        media_type: manifest
+            .media_type
+            .clone()
+            .or(media_type)
+            .unwrap_or_else(|| OCI_IMAGE_MEDIA_TYPE.to_string()),
+        digest: digest.clone(),
+        size: manifest_data.len() as i64,
+        platform: None,
+        annotations: Some(annotations),
+    };
+    Ok((
+        entry,
+        LayoutImage {
+            reference: image.whole(),
+            digest,
+            size,
+        },
+    ))
+}
+
+/// Pushes every artifact in the bundle at `source` to `registry`, keeping the repository and the
+/// tag or digest each artifact was exported from. Manifests are pushed exactly as they were
+/// exported, so artifacts keep their digests
+pub async fn push_oci_layout(
+    source: impl AsRef<Path>,
+    registry: &str,
+    options: OciRegistryOptions,
+) -> Result<Vec<LayoutImage>> {
+    let layout = OciLayout::open(source.as_ref()).await?;
+    let mut api = RegistryApi::with_credentials(registry, options).await;
+    let mut images = Vec::new();
+    for entry in layout.entries().await? {
+        let source_ref = layout_reference(&entry)?;
+        let image = match source_ref.tag() {
+            Some(tag) => Reference::with_tag(
+                registry.to_string(),
+                source_ref.repository().to_string(),
+                tag.to_string(),
+            ),
+            None => Reference::with_digest(
+                registry.to_string(),
+                source_ref.repository().to_string(),
+                entry.digest.clone(),
+            ),
+        };
+        let (_, config, layers) = layout.read_image(&entry).await?;
+        let mut size = 0;
+        for data in std::iter::once(&config.data).chain(layers.iter().map(|l| &l.data)) {
+            size += data.len() as u64;
+            api.push_blob(image.repository(), data).await?;
+        }
+        let manifest_data = layout.read_blob(&entry.digest).await?;
+        api.push_manifest_raw(&image, &manifest_data, &entry.media_type)
+            .await?;
+        images.push(LayoutImage {
+            reference: image.whole(),
+            digest: entry.digest,
+            size,
+        });
+    }
+    Ok(images)
+}
+
+/// Returns the reference an index entry was exported from
+fn layout_reference(entry: &ImageIndexEntry) -> Result<Reference> {
+    let annotations = entry.annotations.clone().unwrap_or_default();
+    match (
+        annotations.get(IMAGE_NAME_ANNOTATION),
+        annotations.get(REF_NAME_ANNOTATION),
+    ) {
+        (Some(name), _) => Ok(name.parse()?),
+        // Layouts written by other tools may only have the full reference in the ref name
+        (None, Some(name)) if name.contains('/') => Ok(name.parse()?),
+        _ => bail!(
+            "Manifest {} in the bundle has no image reference annotation",
+            entry.digest
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TarballFormat {
+    Tar,
+    TarGz,
+}
+
+impl TarballFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(TarballFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(TarballFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+struct OciLayout {
+    root: PathBuf,
+    /// Holds on to the directory a tarball was extracted into, so it is removed once the layout
+    /// is dropped
+    _extracted: Option<tempfile::TempDir>,
+}
+
+impl OciLayout {
+    async fn create(root: &Path) -> Result<Self> {
+        tokio::fs::create_dir_all(root.join(BLOBS_DIR).join("sha256")).await?;
+        let marker = LayoutMarker {
+            image_layout_version: OCI_LAYOUT_VERSION.to_string(),
+        };
+        tokio::fs::write(root.join(OCI_LAYOUT_FILE), serde_json::to_vec(&marker)?).await?;
+        Ok(OciLayout {
+            root: root.to_path_buf(),
+            _extracted: None,
+        })
+    }
+
+    /// Opens a layout directory, or extracts a (gzipped) tarball of one to a temporary directory
+    async fn open(path: &Path) -> Result<Self> {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .with_context(|| format!("Unable to open {}", path.display()))?;
+        let layout = if metadata.is_dir() {
+            OciLayout {
+                root: path.to_path_buf(),
+                _extracted: None,
+            }
+        } else {
+            let tempdir = tempfile::tempdir()?;
+            let mut file = File::open(path).await?;
+            let mut magic = [0u8; 2];
+            let gzipped = file.read_exact(&mut magic).await.is_ok() && magic == [0x1f, 0x8b];
+            let file = File::open(path).await?;
+            if gzipped {
+                unpack(GzipDecoder::new(BufReader::new(file)), tempdir.path()).await?;
+            } else {
+                unpack(file, tempdir.path()).await?;
+            }
+            OciLayout {
+                root: tempdir.path().to_path_buf(),
+                _extracted: Some(tempdir),
+            }
+        };
+
+        let marker: LayoutMarker = serde_json::from_slice(
+            &tokio::fs::read(layout.root.join(OCI_LAYOUT_FILE))
+                .await
+                .with_context(|| format!("{} is not an OCI image layout", path.display()))?,
+        )?;
+        if marker.image_layout_version != OCI_LAYOUT_VERSION {
+            bail!(
+                "Unsupported OCI image layout version {}",
+                marker.image_layout_version
+            );
+        }
+        Ok(layout)
+    }
+
+    /// Returns the manifests listed in the index
+    async fn entries(&self) -> Result<Vec<ImageIndexEntry>> {
+        let index: OciImageIndex =
+            serde_json::from_slice(&tokio::fs::read(self.root.join(INDEX_FILE)).await?)?;
+        index
+            .manifests
+            .into_iter()
+            .map(|entry| {
+                if entry.media_type == OCI_IMAGE_INDEX_MEDIA_TYPE {
+                    bail!("Nested image indexes in bundles are not supported");
+                }
+                Ok(entry)
+            })
+            .collect()
+    }
+
+    /// Reads the manifest of an index entry along with its config and layers
+    async fn read_image(
+        &self,
+        entry: &ImageIndexEntry,
+    ) -> Result<(OciImageManifest, Config, Vec<ImageLayer>)> {
+        let manifest: OciImageManifest =
+            serde_json::from_slice(&self.read_blob(&entry.digest).await?)?;
+        let config = Config {
+            data: self.read_descriptor(&manifest.config).await?,
+            media_type: manifest.config.media_type.clone(),
+            annotations: manifest.config.annotations.clone(),
+        };
+        let mut layers = Vec::with_capacity(manifest.layers.len());
+        for layer in manifest.layers.iter() {
+            layers.push(ImageLayer {
+                data: self.read_descriptor(layer).await?,
+                media_type: layer.media_type.clone(),
+                annotations: layer.annotations.clone(),
+            });
+        }
+        Ok((manifest, config, layers))
+    }
+
+    async fn read_descriptor(&self, descriptor: &OciDescriptor) -> Result<Vec<u8>> {
+        self.read_blob(&descriptor.digest).await
+    }
+
+    /// Reads a blob, failing if its content doesn't match its digest
+    async fn read_blob(&self, digest: &str) -> Result<Vec<u8>> {
+        let data = tokio::fs::read(self.blob_path(digest)?)
+            .await
+            .with_context(|| format!("Blob {digest} is missing from the bundle"))?;
+        if sha256_digest(&data) != digest {
+            bail!("Blob {digest} in the bundle did not match its digest, aborting");
+        }
+        Ok(data)
+    }
+
+    /// Writes a blob, returning its digest
+    async fn write_blob(&self, data: &[u8]) -> Result<String> {
+        let digest = sha256_digest(data);
+        tokio::fs::write(self.blob_path(&digest)?, data).await?;
+        Ok(digest)
+    }
+
+    async fn write_index(&self, manifests: Vec<ImageIndexEntry>) -> Result<()> {
+        let index = OciImageIndex {
+            schema_version: 2,
+            media_type: Some(OCI_IMAGE_INDEX_MEDIA_TYPE.to_string()),
+            manifests,
+            annotations: None,
+        };
+        tokio::fs::write(self.root.join(INDEX_FILE), serde_json::to_vec(&index)?).await?;
+        Ok(())
+    }
+
+    async fn write_tarball(&self, destination: &Path, format: TarballFormat) -> Result<()> {
+        let file = File::create(destination).await?;
+        match format {
+            TarballFormat::Tar => {
+                let mut file = self.append_to(Builder::new(file)).await?;
+                file.flush().await?;
+            }
+            TarballFormat::TarGz => {
+                let mut encoder = self.append_to(Builder::new(GzipEncoder::new(file))).await?;
+                encoder.shutdown().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn append_to<W: AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        mut builder: Builder<W>,
+    ) -> Result<W> {
+        builder.mode(HeaderMode::Deterministic);
+        builder
+            .append_path_with_name(self.root.join(OCI_LAYOUT_FILE), OCI_LAYOUT_FILE)
+            .await?;
+        builder
+            .append_path_with_name(self.root.join(INDEX_FILE), INDEX_FILE)
+            .await?;
+        builder
+            .append_dir_all(BLOBS_DIR, self.root.join(BLOBS_DIR))
+            .await?;
+        Ok(builder.into_inner().await?)
+    }
+
+    fn blob_path(&self, digest: &str) -> Result<PathBuf> {
+        match digest.split_once(':') {
+            Some(("sha256", hex))
+                if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Ok(self.root.join(BLOBS_DIR).join("sha256").join(hex))
+            }
+            _ => bail!("Unsupported blob digest {digest}"),
+        }
+    }
+}
+
+async fn unpack<R: AsyncRead + Unpin + Send + Sync>(reader: R, destination: &Path) -> Result<()> {
+    Archive::new(reader)
+        .unpack(destination)
+        .await
+        .context("Unable to extract the bundle")
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Writes a bundle with a single wasm artifact, like [`export_oci_layout`] would
+    async fn write_bundle(root: &Path, reference: &str, wasm: &[u8]) -> ImageIndexEntry {
+        let layout = OciLayout::create(root).await.unwrap();
+        let config = b"{}".to_vec();
+        let descriptor = |data: &[u8], media_type: &str| OciDescriptor {
+            media_type: media_type.to_string(),
+            digest: sha256_digest(data),
+            size: data.len() as i64,
+            ..Default::default()
+        };
+        let manifest = OciImageManifest {
+            config: descriptor(&config, super::super::WASM_CONFIG_MEDIA_TYPE),
+            layers: vec![descriptor(wasm, super::super::WASM_MEDIA_TYPE)],
+            ..Default::default()
+        };
+        layout.write_blob(&config).await.unwrap();
+        layout.write_blob(wasm).await.unwrap();
+        let manifest_data = serde_json::to_vec(&manifest).unwrap();
+        let entry = ImageIndexEntry {
+            media_type: OCI_IMAGE_MEDIA_TYPE.to_string(),
+            digest: layout.write_blob(&manifest_data).await.unwrap(),
+            size: manifest_data.len() as i64,
+            platform: None,
+            annotations: Some(HashMap::from([
+                (IMAGE_NAME_ANNOTATION.to_string(), reference.to_string()),
+                (REF_NAME_ANNOTATION.to_string(), "0.1.0".to_string()),
+            ])),
+        };
+        layout.write_index(vec![entry.clone()]).await.unwrap();
+        entry
+    }
+
+    #[tokio::test]
+    async fn test_layout_entries() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let entry = write_bundle(tempdir.path(), "localhost:5000/echo:0.1.0", b"\0asm").await;
+
+        let layout = OciLayout::open(tempdir.path()).await.unwrap();
+        let entries = layout.entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].digest, entry.digest);
+        assert_eq!(
+            layout_reference(&entries[0]).unwrap().whole(),
+            "localhost:5000/echo:0.1.0"
+        );
+        let (_, _, layers) = layout.read_image(&entries[0]).await.unwrap();
+        assert_eq!(layers[0].data, b"\0asm");
+        assert_eq!(layers[0].media_type, super::super::WASM_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_layout_tarball() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let bundle = tempdir.path().join("bundle");
+        write_bundle(&bundle, "localhost:5000/echo:0.1.0", b"\0asm").await;
+        let layout = OciLayout::open(&bundle).await.unwrap();
+
+        for name in ["bundle.tar", "bundle.tar.gz"] {
+            let tarball = tempdir.path().join(name);
+            let format = TarballFormat::from_path(&tarball).unwrap();
+            layout.write_tarball(&tarball, format).await.unwrap();
+
+            let extracted = OciLayout::open(&tarball).await.unwrap();
+            let entries = extracted.entries().await.unwrap();
+            assert_eq!(entries.len(), 1);
+            let (_, _, layers) = extracted.read_image(&entries[0]).await.unwrap();
+            assert_eq!(layers[0].data, b"\0asm");
+        }
+        assert_eq!(TarballFormat::from_path(&bundle), None);
+    }
+
+    #[tokio::test]
+    async fn test_layout_tampered_blob() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let entry = write_bundle(tempdir.path(), "localhost:5000/echo:0.1.0", b"\0asm").await;
+        let layout = OciLayout::open(tempdir.path()).await.unwrap();
+        tokio::fs::write(layout.blob_path(&sha256_digest(b"\0asm")).unwrap(), b"evil")
+            .await
+            .unwrap();
+        assert!(layout.read_image(&entry).await.is_err());
+        assert!(layout.blob_path("sha256:../../etc/passwd").is_err());
+    }
+}
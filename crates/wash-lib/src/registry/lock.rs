@@ -0,0 +1,79 @@
+//! Lock files that serialize read-modify-write cycles on files shared between wash processes
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+/// Age after which a lock is assumed to belong to a process that crashed
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Exclusive lock held while a shared file is loaded, modified and saved, so concurrent processes
+/// don't overwrite each other's changes. The lock is a file that only one process can create,
+/// removed when the lock is dropped
+pub(crate) struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Waits until the lock file at `path` can be created
+    pub(crate) async fn acquire(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        loop {
+            match Self::try_acquire(path)? {
+                Some(lock) => return Ok(lock),
+                None => tokio::time::sleep(LOCK_RETRY_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Same as [`FileLock::acquire`], blocking the current thread while waiting
+    pub(crate) fn acquire_blocking(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        loop {
+            match Self::try_acquire(path)? {
+                Some(lock) => return Ok(lock),
+                None => std::thread::sleep(LOCK_RETRY_INTERVAL),
+            }
+        }
+    }
+
+    /// Creates the lock file, returning `None` if another process holds the lock. A stale lock is
+    /// removed so the next attempt can take it
+    fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(_) => Ok(Some(FileLock {
+                path: path.to_path_buf(),
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let stale = std::fs::metadata(path)
+                    .ok()
+                    .and_then(|metadata| metadata.modified().ok())
+                    .and_then(|modified| modified.elapsed().ok())
+                    .map_or(false, |age| age > STALE_LOCK_AGE);
+                if stale {
+                    log::warn!("Removing stale lock {}", path.display());
+                    let _ = std::fs::remove_file(path);
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e).with_context(|| format!("Unable to lock {}", path.display())),
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,1120 @@
+//! Utilities for pulling and pushing artifacts to various registries
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use oci_distribution::manifest::{
+    OciDescriptor, OciImageManifest, OciManifest, OCI_IMAGE_INDEX_MEDIA_TYPE, OCI_IMAGE_MEDIA_TYPE,
+};
+use oci_distribution::{
+    client::{Client, ClientConfig, ClientProtocol, Config, ImageLayer},
+    secrets::RegistryAuth,
+    Reference,
+};
+use provider_archive::ProviderArchive;
+use regex::RegexBuilder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+pub mod bundle;
+pub mod cache;
+pub mod credentials;
+pub mod layout;
+mod lock;
+pub mod policy;
+
+use cache::OciCache;
+use credentials::{normalize_registry, resolve_credentials, Credentials};
+use policy::TrustPolicy;
+
+const PROVIDER_ARCHIVE_MEDIA_TYPE: &str = "application/vnd.wasmcloud.provider.archive.layer.v1+par";
+const PROVIDER_ARCHIVE_CONFIG_MEDIA_TYPE: &str =
+    "application/vnd.wasmcloud.provider.archive.config";
+const WASM_MEDIA_TYPE: &str = "application/vnd.module.wasm.content.layer.v1+wasm";
+const WASM_CONFIG_MEDIA_TYPE: &str = "application/vnd.wasmcloud.actor.archive.config";
+const OCI_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+/// Manifest media types accepted when fetching raw manifests
+const MANIFEST_MEDIA_TYPES: &[&str] = &[
+    OCI_IMAGE_MEDIA_TYPE,
+    OCI_IMAGE_INDEX_MEDIA_TYPE,
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+// straight up stolen from oci_distribution::Reference
+pub const REFERENCE_REGEXP: &str = r"^((?:(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9])(?:(?:\.(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9]))+)?(?::[0-9]+)?/)?[a-z0-9]+(?:(?:(?:[._]|__|[-]*)[a-z0-9]+)+)?(?:(?:/[a-z0-9]+(?:(?:(?:[._]|__|[-]*)[a-z0-9]+)+)?)+)?)(?::([\w][\w.-]{0,127}))?(?:@([A-Za-z][A-Za-z0-9]*(?:[-_+.][A-Za-z][A-Za-z0-9]*)*[:][[:xdigit:]]{32,}))?$";
+
+/// Additional options for pulling an OCI artifact
+#[derive(Default, Clone)]
+pub struct OciPullOptions {
+    /// The digest of the content you expect to receive. This is used for validation purposes only
+    pub digest: Option<String>,
+    /// By default, we do not allow latest tags in wasmCloud. This overrides that setting
+    pub allow_latest: bool,
+    /// An optional username to use for authentication.
+    pub user: Option<String>,
+    /// An optional password to use for authentication
+    pub password: Option<String>,
+    /// Whether or not to allow pulling from non-https registries
+    pub insecure: bool,
+    /// Claims an artifact must have to be accepted. Artifacts that don't satisfy the policy are
+    /// refused before they are returned or cached
+    pub trust_policy: TrustPolicy,
+}
+
+/// Additional options for pushing an OCI artifact
+#[derive(Default)]
+pub struct OciPushOptions {
+    /// A path to an optional OCI configuration
+    pub config: Option<PathBuf>,
+    /// By default, we do not allow latest tags in wasmCloud. This overrides that setting
+    pub allow_latest: bool,
+    /// An optional username to use for authentication.
+    pub user: Option<String>,
+    /// An optional password to use for authentication.
+    pub password: Option<String>,
+    /// Whether or not to allow pulling from non-https registries
+    pub insecure: bool,
+    /// Optional annotations you'd like to add to the pushed artifact
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+/// Connection options for a registry, used when listing its contents and for either side of a
+/// copy
+#[derive(Default, Clone)]
+pub struct OciRegistryOptions {
+    /// An optional username to use for authentication.
+    pub user: Option<String>,
+    /// An optional password to use for authentication.
+    pub password: Option<String>,
+    /// Whether or not to allow connecting to non-https registries
+    pub insecure: bool,
+}
+
+/// Additional options for copying an OCI artifact between registries
+#[derive(Default)]
+pub struct OciCopyOptions {
+    /// Additional tags to give the copied artifact in the destination repository
+    pub extra_tags: Vec<String>,
+    /// By default, we do not allow latest tags in wasmCloud. This overrides that setting
+    pub allow_latest: bool,
+    /// Options for the registry the artifact is copied from
+    pub source: OciRegistryOptions,
+    /// Options for the registry the artifact is copied to
+    pub destination: OciRegistryOptions,
+}
+
+/// The result of copying an OCI artifact
+#[derive(Debug)]
+pub struct OciCopyResult {
+    /// Digest of the source manifest
+    pub source_digest: String,
+    /// Digest of the manifest in the destination registry. This only differs from the source
+    /// digest if the registry stores the manifest with different formatting
+    pub destination_digest: String,
+    /// Every reference the artifact was pushed to
+    pub references: Vec<String>,
+}
+
+/// The manifest of an artifact, fetched without its layers
+#[derive(Debug)]
+pub struct OciManifestInfo {
+    /// Digest of the manifest
+    pub digest: String,
+    /// The image manifest or image index
+    pub manifest: OciManifest,
+    /// Contents of the config blob, if the manifest is an image manifest
+    pub config: Option<Vec<u8>>,
+}
+
+/// The types of artifacts that wash supports
+pub enum SupportedArtifacts {
+    /// A par.gz (i.e. parcheezy) file containing capability providers
+    Par,
+    /// WebAssembly modules
+    Wasm,
+}
+
+/// Attempts to return a local artifact, then a cached artifact (if a cache is given).
+///
+/// Cached artifacts for tags are only used if the tag still points at the same manifest in the
+/// registry, or if the registry can't be reached. Falls back to pulling from the registry, and
+/// caches what was pulled.
+pub async fn get_oci_artifact(
+    url_or_file: String,
+    cache: Option<&OciCache>,
+    options: OciPullOptions,
+) -> Result<Vec<u8>> {
+    if let Ok(mut local_artifact) = File::open(&url_or_file).await {
+        let mut buf = Vec::new();
+        local_artifact.read_to_end(&mut buf).await?;
+        return Ok(buf);
+    }
+    let Some(cache) = cache else {
+        return pull_oci_artifact(url_or_file, options).await;
+    };
+
+    let image: Reference = url_or_file.to_lowercase().parse()?;
+    let key = image.whole();
+    if let Some(entry) = cache.entry(&key).await? {
+        let expected = normalize_digest(options.digest.clone());
+        let fresh = if expected.is_some() && expected != entry.manifest_digest {
+            false
+        } else if image.digest().is_some() {
+            // References pinned to a digest never change
+            true
+        } else {
+            match fetch_manifest_digest(&image, &options).await {
+                Ok(current) => Some(current) == entry.manifest_digest,
+                Err(e) => {
+                    log::warn!("Unable to check {key} for updates, using the cached artifact: {e}");
+                    true
+                }
+            }
+        };
+        if fresh {
+            if let Some(data) = cache.get(&key).await? {
+                // The artifact may have been cached under a less strict policy
+                verify_trust(&key, &data, &options.trust_policy).await?;
+                return Ok(data);
+            }
+        }
+    }
+
+    let (data, manifest_digest) = pull_artifact(url_or_file, options).await?;
+    if let Err(e) = cache.put(&key, manifest_digest, &data).await {
+        log::warn!("Unable to cache {key}: {e}");
+    }
+    Ok(data)
+}
+
+/// Fetches the digest of the manifest the reference currently points at
+async fn fetch_manifest_digest(image: &Reference, options: &OciPullOptions) -> Result<String> {
+    let auth = resolve_auth(image, options.user.clone(), options.password.clone()).await;
+    Ok(oci_client(options.insecure)
+        .fetch_manifest_digest(image, &auth)
+        .await?)
+}
+
+/// Reformats a digest in case the sha256: prefix is left off
+fn normalize_digest(digest: Option<String>) -> Option<String> {
+    match digest {
+        Some(d) if d.starts_with("sha256:") => Some(d),
+        Some(d) => Some(format!("sha256:{d}")),
+        None => None,
+    }
+}
+
+/// Pull down the artifact from the given url and additional options
+pub async fn pull_oci_artifact(url: String, options: OciPullOptions) -> Result<Vec<u8>> {
+    Ok(pull_artifact(url, options).await?.0)
+}
+
+/// Pulls the artifact, returning it along with the digest of its manifest
+async fn pull_artifact(url: String, options: OciPullOptions) -> Result<(Vec<u8>, Option<String>)> {
+    let image: Reference = url.to_lowercase().parse()?;
+
+    // NOTE(ceejimus): the FromStr implementation for the oci_distribution "Reference"
+    // struct defaults the tag to "latest" if unspecified. Ideally, they would expose
+    // some method to check if our valid input string has a tag or not, but, alas ...
+    // earwax.
+    // They don't even make the regex string public, so I stole it. These lines shouldn't fail
+    // if the parsing for "image" doesn't. Unless of course the change it... what a pickle.
+    let re = RegexBuilder::new(REFERENCE_REGEXP)
+        .size_limit(10 * (1 << 21))
+        .build()?;
+    let input_tag = match re.captures(&url) {
+        Some(caps) => caps.get(2).map(|m| m.as_str().to_owned()),
+        None => bail!("Invalid OCI reference URL."),
+    }
+    .unwrap_or_default();
+
+    if !options.allow_latest {
+        if input_tag == "latest" {
+            bail!("Pulling artifacts with tag 'latest' is prohibited. This can be overriden with the flag '--allow-latest'.");
+        } else if input_tag.is_empty() {
+            bail!("Registry URLs must have explicit tag. To default missing tags to 'latest', use the flag '--allow-latest'.");
+        }
+    }
+
+    let mut client = oci_client(options.insecure);
+    let auth = resolve_auth(&image, options.user.clone(), options.password.clone()).await;
+    match client.pull_manifest(&image, &auth).await? {
+        (OciManifest::Image(manifest), digest) => {
+            let data = pull_image_artifact(&client, &image, &manifest, &digest, &options).await?;
+            Ok((data, Some(digest)))
+        }
+        (OciManifest::ImageIndex(_), _) => bail!(
+            "{} is an image index, not an actor or provider artifact",
+            image.whole()
+        ),
+    }
+}
+
+/// Pulls the layers of an actor or provider image whose manifest has already been fetched,
+/// checking the manifest against the expected digest and the artifact against the trust policy
+async fn pull_image_artifact(
+    client: &Client,
+    image: &Reference,
+    manifest: &OciImageManifest,
+    digest: &str,
+    options: &OciPullOptions,
+) -> Result<Vec<u8>> {
+    if let Some(expected) = normalize_digest(options.digest.clone()) {
+        if expected != digest {
+            bail!("Image digest did not match provided digest, aborting");
+        }
+    }
+    if manifest.layers.is_empty() {
+        bail!("{} has no layers", image.whole());
+    }
+    let mut data = Vec::new();
+    for layer in manifest.layers.iter() {
+        if ![PROVIDER_ARCHIVE_MEDIA_TYPE, WASM_MEDIA_TYPE, OCI_MEDIA_TYPE]
+            .contains(&layer.media_type.as_str())
+        {
+            bail!(
+                "{} has a layer of unsupported media type {}",
+                image.whole(),
+                layer.media_type
+            );
+        }
+        data.extend(pull_verified_blob(client, image, layer).await?);
+    }
+    verify_trust(&image.whole(), &data, &options.trust_policy).await?;
+    Ok(data)
+}
+
+async fn verify_trust(reference: &str, artifact: &[u8], policy: &TrustPolicy) -> Result<()> {
+    policy
+        .verify(artifact)
+        .await
+        .with_context(|| format!("{reference} was refused by the trust policy"))
+}
+
+/// Pushes the artifact, returning the digest of the pushed manifest
+pub async fn push_oci_artifact(
+    url: String,
+    artifact: impl AsRef<Path>,
+    options: OciPushOptions,
+) -> Result<String> {
+    let image: Reference = url.to_lowercase().parse()?;
+
+    if image.tag().unwrap() == "latest" && !options.allow_latest {
+        bail!("Pushing artifacts with tag 'latest' is prohibited");
+    };
+
+    let mut artifact_buf = vec![];
+    let mut f = File::open(artifact).await?;
+    f.read_to_end(&mut artifact_buf).await?;
+
+    let mut config_buf = vec![];
+    match options.config {
+        Some(config_file) => {
+            let mut f = File::open(config_file).await?;
+            f.read_to_end(&mut config_buf).await?;
+        }
+        None => {
+            // If no config provided, send blank config
+            config_buf = b"{}".to_vec();
+        }
+    };
+    let (layer, config) = artifact_image(artifact_buf, config_buf).await?;
+
+    let mut client = Client::new(ClientConfig {
+        protocol: if options.insecure {
+            ClientProtocol::Http
+        } else {
+            ClientProtocol::Https
+        },
+        ..Default::default()
+    });
+
+    let auth = resolve_auth(&image, options.user, options.password).await;
+
+    let descriptor = push_image(
+        &mut client,
+        &image,
+        &auth,
+        &layer,
+        config,
+        options.annotations,
+    )
+    .await?;
+    Ok(descriptor.digest)
+}
+
+/// Builds the layer and config for an actor module or provider archive, with the media types
+/// matching the kind of artifact
+async fn artifact_image(artifact: Vec<u8>, config: Vec<u8>) -> Result<(Vec<ImageLayer>, Config)> {
+    let (artifact_media_type, config_media_type) = match validate_artifact(&artifact).await? {
+        SupportedArtifacts::Wasm => (WASM_MEDIA_TYPE, WASM_CONFIG_MEDIA_TYPE),
+        SupportedArtifacts::Par => (
+            PROVIDER_ARCHIVE_MEDIA_TYPE,
+            PROVIDER_ARCHIVE_CONFIG_MEDIA_TYPE,
+        ),
+    };
+    let config = Config {
+        data: config,
+        media_type: config_media_type.to_string(),
+        annotations: None,
+    };
+    let layer = vec![ImageLayer {
+        data: artifact,
+        media_type: artifact_media_type.to_string(),
+        annotations: None,
+    }];
+    Ok((layer, config))
+}
+
+/// Pushes an image made of the layers and config, returning the descriptor of its manifest
+async fn push_image(
+    client: &mut Client,
+    image: &Reference,
+    auth: &RegistryAuth,
+    layers: &[ImageLayer],
+    config: Config,
+    annotations: Option<HashMap<String, String>>,
+) -> Result<OciDescriptor> {
+    let manifest = OciImageManifest::build(layers, &config, annotations);
+    // The client serializes the manifest canonically, so serializing it the same way gives the
+    // digest the registry stores it under
+    let body = canonical_json(&OciManifest::Image(manifest.clone()))?;
+    client
+        .push(image, layers, config, auth, Some(manifest))
+        .await?;
+    Ok(OciDescriptor {
+        media_type: OCI_IMAGE_MEDIA_TYPE.to_string(),
+        digest: format!("sha256:{:x}", Sha256::digest(&body)),
+        size: body.len() as i64,
+        ..Default::default()
+    })
+}
+
+/// Serializes the value with the canonical JSON formatter that the OCI client uses for manifests
+fn canonical_json<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut body, olpc_cjson::CanonicalFormatter::new());
+    value.serialize(&mut serializer)?;
+    Ok(body)
+}
+
+/// Returns the auth to use for the registry of the image. Explicit credentials are used if both a
+/// user and password are given, otherwise credentials stored by `wash reg login` or found in the
+/// docker configuration are used, falling back to anonymous authentication
+pub async fn resolve_auth(
+    image: &Reference,
+    user: Option<String>,
+    password: Option<String>,
+) -> RegistryAuth {
+    match resolve_basic_auth(image.registry(), user, password).await {
+        Some(Credentials { username, password }) => RegistryAuth::Basic(username, password),
+        None => RegistryAuth::Anonymous,
+    }
+}
+
+async fn resolve_basic_auth(
+    registry: &str,
+    user: Option<String>,
+    password: Option<String>,
+) -> Option<Credentials> {
+    if let (Some(username), Some(password)) = (user, password) {
+        return Some(Credentials { username, password });
+    }
+    resolve_credentials(registry).await.unwrap_or_else(|e| {
+        log::warn!(
+            "Unable to look up credentials for {registry}, using anonymous authentication: {e:#}"
+        );
+        None
+    })
+}
+
+/// Checks that the credentials are accepted by the registry, as `docker login` does
+pub async fn verify_credentials(
+    registry: &str,
+    credentials: &Credentials,
+    insecure: bool,
+) -> Result<()> {
+    let registry = registry_host(registry);
+    let mut api = RegistryApi::new(
+        &registry,
+        OciRegistryOptions {
+            user: Some(credentials.username.clone()),
+            password: Some(credentials.password.clone()),
+            insecure,
+        },
+    );
+    let url = format!("{}/v2/", api.base_url);
+    let resp = api.get(&url).await?;
+    if resp.status() != reqwest::StatusCode::UNAUTHORIZED {
+        return Ok(());
+    }
+    api.authorize(&resp, None).await?;
+    let resp = api.get(&url).await?;
+    if !resp.status().is_success() {
+        bail!("Registry rejected the credentials ({})", resp.status());
+    }
+    Ok(())
+}
+
+/// Returns the host to make API requests to for a registry name
+fn registry_host(registry: &str) -> String {
+    match normalize_registry(registry).as_str() {
+        "docker.io" => "index.docker.io".to_string(),
+        registry => registry.to_string(),
+    }
+}
+
+/// Lists the tags of the repository in the given reference. Any tag or digest in the reference is
+/// ignored. Tags are returned in the order the registry lists them
+pub async fn list_tags(url: &str, options: OciRegistryOptions) -> Result<Vec<String>> {
+    let image: Reference = url.to_lowercase().parse()?;
+    let pages: Vec<TagList> = RegistryApi::with_credentials(image.registry(), options)
+        .await
+        .list(
+            &format!("/v2/{}/tags/list", image.repository()),
+            &format!("repository:{}:pull", image.repository()),
+        )
+        .await?;
+    Ok(pages.into_iter().flat_map(|page| page.tags).collect())
+}
+
+/// Lists the repositories of a registry using the catalog API. Not every registry supports this,
+/// and some only list the repositories the user has access to
+pub async fn list_repositories(registry: &str, options: OciRegistryOptions) -> Result<Vec<String>> {
+    let pages: Vec<Catalog> = RegistryApi::with_credentials(registry, options)
+        .await
+        .list("/v2/_catalog", "registry:catalog:*")
+        .await?;
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.repositories)
+        .collect())
+}
+
+/// A page of the tags list API
+#[derive(Debug, Deserialize)]
+struct TagList {
+    #[serde(default, deserialize_with = "null_as_empty")]
+    tags: Vec<String>,
+}
+
+/// A page of the catalog API
+#[derive(Debug, Deserialize)]
+struct Catalog {
+    #[serde(default, deserialize_with = "null_as_empty")]
+    repositories: Vec<String>,
+}
+
+/// Registries return `null` instead of an empty list for repositories without tags
+fn null_as_empty<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    Ok(Option::<Vec<String>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Makes requests to the registry APIs that `oci_distribution` doesn't cover, such as paginated
+/// lists and manifests as raw bytes, authenticating with a bearer token when the registry asks
+/// for one
+struct RegistryApi {
+    client: reqwest::Client,
+    base_url: String,
+    options: OciRegistryOptions,
+    authorization: Option<Authorization>,
+}
+
+/// Credentials sent with every request once the registry has challenged us
+enum Authorization {
+    Basic(String, String),
+    Bearer(String),
+}
+
+impl RegistryApi {
+    fn new(registry: &str, options: OciRegistryOptions) -> Self {
+        let scheme = if options.insecure { "http" } else { "https" };
+        RegistryApi {
+            client: reqwest::Client::new(),
+            base_url: format!("{scheme}://{registry}"),
+            options,
+            authorization: None,
+        }
+    }
+
+    /// Creates a client for the registry, using stored credentials if none are given
+    async fn with_credentials(registry: &str, options: OciRegistryOptions) -> Self {
+        let credentials =
+            resolve_basic_auth(registry, options.user.clone(), options.password.clone()).await;
+        RegistryApi::new(
+            &registry_host(registry),
+            OciRegistryOptions {
+                user: credentials.as_ref().map(|c| c.username.clone()),
+                password: credentials.map(|c| c.password),
+                ..options
+            },
+        )
+    }
+
+    /// Fetches every page of the list at `path`, following the `Link` headers of the responses
+    async fn list<T: serde::de::DeserializeOwned>(
+        &mut self,
+        path: &str,
+        scope: &str,
+    ) -> Result<Vec<T>> {
+        let mut pages = Vec::new();
+        let mut next = Some(format!("{}{path}", self.base_url));
+        while let Some(url) = next.take() {
+            let resp =
+                check_status(self.send(scope, |client| client.get(&url)).await?, &url).await?;
+            next = resp
+                .headers()
+                .get(reqwest::header::LINK)
+                .and_then(|link| link.to_str().ok())
+                .and_then(next_link)
+                .map(|link| {
+                    if link.starts_with('/') {
+                        format!("{}{link}", self.base_url)
+                    } else {
+                        link
+                    }
+                });
+            pages.push(resp.json().await?);
+        }
+        Ok(pages)
+    }
+
+    /// Fetches the manifest of the image exactly as the registry stores it, along with its media
+    /// type. Parsed manifests can't be used where digests matter, since serializing them again
+    /// may not give the same bytes
+    async fn pull_manifest_raw(&mut self, image: &Reference) -> Result<(Vec<u8>, Option<String>)> {
+        let url = self.manifest_url(image);
+        let scope = format!("repository:{}:pull", image.repository());
+        let resp = self
+            .send(&scope, |client| {
+                client
+                    .get(&url)
+                    .header(reqwest::header::ACCEPT, MANIFEST_MEDIA_TYPES.join(", "))
+            })
+            .await?;
+        let resp = check_status(resp, &url).await?;
+        let media_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok((resp.bytes().await?.to_vec(), media_type))
+    }
+
+    /// Uploads the blob to the repository in a single request, unless the repository has it
+    async fn push_blob(&mut self, repository: &str, data: &[u8]) -> Result<()> {
+        let scope = format!("repository:{repository}:pull,push");
+        let digest = format!("sha256:{:x}", Sha256::digest(data));
+        let url = format!("{}/v2/{repository}/blobs/{digest}", self.base_url);
+        if self
+            .send(&scope, |client| client.head(&url))
+            .await?
+            .status()
+            .is_success()
+        {
+            return Ok(());
+        }
+
+        let url = format!("{}/v2/{repository}/blobs/uploads/", self.base_url);
+        let resp = check_status(self.send(&scope, |client| client.post(&url)).await?, &url).await?;
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow!("Registry did not return an upload location for {url}"))?;
+        let mut url = if location.starts_with('/') {
+            format!("{}{location}", self.base_url)
+        } else {
+            location.to_string()
+        };
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str(&format!("digest={digest}"));
+        let resp = self
+            .send(&scope, |client| {
+                client
+                    .put(&url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .body(data.to_vec())
+            })
+            .await?;
+        check_status(resp, &url).await?;
+        Ok(())
+    }
+
+    /// Pushes the manifest bytes as they are, so the manifest keeps its digest
+    async fn push_manifest_raw(
+        &mut self,
+        image: &Reference,
+        data: &[u8],
+        media_type: &str,
+    ) -> Result<()> {
+        let url = self.manifest_url(image);
+        let scope = format!("repository:{}:pull,push", image.repository());
+        let resp = self
+            .send(&scope, |client| {
+                client
+                    .put(&url)
+                    .header(reqwest::header::CONTENT_TYPE, media_type)
+                    .body(data.to_vec())
+            })
+            .await?;
+        check_status(resp, &url).await?;
+        Ok(())
+    }
+
+    fn manifest_url(&self, image: &Reference) -> String {
+        format!(
+            "{}/v2/{}/manifests/{}",
+            self.base_url,
+            image.repository(),
+            image.digest().or_else(|| image.tag()).unwrap_or("latest")
+        )
+    }
+
+    /// Sends the request built by `build`, answering the registry's authentication challenge for
+    /// `scope` and sending the request again if the registry refuses it
+    async fn send(
+        &mut self,
+        scope: &str,
+        build: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let resp = self.authenticate(build(&self.client)).send().await?;
+        if resp.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+        self.authorize(&resp, Some(scope)).await?;
+        Ok(self.authenticate(build(&self.client)).send().await?)
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        Ok(self.authenticate(self.client.get(url)).send().await?)
+    }
+
+    fn authenticate(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.authorization {
+            Some(Authorization::Basic(user, password)) => req.basic_auth(user, Some(password)),
+            Some(Authorization::Bearer(token)) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    /// Answers the authentication challenge of an unauthorized response, using basic auth
+    /// directly or exchanging it for a bearer token
+    async fn authorize(&mut self, resp: &reqwest::Response, scope: Option<&str>) -> Result<()> {
+        let challenge = resp
+            .headers()
+            .get(reqwest::header::WWW_AUTHENTICATE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(parse_challenge)
+            .ok_or_else(|| anyhow!("Registry requires authentication, but sent no challenge"))?;
+        let basic = match (&self.options.user, &self.options.password) {
+            (Some(user), Some(password)) => Some((user.clone(), password.clone())),
+            _ => None,
+        };
+        match challenge {
+            AuthChallenge::Basic => {
+                let Some((user, password)) = basic else {
+                    bail!("Registry requires authentication, provide a user and password");
+                };
+                self.authorization = Some(Authorization::Basic(user, password));
+            }
+            AuthChallenge::Bearer { realm, service } => {
+                let mut query = Vec::new();
+                if let Some(scope) = scope {
+                    query.push(("scope", scope.to_string()));
+                }
+                if let Some(service) = service {
+                    query.push(("service", service));
+                }
+                let mut req = self.client.get(&realm).query(&query);
+                if let Some((user, password)) = basic {
+                    req = req.basic_auth(user, Some(password));
+                }
+                let resp = req.send().await?;
+                if !resp.status().is_success() {
+                    bail!(
+                        "Unable to authenticate with registry: {}",
+                        resp.text().await.unwrap_or_default()
+                    );
+                }
+                let token: TokenResponse = resp.json().await?;
+                let token = token
+                    .token
+                    .or(token.access_token)
+                    .ok_or_else(|| anyhow!("Registry did not return a token"))?;
+                self.authorization = Some(Authorization::Bearer(token));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns an unsuccessful registry response into an error that includes what the registry said
+async fn check_status(resp: reqwest::Response, url: &str) -> Result<reqwest::Response> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+    bail!(
+        "Registry returned {} for {url}: {}",
+        resp.status(),
+        resp.text().await.unwrap_or_default()
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AuthChallenge {
+    Basic,
+    Bearer {
+        realm: String,
+        service: Option<String>,
+    },
+}
+
+/// Parses a `WWW-Authenticate` header like `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`
+fn parse_challenge(header: &str) -> Option<AuthChallenge> {
+    let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if scheme.eq_ignore_ascii_case("basic") {
+        return Some(AuthChallenge::Basic);
+    }
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let params = params
+        .split(',')
+        .filter_map(|param| param.trim().split_once('='))
+        .map(|(key, value)| (key.to_lowercase(), value.trim_matches('"').to_string()))
+        .collect::<HashMap<_, _>>();
+    Some(AuthChallenge::Bearer {
+        realm: params.get("realm")?.to_owned(),
+        service: params.get("service").cloned(),
+    })
+}
+
+/// Returns the URL of the next page from a `Link` header like `</v2/_catalog?last=b&n=2>; rel="next"`
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim().replace(' ', "") == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+/// Copies an artifact from one reference to another, possibly in a different registry. The
+/// manifest is pushed unchanged, so the config media type (such as [`WASM_CONFIG_MEDIA_TYPE`] or
+/// [`PROVIDER_ARCHIVE_CONFIG_MEDIA_TYPE`]), layer media types and annotations are kept. Every blob
+/// is checked against its digest before it is pushed
+pub async fn copy_oci_artifact(
+    source: &str,
+    destination: &str,
+    options: OciCopyOptions,
+) -> Result<OciCopyResult> {
+    let source_ref: Reference = source.to_lowercase().parse()?;
+    let destination_ref: Reference = destination.to_lowercase().parse()?;
+    if !options.allow_latest {
+        if source_ref.digest().is_none() && explicit_tag(source)?.is_none() {
+            bail!("Registry URLs must have explicit tag. To default missing tags to 'latest', use the flag '--allow-latest'.");
+        }
+        let tags = std::iter::once(destination_ref.tag().unwrap_or_default())
+            .chain(options.extra_tags.iter().map(String::as_str));
+        for tag in tags {
+            if tag == "latest" {
+                bail!("Pushing artifacts with tag 'latest' is prohibited. This can be overriden with the flag '--allow-latest'.");
+            }
+        }
+    }
+
+    let mut source_client = oci_client(options.source.insecure);
+    let source_auth = resolve_auth(&source_ref, options.source.user, options.source.password).await;
+    let (manifest, source_digest) = match source_client
+        .pull_manifest(&source_ref, &source_auth)
+        .await?
+    {
+        (OciManifest::Image(manifest), digest) => (manifest, digest),
+        (OciManifest::ImageIndex(_), _) => {
+            bail!("{source} is an image index, only single artifacts can be copied")
+        }
+    };
+
+    let config = Config {
+        data: pull_verified_blob(&source_client, &source_ref, &manifest.config).await?,
+        media_type: manifest.config.media_type.clone(),
+        annotations: manifest.config.annotations.clone(),
+    };
+    let mut layers = Vec::with_capacity(manifest.layers.len());
+    for layer in manifest.layers.iter() {
+        layers.push(ImageLayer {
+            data: pull_verified_blob(&source_client, &source_ref, layer).await?,
+            media_type: layer.media_type.clone(),
+            annotations: layer.annotations.clone(),
+        });
+    }
+
+    let mut destination_client = oci_client(options.destination.insecure);
+    let destination_auth = resolve_auth(
+        &destination_ref,
+        options.destination.user,
+        options.destination.password,
+    )
+    .await;
+    let mut references = vec![destination_ref.clone()];
+    for tag in options.extra_tags {
+        references.push(Reference::with_tag(
+            destination_ref.registry().to_string(),
+            destination_ref.repository().to_string(),
+            tag,
+        ));
+    }
+    // The client has no way to tag an existing manifest, so the blobs are pushed for every tag
+    // (registries that already have a blob discard the upload)
+    for reference in references.iter() {
+        destination_client
+            .push(
+                reference,
+                &layers,
+                config.clone(),
+                &destination_auth,
+                Some(manifest.clone()),
+            )
+            .await?;
+    }
+    let destination_digest = destination_client
+        .fetch_manifest_digest(&destination_ref, &destination_auth)
+        .await?;
+
+    Ok(OciCopyResult {
+        source_digest,
+        destination_digest,
+        references: references.iter().map(Reference::whole).collect(),
+    })
+}
+
+/// Fetches the manifest of the reference and, for image manifests, its config blob. Layers are
+/// never downloaded
+pub async fn inspect_oci_manifest(
+    url: &str,
+    options: OciRegistryOptions,
+) -> Result<OciManifestInfo> {
+    let image: Reference = url.to_lowercase().parse()?;
+    let mut client = oci_client(options.insecure);
+    let auth = resolve_auth(&image, options.user, options.password).await;
+    let (manifest, digest) = client.pull_manifest(&image, &auth).await?;
+    let config = match &manifest {
+        OciManifest::Image(manifest) => {
+            Some(pull_verified_blob(&client, &image, &manifest.config).await?)
+        }
+        OciManifest::ImageIndex(_) => None,
+    };
+    Ok(OciManifestInfo {
+        digest,
+        manifest,
+        config,
+    })
+}
+
+/// Finds actor claims stored in an artifact config, either as the whole config or as a string
+/// field of a JSON config. Returns the raw JWT
+pub fn config_claims_jwt(config: &[u8]) -> Option<String> {
+    let is_actor_jwt = |jwt: &str| {
+        jwt.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && wascap::jwt::Claims::<wascap::jwt::Actor>::decode(jwt).is_ok()
+    };
+    let config = std::str::from_utf8(config).ok()?.trim();
+    if is_actor_jwt(config) {
+        return Some(config.to_string());
+    }
+    match serde_json::from_str::<serde_json::Value>(config).ok()? {
+        serde_json::Value::Object(fields) => fields
+            .values()
+            .filter_map(serde_json::Value::as_str)
+            .find(|value| is_actor_jwt(value))
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+fn oci_client(insecure: bool) -> Client {
+    Client::new(ClientConfig {
+        protocol: if insecure {
+            ClientProtocol::Http
+        } else {
+            ClientProtocol::Https
+        },
+        ..Default::default()
+    })
+}
+
+/// Pulls the blob for the descriptor, failing if its content doesn't match the digest
+async fn pull_verified_blob(
+    client: &Client,
+    image: &Reference,
+    descriptor: &OciDescriptor,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(descriptor.size.max(0) as usize);
+    client
+        .pull_blob(image, &descriptor.digest, &mut data)
+        .await?;
+    let digest = format!("sha256:{:x}", Sha256::digest(&data));
+    if digest != descriptor.digest {
+        bail!(
+            "Blob {} of {} did not match its digest (got {digest}), aborting",
+            descriptor.digest,
+            image.whole()
+        );
+    }
+    Ok(data)
+}
+
+/// Returns the tag written in the URL. [`Reference`] defaults missing tags to `latest`, so the tag
+/// has to be found with the reference regex instead
+fn explicit_tag(url: &str) -> Result<Option<String>> {
+    let re = RegexBuilder::new(REFERENCE_REGEXP)
+        .size_limit(10 * (1 << 21))
+        .build()?;
+    match re.captures(url) {
+        Some(caps) => Ok(caps.get(2).map(|m| m.as_str().to_owned())),
+        None => bail!("Invalid OCI reference URL."),
+    }
+}
+
+/// Helper function to determine artifact type and validate that it is
+/// a supported artifact type
+pub async fn validate_artifact(artifact: &[u8]) -> Result<SupportedArtifacts> {
+    match validate_actor_module(artifact) {
+        Ok(_) => Ok(SupportedArtifacts::Wasm),
+        Err(_) => match validate_provider_archive(artifact).await {
+            Ok(_) => Ok(SupportedArtifacts::Par),
+            Err(_) => bail!("Unsupported artifact type"),
+        },
+    }
+}
+
+/// Attempts to inspect the claims of an actor module
+/// Will fail without actor claims, or if the artifact is invalid
+fn validate_actor_module(artifact: &[u8]) -> Result<()> {
+    match wascap::wasm::extract_claims(artifact) {
+        Ok(Some(_token)) => Ok(()),
+        Ok(None) => bail!("No capabilities discovered in actor module"),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+/// Attempts to unpack a provider archive
+/// Will fail without claims or if the archive is invalid
+async fn validate_provider_archive(artifact: &[u8]) -> Result<()> {
+    match ProviderArchive::try_load(artifact).await {
+        Ok(_par) => Ok(()),
+        Err(e) => bail!("Invalid provider archive: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_challenge() {
+        assert_eq!(
+            parse_challenge(
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull""#
+            ),
+            Some(AuthChallenge::Bearer {
+                realm: "https://auth.docker.io/token".to_string(),
+                service: Some("registry.docker.io".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_challenge(r#"Basic realm="Registry Realm""#),
+            Some(AuthChallenge::Basic)
+        );
+        assert_eq!(parse_challenge("Negotiate"), None);
+    }
+
+    #[test]
+    fn test_explicit_tag() {
+        assert_eq!(
+            explicit_tag("wasmcloud.azurecr.io/echo:0.3.4").unwrap(),
+            Some("0.3.4".to_string())
+        );
+        assert_eq!(explicit_tag("wasmcloud.azurecr.io/echo").unwrap(), None);
+        assert_eq!(
+            explicit_tag(
+                "localhost:5000/echo@sha256:a17a163afa8447622055deb049587641a9e23243a6cc4411eb33bd4267214cf3"
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link(r#"</v2/_catalog?last=echo&n=100>; rel="next""#),
+            Some("/v2/_catalog?last=echo&n=100".to_string())
+        );
+        assert_eq!(next_link(r#"</v2/_catalog?n=100>; rel="prev""#), None);
+    }
+
+    #[test]
+    fn test_manifest_url() {
+        let api = RegistryApi::new("localhost:5000", OciRegistryOptions::default());
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let by_tag: Reference = "localhost:5000/wasmcloud/echo:0.1.0".parse().unwrap();
+        let by_digest: Reference = format!("localhost:5000/echo@{digest}").parse().unwrap();
+        assert_eq!(
+            api.manifest_url(&by_tag),
+            "https://localhost:5000/v2/wasmcloud/echo/manifests/0.1.0"
+        );
+        assert_eq!(
+            api.manifest_url(&by_digest),
+            format!("https://localhost:5000/v2/echo/manifests/{digest}")
+        );
+    }
+
+    #[test]
+    fn test_config_claims_jwt() {
+        let account = nkeys::KeyPair::new_account();
+        let module = nkeys::KeyPair::new_module();
+        let jwt = wascap::jwt::Claims::<wascap::jwt::Actor>::new(
+            "echo".to_string(),
+            account.public_key(),
+            module.public_key(),
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+        )
+        .encode(&account)
+        .unwrap();
+
+        assert_eq!(config_claims_jwt(jwt.as_bytes()), Some(jwt.clone()));
+        let config = serde_json::to_vec(&serde_json::json!({ "claims": jwt, "rev": 1 })).unwrap();
+        assert_eq!(config_claims_jwt(&config), Some(jwt));
+        assert_eq!(config_claims_jwt(b"{}"), None);
+        assert_eq!(config_claims_jwt(b"not json"), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Trust policies that decide which pulled artifacts are accepted, based on the claims embedded
+//! in actor modules and provider archives.
+
+use anyhow::{bail, Context, Result};
+use async_compression::tokio::bufread::GzipDecoder;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio_stream::StreamExt;
+use tokio_tar::Archive;
+use wascap::jwt::{validate_token, Actor, CapabilityProvider, Claims, WascapEntity};
+
+use super::{validate_artifact, SupportedArtifacts};
+
+const PAR_CLAIMS_FILE: &str = "claims.jwt";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Which artifacts to accept when pulling from a registry. An empty policy accepts every
+/// artifact. Otherwise artifacts must carry valid, signed claims that are neither expired nor
+/// not yet valid, in addition to the checks below
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustPolicy {
+    /// Public keys of the accounts trusted to sign artifacts. If empty, any issuer is trusted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_issuers: Vec<String>,
+    /// Tags that actors must be signed with. Provider claims don't carry tags, so this is not
+    /// checked for provider archives
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_tags: Vec<String>,
+}
+
+impl TrustPolicy {
+    /// Returns true if the policy accepts every artifact
+    pub fn is_empty(&self) -> bool {
+        self.trusted_issuers.is_empty() && self.required_tags.is_empty()
+    }
+
+    /// Validates the claims embedded in the actor module or provider archive against the policy
+    pub async fn verify(&self, artifact: &[u8]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        match validate_artifact(artifact).await? {
+            SupportedArtifacts::Wasm => {
+                let token = wascap::wasm::extract_claims(artifact)?
+                    .context("Actor is not signed, it has no embedded claims")?;
+                let claims = self.verify_token::<Actor>(&token.jwt)?;
+                let tags = claims.metadata.and_then(|md| md.tags).unwrap_or_default();
+                let missing = self
+                    .required_tags
+                    .iter()
+                    .filter(|tag| !tags.contains(tag))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    bail!("Actor is missing required tags: {}", missing.join(", "));
+                }
+            }
+            SupportedArtifacts::Par => {
+                let jwt = par_claims_jwt(artifact)
+                    .await?
+                    .context("Provider archive is not signed, it has no embedded claims")?;
+                self.verify_token::<CapabilityProvider>(&jwt)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_token<T>(&self, jwt: &str) -> Result<Claims<T>>
+    where
+        T: Serialize + serde::de::DeserializeOwned + WascapEntity,
+    {
+        let validation = validate_token::<T>(jwt)?;
+        if !validation.signature_valid {
+            bail!("Embedded claims have an invalid signature");
+        }
+        if validation.expired {
+            bail!("Embedded claims have expired");
+        }
+        if validation.cannot_use_yet {
+            bail!(
+                "Embedded claims are not valid until {}",
+                validation.not_before_human
+            );
+        }
+        let claims = Claims::<T>::decode(jwt)?;
+        if !self.trusted_issuers.is_empty() && !self.trusted_issuers.contains(&claims.issuer) {
+            bail!("Artifact was signed by untrusted issuer {}", claims.issuer);
+        }
+        Ok(claims)
+    }
+}
+
+/// Reads the raw claims JWT from a (possibly gzipped) provider archive. The provider archive
+/// crate only decodes the claims without checking their signature, so the token is read here to
+/// validate it
+async fn par_claims_jwt(artifact: &[u8]) -> Result<Option<String>> {
+    let reader: Box<dyn AsyncRead + Unpin + Send + Sync + '_> = if artifact.starts_with(&GZIP_MAGIC)
+    {
+        Box::new(GzipDecoder::new(BufReader::new(artifact)))
+    } else {
+        Box::new(artifact)
+    };
+    let mut archive = Archive::new(reader);
+    let mut entries = archive.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        if entry.path()?.file_name() == Some(PAR_CLAIMS_FILE.as_ref()) {
+            let mut jwt = String::new();
+            entry.read_to_string(&mut jwt).await?;
+            return Ok(Some(jwt.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nkeys::KeyPair;
+    use wascap::{jwt::Claims, wasm::embed_claims};
+
+    const EMPTY_MODULE: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+    fn signed_module(issuer: &KeyPair, tags: Vec<String>, expires: Option<u64>) -> Vec<u8> {
+        let subject = KeyPair::new_module();
+        let claims = Claims::<Actor>::with_dates(
+            "echo".to_string(),
+            issuer.public_key(),
+            subject.public_key(),
+            Some(vec![]),
+            Some(tags),
+            None,
+            expires,
+            false,
+            None,
+            None,
+            None,
+        );
+        embed_claims(&EMPTY_MODULE, &claims, issuer).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_trust_policy() {
+        let account = KeyPair::new_account();
+        let module = signed_module(&account, vec!["prod".to_string()], None);
+
+        // An empty policy accepts anything, even unsigned modules
+        let empty = TrustPolicy::default();
+        assert!(empty.verify(&EMPTY_MODULE).await.is_ok());
+
+        let policy = TrustPolicy {
+            trusted_issuers: vec![account.public_key()],
+            required_tags: vec!["prod".to_string()],
+        };
+        policy.verify(&module).await.unwrap();
+        assert!(policy.verify(&EMPTY_MODULE).await.is_err());
+
+        let untrusted = signed_module(&KeyPair::new_account(), vec!["prod".to_string()], None);
+        assert!(policy
+            .verify(&untrusted)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("untrusted issuer"));
+
+        let untagged = signed_module(&account, vec![], None);
+        assert!(policy
+            .verify(&untagged)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("prod"));
+
+        let expired = signed_module(&account, vec!["prod".to_string()], Some(1));
+        assert!(policy
+            .verify(&expired)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("expired"));
+    }
+}
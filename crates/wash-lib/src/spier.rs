@@ -1,9 +1,16 @@
-use std::{collections::HashMap, str::FromStr, task::Poll};
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+    task::Poll,
+    time::Duration,
+};
 
 use anyhow::Result;
 use chrono::{DateTime, Local};
 use futures::{Stream, StreamExt};
-use wasmbus_rpc::core::Invocation;
+use regex::Regex;
+use tokio::time::{Instant, Interval};
+use wasmbus_rpc::core::{Invocation, InvocationResponse, WasmCloudEntity};
 
 use crate::{
     common::{find_actor_id, CLAIMS_NAME, CLAIMS_SUBJECT},
@@ -25,6 +32,76 @@ pub struct ObservedInvocation {
     /// The inner message that was received. We will attempt to parse the inner message from CBOR
     /// and JSON into a JSON string and fall back to the raw bytes if we are unable to do so
     pub message: ObservedMessage,
+    /// The size of the inner message in bytes
+    pub payload_size: usize,
+    /// Whether the invocation succeeded, as reported by its response
+    pub status: InvocationStatus,
+    /// The inner message of the response, if a response was observed
+    pub response: Option<ObservedMessage>,
+    /// Time between observing the invocation and observing its response
+    pub latency: Option<Duration>,
+}
+
+/// The outcome of an observed invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvocationStatus {
+    /// A response without an error was observed
+    Success,
+    /// A response with the contained error message was observed
+    Error(String),
+    /// No response was observed before the spier's response timeout
+    TimedOut,
+    /// The invocation did not expect a response, or responses could not be observed (e.g. when
+    /// reading from a capture)
+    Unknown,
+}
+
+impl std::fmt::Display for InvocationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvocationStatus::Success => write!(f, "success"),
+            InvocationStatus::Error(e) => write!(f, "error: {e}"),
+            InvocationStatus::TimedOut => write!(f, "timed out"),
+            InvocationStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl InvocationStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            InvocationStatus::Success => "success",
+            InvocationStatus::Error(_) => "error",
+            InvocationStatus::TimedOut => "timed_out",
+            InvocationStatus::Unknown => "unknown",
+        }
+    }
+}
+
+impl ObservedInvocation {
+    /// Returns a JSON representation of the invocation, meant to be emitted as a single line for
+    /// log processing tools
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "id": self.invocation.id,
+            "from": self.from,
+            "to": self.to,
+            "origin": self.invocation.origin,
+            "target": self.invocation.target,
+            "operation": self.invocation.operation,
+            "host": self.invocation.host_id,
+            "payload_size": self.payload_size,
+            "message": self.message.to_json(),
+            "status": self.status.name(),
+            "error": match &self.status {
+                InvocationStatus::Error(e) => Some(e),
+                _ => None,
+            },
+            "response": self.response.as_ref().map(ObservedMessage::to_json),
+            "latency_ms": self.latency.map(|l| l.as_secs_f64() * 1000.0),
+        })
+    }
 }
 
 /// A inner message that we've seen in an invocation message. This will either be a raw bytes or a
@@ -50,6 +127,18 @@ impl std::fmt::Display for ObservedMessage {
 }
 
 impl ObservedMessage {
+    /// Returns the parsed message as a JSON value, or the raw bytes as a (lossy) string
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            ObservedMessage::Raw(bytes) => {
+                serde_json::Value::String(String::from_utf8_lossy(bytes).into_owned())
+            }
+            ObservedMessage::Parsed(v) => {
+                serde_json::from_str(v).unwrap_or_else(|_| serde_json::Value::String(v.to_owned()))
+            }
+        }
+    }
+
     pub fn parse(data: Vec<u8>) -> Self {
         // Try parsing with msgpack and then with cbor. If neither work, then just return the raw
         // NOTE(thomastaylor312): I don't think anyone else does their own encoding, but if that
@@ -77,12 +166,123 @@ impl ObservedMessage {
     }
 }
 
-/// A struct that can spy on the RPC messages sent to and from an actor, consumable as a stream
+/// Criteria used to select which invocations a [`Spier`] returns. Each list that is not empty
+/// must have at least one match for an invocation to be returned
+#[derive(Debug, Default, Clone)]
+pub struct SpyFilter {
+    /// Actor IDs or names, matched against the origin and target of the invocation
+    pub actors: Vec<String>,
+    /// Provider IDs or names, matched against the origin and target of the invocation
+    pub providers: Vec<String>,
+    /// Contract IDs, matched against the origin and target of the invocation
+    pub contract_ids: Vec<String>,
+    /// Operation name patterns, where `*` matches any number of characters (e.g. `HttpServer.*`)
+    pub operations: Vec<String>,
+    /// Minimum size of the invocation payload in bytes
+    pub min_payload_size: Option<usize>,
+    /// Maximum size of the invocation payload in bytes
+    pub max_payload_size: Option<usize>,
+}
+
+impl SpyFilter {
+    fn compile(&self) -> Result<CompiledFilter> {
+        let operations = self
+            .operations
+            .iter()
+            .map(|pattern| {
+                let pattern = pattern
+                    .split('*')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(".*");
+                Regex::new(&format!("^{pattern}$"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(CompiledFilter {
+            filter: self.clone(),
+            operations,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompiledFilter {
+    filter: SpyFilter,
+    operations: Vec<Regex>,
+}
+
+impl CompiledFilter {
+    fn matches(
+        &self,
+        inv: &Invocation,
+        payload_size: usize,
+        names: &HashMap<String, String>,
+    ) -> bool {
+        let entities = [&inv.origin, &inv.target];
+        let entity_matches = |values: &[String], entity: &WasmCloudEntity| {
+            let key = entity.public_key();
+            let name = names.get(&key);
+            values.iter().any(|value| {
+                value == &key || matches!(name, Some(name) if name.eq_ignore_ascii_case(value))
+            })
+        };
+        let SpyFilter {
+            actors,
+            providers,
+            contract_ids,
+            min_payload_size,
+            max_payload_size,
+            ..
+        } = &self.filter;
+        (actors.is_empty()
+            || entities
+                .iter()
+                .any(|e| e.is_actor() && entity_matches(actors, e)))
+            && (providers.is_empty()
+                || entities
+                    .iter()
+                    .any(|e| e.is_provider() && entity_matches(providers, e)))
+            && (contract_ids.is_empty()
+                || entities
+                    .iter()
+                    .any(|e| e.is_provider() && contract_ids.contains(&e.contract_id)))
+            && (self.operations.is_empty()
+                || self.operations.iter().any(|re| re.is_match(&inv.operation)))
+            && min_payload_size
+                .map(|min| payload_size >= min)
+                .unwrap_or(true)
+            && max_payload_size
+                .map(|max| payload_size <= max)
+                .unwrap_or(true)
+    }
+}
+
+/// How long to wait for the response to an invocation before reporting it as timed out
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A struct that can spy on the RPC messages sent to and from an actor, or on all RPC messages in
+/// a lattice, consumable as a stream.
+///
+/// Invocations are paired with their responses by listening on the reply inboxes, so an
+/// invocation is only returned once its response has been observed or the response timeout has
+/// passed. Reply inboxes don't carry the lattice or actor they belong to, so this means
+/// subscribing to `_INBOX.>`, which delivers every request/reply response on the NATS server to
+/// the spier, not just those of the lattice being observed. On a busy server that can be a lot of
+/// extra traffic; setting the response timeout to zero with [`Spier::set_response_timeout`] drops
+/// that subscription and returns invocations as soon as they arrive, without their responses
 pub struct Spier {
     stream: futures::stream::SelectAll<async_nats::Subscriber>,
-    actor_id: ModuleId,
-    friendly_name: Option<String>,
-    provider_info: HashMap<String, ProviderDetails>,
+    /// Subscription to the reply inboxes used for invocation responses, or `None` if responses
+    /// aren't being tracked
+    responses: Option<async_nats::Subscriber>,
+    /// The actor being spied on, or `None` when spying on the whole lattice
+    actor_id: Option<ModuleId>,
+    /// Friendly names from claims, keyed by public key
+    names: HashMap<String, String>,
+    filter: CompiledFilter,
+    responses_pending: PendingResponses,
+    expiry_check: Interval,
+    invocations_closed: bool,
 }
 
 impl Spier {
@@ -93,6 +293,23 @@ impl Spier {
         ctl_client: &wasmcloud_control_interface::Client,
         nats_client: &async_nats::Client,
     ) -> Result<Self> {
+        Self::new_with_filter(
+            actor_id_or_name,
+            SpyFilter::default(),
+            ctl_client,
+            nats_client,
+        )
+        .await
+    }
+
+    /// Same as [`Spier::new`], but only returns invocations that match the given filter
+    pub async fn new_with_filter(
+        actor_id_or_name: &str,
+        filter: SpyFilter,
+        ctl_client: &wasmcloud_control_interface::Client,
+        nats_client: &async_nats::Client,
+    ) -> Result<Self> {
+        let filter = filter.compile()?;
         let (actor_id, friendly_name) = find_actor_id(actor_id_or_name, ctl_client).await?;
         let linked_providers = get_linked_providers(&actor_id, ctl_client).await?;
 
@@ -116,23 +333,221 @@ impl Spier {
         subs.push(actor_stream);
 
         let stream = futures::stream::select_all(subs);
+        let responses = subscribe_responses(nats_client).await?;
 
-        Ok(Self {
+        let mut names: HashMap<String, String> = linked_providers
+            .into_iter()
+            .filter_map(|prov| Some((prov.id.into_string(), prov.friendly_name?)))
+            .collect();
+        if let Some(name) = friendly_name {
+            names.insert(actor_id.to_string(), name);
+        }
+
+        Ok(Self::from_parts(
             stream,
+            responses,
+            Some(actor_id),
+            names,
+            filter,
+        ))
+    }
+
+    /// Creates a new Spier instance that observes every RPC message in the lattice, returning the
+    /// ones that match the given filter. Origins and targets are named using the claims known to
+    /// the lattice when the spier is created
+    pub async fn new_lattice(
+        filter: SpyFilter,
+        ctl_client: &wasmcloud_control_interface::Client,
+        nats_client: &async_nats::Client,
+    ) -> Result<Self> {
+        let filter = filter.compile()?;
+        let names = get_claim_names(ctl_client).await?;
+        let stream = nats_client
+            .subscribe(format!("wasmbus.rpc.{}.>", ctl_client.lattice_prefix))
+            .await?;
+        let responses = subscribe_responses(nats_client).await?;
+
+        Ok(Self::from_parts(
+            futures::stream::select_all([stream]),
+            responses,
+            None,
+            names,
+            filter,
+        ))
+    }
+
+    fn from_parts(
+        stream: futures::stream::SelectAll<async_nats::Subscriber>,
+        responses: async_nats::Subscriber,
+        actor_id: Option<ModuleId>,
+        names: HashMap<String, String>,
+        filter: CompiledFilter,
+    ) -> Self {
+        Self {
+            stream,
+            responses: Some(responses),
             actor_id,
-            friendly_name,
-            provider_info: linked_providers
-                .into_iter()
-                .map(|prov| (prov.id.clone().into_string(), prov))
-                .collect(),
+            names,
+            filter,
+            responses_pending: PendingResponses::new(DEFAULT_RESPONSE_TIMEOUT),
+            expiry_check: tokio::time::interval(Duration::from_millis(250)),
+            invocations_closed: false,
+        }
+    }
+
+    /// Sets how long to wait for the response to an invocation before returning it as timed out.
+    /// Defaults to [`DEFAULT_RESPONSE_TIMEOUT`]. A zero timeout stops tracking responses
+    /// altogether: the `_INBOX.>` subscription is dropped and invocations are returned as soon as
+    /// they are observed, with an unknown status
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.responses_pending.timeout = timeout;
+        if timeout.is_zero() {
+            self.responses = None;
+            self.responses_pending.release_all();
+        }
+    }
+
+    /// Returns the actor name, or id if no name is set, that this spier is spying on. Returns
+    /// `None` if the spier is observing the whole lattice
+    pub fn actor_id(&self) -> Option<&str> {
+        self.actor_id.as_ref().map(|id| {
+            self.names
+                .get(id.as_ref())
+                .map(String::as_str)
+                .unwrap_or_else(|| id.as_ref())
         })
     }
 
-    /// Returns the actor name, or id if no name is set, that this spier is spying on
-    pub fn actor_id(&self) -> &str {
-        self.friendly_name
-            .as_deref()
-            .unwrap_or_else(|| self.actor_id.as_ref())
+    fn friendly_name(&self, entity: &WasmCloudEntity) -> String {
+        let pubkey = entity.public_key();
+        self.names.get(&pubkey).cloned().unwrap_or(pubkey)
+    }
+}
+
+impl Spier {
+    /// Parses and filters an invocation, queueing it as pending if it expects a response
+    fn handle_invocation(&mut self, msg: async_nats::Message) {
+        // Try to parse the invocation first
+        let mut inv: Invocation = match rmp_serde::from_slice(&msg.payload) {
+            Ok(inv) => inv,
+            // TODO: We should probably have some logging here
+            // Just skip it if we can't parse it
+            Err(_e) => return,
+        };
+        let body = inv.msg;
+        inv.msg = Vec::new();
+
+        if let Some(actor_id) = &self.actor_id {
+            if inv.origin.is_provider() && inv.target.public_key() != actor_id.as_ref() {
+                // This is a provider invocation that isn't for us, so we should skip it
+                return;
+            }
+        }
+        // Large payloads are sent out of band, in which case the length is set but the
+        // body is empty
+        let payload_size = inv
+            .content_length
+            .map(|len| len as usize)
+            .unwrap_or(body.len());
+        if !self.filter.matches(&inv, payload_size, &self.names) {
+            return;
+        }
+        let from = self.friendly_name(&inv.origin);
+        let to = self.friendly_name(&inv.target);
+        // NOTE(thomastaylor312): Ideally we'd consume `msg.payload` above with a
+        // `Cursor` and `from_reader` and then manually reconstruct the acking using the
+        // message context, but I didn't want to waste time optimizing yet
+        let observed = ObservedInvocation {
+            invocation: inv,
+            timestamp: Local::now(),
+            from,
+            to,
+            message: ObservedMessage::parse(body),
+            payload_size,
+            status: InvocationStatus::Unknown,
+            response: None,
+            latency: None,
+        };
+        let reply = msg.reply.filter(|_| self.responses.is_some());
+        self.responses_pending.observe(observed, reply);
+    }
+}
+
+/// Pairs observed invocations with their responses, keyed by the reply subject of the invocation
+#[derive(Debug)]
+struct PendingResponses {
+    /// Invocations waiting for a response, keyed by reply subject
+    pending: HashMap<String, (ObservedInvocation, Instant)>,
+    /// Invocations ready to be returned from the stream
+    ready: VecDeque<ObservedInvocation>,
+    timeout: Duration,
+}
+
+impl PendingResponses {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            ready: VecDeque::new(),
+            timeout,
+        }
+    }
+
+    /// Queues an invocation as waiting for a response on `reply`, or as ready if it doesn't
+    /// expect a response
+    fn observe(&mut self, observed: ObservedInvocation, reply: Option<String>) {
+        match reply {
+            Some(reply) => {
+                self.pending.insert(reply, (observed, Instant::now()));
+            }
+            None => self.ready.push_back(observed),
+        }
+    }
+
+    /// Completes the pending invocation this message is a response to, if any
+    fn respond(&mut self, subject: &str, payload: &[u8]) {
+        let Some((mut observed, started)) = self.pending.remove(subject) else {
+            return;
+        };
+        observed.latency = Some(started.elapsed());
+        match rmp_serde::from_slice::<InvocationResponse>(payload) {
+            Ok(resp) => {
+                observed.status = match resp.error {
+                    Some(e) if !e.is_empty() => InvocationStatus::Error(e),
+                    _ => InvocationStatus::Success,
+                };
+                observed.response = Some(ObservedMessage::parse(resp.msg));
+            }
+            Err(_) => observed.response = Some(ObservedMessage::Raw(payload.to_vec())),
+        }
+        self.ready.push_back(observed);
+    }
+
+    /// Moves every pending invocation to the ready queue without marking it as timed out, for
+    /// when responses are no longer being tracked
+    fn release_all(&mut self) {
+        let mut pending = self.pending.drain().map(|(_, p)| p).collect::<Vec<_>>();
+        pending.sort_by_key(|(_, started)| *started);
+        self.ready
+            .extend(pending.into_iter().map(|(observed, _)| observed));
+    }
+
+    /// Moves invocations that have waited longer than the response timeout (or all of them, if
+    /// `all` is set) to the ready queue
+    fn expire(&mut self, all: bool) {
+        let timeout = self.timeout;
+        let mut expired = self
+            .pending
+            .iter()
+            .filter(|(_, (_, started))| all || started.elapsed() >= timeout)
+            .map(|(reply, (_, started))| (*started, reply.clone()))
+            .collect::<Vec<_>>();
+        expired.sort();
+        for (_, reply) in expired {
+            if let Some((mut observed, _)) = self.pending.remove(&reply) {
+                observed.status = InvocationStatus::TimedOut;
+                self.ready.push_back(observed);
+            }
+        }
     }
 }
 
@@ -142,65 +557,53 @@ impl Stream for Spier {
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        match self.stream.poll_next_unpin(cx) {
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Ready(Some(msg)) => {
-                // Try to parse the invocation first
-                let mut inv: Invocation = match rmp_serde::from_slice(&msg.payload) {
-                    Ok(inv) => inv,
-                    Err(_e) => {
-                        // TODO: We should probably have some logging here
-                        // Just skip it if we can't parse it. This means we need to tell the executor to automatically wake up and poll immediately if we skip
-                        cx.waker().wake_by_ref();
-                        return Poll::Pending;
+        loop {
+            if let Some(observed) = self.responses_pending.ready.pop_front() {
+                return Poll::Ready(Some(observed));
+            }
+            if let Some(Poll::Ready(Some(msg))) = self
+                .responses
+                .as_mut()
+                .map(|responses| responses.poll_next_unpin(cx))
+            {
+                self.responses_pending.respond(&msg.subject, &msg.payload);
+                continue;
+            }
+            if !self.invocations_closed {
+                match self.stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(msg)) => {
+                        self.handle_invocation(msg);
+                        continue;
                     }
-                };
-                let body = inv.msg;
-                inv.msg = Vec::new();
-
-                if inv.origin.is_provider() && inv.target.public_key() != self.actor_id.as_ref() {
-                    // This is a provider invocation that isn't for us, so we should skip it
-                    cx.waker().wake_by_ref();
-                    return Poll::Pending;
+                    Poll::Ready(None) => {
+                        // No more invocations are coming, so return whatever is still waiting
+                        self.invocations_closed = true;
+                        self.responses_pending.expire(true);
+                        continue;
+                    }
+                    Poll::Pending => {}
                 }
-                let from = if inv.origin.is_actor() {
-                    self.friendly_name
-                        .clone()
-                        .unwrap_or_else(|| inv.origin.public_key())
-                } else {
-                    let pubkey = inv.origin.public_key();
-                    self.provider_info
-                        .get(&pubkey)
-                        .and_then(|prov| prov.friendly_name.clone())
-                        .unwrap_or(pubkey)
-                };
-                let to = if inv.target.is_actor() {
-                    self.friendly_name
-                        .clone()
-                        .unwrap_or_else(|| inv.target.public_key())
-                } else {
-                    let pubkey = inv.target.public_key();
-                    self.provider_info
-                        .get(&pubkey)
-                        .and_then(|prov| prov.friendly_name.clone())
-                        .unwrap_or(pubkey)
-                };
-                // NOTE(thomastaylor312): Ideally we'd consume `msg.payload` above with a
-                // `Cursor` and `from_reader` and then manually reconstruct the acking using the
-                // message context, but I didn't want to waste time optimizing yet
-                Poll::Ready(Some(ObservedInvocation {
-                    invocation: inv,
-                    timestamp: Local::now(),
-                    from,
-                    to,
-                    message: ObservedMessage::parse(body),
-                }))
+            } else if self.responses_pending.pending.is_empty() {
+                return Poll::Ready(None);
+            }
+            if !self.responses_pending.pending.is_empty()
+                && self.expiry_check.poll_tick(cx).is_ready()
+            {
+                self.responses_pending.expire(false);
+                continue;
             }
-            Poll::Pending => Poll::Pending,
+            return Poll::Pending;
         }
     }
 }
 
+/// Subscribes to the inboxes that invocation responses are sent to. This is server-wide: every
+/// reply sent to an `_INBOX.` subject on the server is delivered, whichever lattice or client it
+/// belongs to, and responses that don't match an observed invocation are discarded
+async fn subscribe_responses(nats_client: &async_nats::Client) -> Result<async_nats::Subscriber> {
+    Ok(nats_client.subscribe("_INBOX.>".to_string()).await?)
+}
+
 #[derive(Debug)]
 struct ProviderDetails {
     id: ServiceId,
@@ -235,24 +638,238 @@ async fn get_linked_providers(
                 })
                 .collect::<Vec<_>>()
         })?;
-    let mut claim_names: HashMap<String, String> = ctl_client
+    let mut claim_names = get_claim_names(ctl_client).await?;
+    details.iter_mut().for_each(|detail| {
+        detail.friendly_name = claim_names.remove(detail.id.as_ref());
+    });
+    Ok(details)
+}
+
+/// Fetches the friendly names of all actors and providers in the lattice, keyed by public key
+async fn get_claim_names(
+    ctl_client: &wasmcloud_control_interface::Client,
+) -> Result<HashMap<String, String>> {
+    Ok(ctl_client
         .get_claims()
         .await
         .map_err(|e| anyhow::anyhow!("Unable to get claims: {e:?}"))?
         .claims
         .into_iter()
         .filter_map(|mut claims| {
-            let id = claims.remove(CLAIMS_SUBJECT).unwrap_or_default();
-            // If it isn't a provider, skip
-            if !id.starts_with(ServiceId::prefix()) {
-                return None;
-            }
+            let id = claims.remove(CLAIMS_SUBJECT)?;
             // Only return it if it has a name
             claims.remove(CLAIMS_NAME).map(|name| (id, name))
         })
-        .collect();
-    details.iter_mut().for_each(|detail| {
-        detail.friendly_name = claim_names.remove(detail.id.as_ref());
-    });
-    Ok(details)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTOR_ID: &str = "MDPDJEYIAK6MACO67PRFGOSSLODBISK4SCEYDY3HEOY4P5CVJN6UCWUK";
+    const PROVIDER_ID: &str = "VAG3QITQQ2ODAOWB5TTQSDJ53XK3SHBEIFNK4AYJ5RKAX2UNSCAPHA5M";
+
+    fn invocation(operation: &str) -> Invocation {
+        let mut inv = Invocation::default();
+        inv.origin = WasmCloudEntity {
+            public_key: PROVIDER_ID.to_string(),
+            link_name: "default".to_string(),
+            contract_id: "wasmcloud:httpserver".to_string(),
+        };
+        inv.target = WasmCloudEntity {
+            public_key: ACTOR_ID.to_string(),
+            ..Default::default()
+        };
+        inv.operation = operation.to_string();
+        inv
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let names = HashMap::from([
+            (ACTOR_ID.to_string(), "echo".to_string()),
+            (PROVIDER_ID.to_string(), "HTTP Server".to_string()),
+        ]);
+        let inv = invocation("HttpServer.HandleRequest");
+        let matches = |filter: SpyFilter| filter.compile().unwrap().matches(&inv, 10, &names);
+
+        assert!(matches(SpyFilter::default()));
+        assert!(matches(SpyFilter {
+            actors: vec!["Echo".to_string(), "other".to_string()],
+            providers: vec![PROVIDER_ID.to_string()],
+            contract_ids: vec!["wasmcloud:httpserver".to_string()],
+            operations: vec!["HttpServer.*".to_string()],
+            min_payload_size: Some(10),
+            max_payload_size: Some(10),
+        }));
+        assert!(matches(SpyFilter {
+            providers: vec!["http server".to_string()],
+            operations: vec!["Other".to_string(), "*.HandleRequest".to_string()],
+            ..Default::default()
+        }));
+        // an actor filter must not match a provider with the same name
+        assert!(!matches(SpyFilter {
+            actors: vec!["HTTP Server".to_string()],
+            ..Default::default()
+        }));
+        assert!(!matches(SpyFilter {
+            contract_ids: vec!["wasmcloud:keyvalue".to_string()],
+            ..Default::default()
+        }));
+        assert!(!matches(SpyFilter {
+            operations: vec!["HttpServer".to_string()],
+            ..Default::default()
+        }));
+        assert!(!matches(SpyFilter {
+            min_payload_size: Some(11),
+            ..Default::default()
+        }));
+        assert!(!matches(SpyFilter {
+            max_payload_size: Some(9),
+            ..Default::default()
+        }));
+    }
+
+    fn observed(operation: &str) -> ObservedInvocation {
+        ObservedInvocation {
+            invocation: invocation(operation),
+            timestamp: Local::now(),
+            from: "HTTP Server".to_string(),
+            to: "echo".to_string(),
+            message: ObservedMessage::Raw(Vec::new()),
+            payload_size: 0,
+            status: InvocationStatus::Unknown,
+            response: None,
+            latency: None,
+        }
+    }
+
+    fn response(msg: &[u8], error: Option<&str>) -> Vec<u8> {
+        let mut resp = InvocationResponse::default();
+        resp.msg = msg.to_vec();
+        resp.error = error.map(str::to_string);
+        rmp_serde::to_vec_named(&resp).unwrap()
+    }
+
+    #[test]
+    fn test_pending_responses() {
+        let mut pending = PendingResponses::new(Duration::from_secs(60));
+        pending.observe(observed("Ok"), Some("_INBOX.ok".to_string()));
+        pending.observe(observed("Fail"), Some("_INBOX.fail".to_string()));
+        pending.observe(
+            observed("Unanswered"),
+            Some("_INBOX.unanswered".to_string()),
+        );
+        pending.observe(observed("NoReply"), None);
+
+        // Invocations without a reply subject are ready right away
+        let no_reply = pending.ready.pop_front().unwrap();
+        assert_eq!(no_reply.invocation.operation, "NoReply");
+        assert_eq!(no_reply.status, InvocationStatus::Unknown);
+        assert!(no_reply.latency.is_none());
+
+        let body = rmp_serde::to_vec_named(&serde_json::json!({"status": 200})).unwrap();
+        pending.respond("_INBOX.ok", &response(&body, None));
+        let ok = pending.ready.pop_front().unwrap();
+        assert_eq!(ok.invocation.operation, "Ok");
+        assert_eq!(ok.status, InvocationStatus::Success);
+        assert!(ok.latency.is_some());
+        assert_eq!(
+            ok.response.unwrap().to_json(),
+            serde_json::json!({"status": 200})
+        );
+
+        pending.respond("_INBOX.fail", &response(&[], Some("boom")));
+        let fail = pending.ready.pop_front().unwrap();
+        assert_eq!(fail.invocation.operation, "Fail");
+        assert_eq!(fail.status, InvocationStatus::Error("boom".to_string()));
+        assert!(fail.latency.is_some());
+
+        // Responses to unknown or already answered invocations are ignored
+        pending.respond("_INBOX.ok", &response(&[], None));
+        pending.respond("_INBOX.other", &response(&[], None));
+        assert!(pending.ready.is_empty());
+
+        // The unanswered invocation is kept until the timeout passes
+        pending.expire(false);
+        assert!(pending.ready.is_empty());
+        pending.timeout = Duration::ZERO;
+        pending.expire(false);
+        let unanswered = pending.ready.pop_front().unwrap();
+        assert_eq!(unanswered.invocation.operation, "Unanswered");
+        assert_eq!(unanswered.status, InvocationStatus::TimedOut);
+        assert!(unanswered.latency.is_none());
+        assert!(unanswered.response.is_none());
+        assert!(pending.pending.is_empty());
+    }
+
+    #[test]
+    fn test_pending_responses_expire_all() {
+        let mut pending = PendingResponses::new(Duration::from_secs(60));
+        pending.observe(observed("First"), Some("_INBOX.1".to_string()));
+        pending.observe(observed("Second"), Some("_INBOX.2".to_string()));
+        pending.expire(true);
+        let expired = pending
+            .ready
+            .iter()
+            .map(|observed| (observed.invocation.operation.as_str(), &observed.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            expired,
+            vec![
+                ("First", &InvocationStatus::TimedOut),
+                ("Second", &InvocationStatus::TimedOut)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pending_responses_release_all() {
+        let mut pending = PendingResponses::new(Duration::from_secs(60));
+        pending.observe(observed("First"), Some("_INBOX.1".to_string()));
+        pending.observe(observed("Second"), Some("_INBOX.2".to_string()));
+        pending.release_all();
+        assert!(pending.pending.is_empty());
+        let released = pending
+            .ready
+            .iter()
+            .map(|observed| (observed.invocation.operation.as_str(), &observed.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            released,
+            vec![
+                ("First", &InvocationStatus::Unknown),
+                ("Second", &InvocationStatus::Unknown)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observed_json() {
+        let body =
+            rmp_serde::to_vec_named(&serde_json::json!({"path": "/", "body": [1, 2]})).unwrap();
+        let observed = ObservedInvocation {
+            invocation: invocation("HttpServer.HandleRequest"),
+            timestamp: Local::now(),
+            from: "HTTP Server".to_string(),
+            to: "echo".to_string(),
+            payload_size: body.len(),
+            message: ObservedMessage::parse(body),
+            status: InvocationStatus::Error("boom".to_string()),
+            response: Some(ObservedMessage::Raw(b"oops".to_vec())),
+            latency: Some(Duration::from_millis(3)),
+        };
+        let json = observed.to_json();
+        assert_eq!(json["operation"], "HttpServer.HandleRequest");
+        assert_eq!(json["origin"]["contract_id"], "wasmcloud:httpserver");
+        assert_eq!(
+            json["message"],
+            serde_json::json!({"path": "/", "body": [1, 2]})
+        );
+        assert_eq!(
+            ObservedMessage::Raw(b"raw".to_vec()).to_json(),
+            serde_json::json!("raw")
+        );
+    }
 }
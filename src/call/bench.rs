@@ -0,0 +1,243 @@
+//! Load-testing mode for `wash call`, which repeatedly sends the same invocation
+//! and reports throughput and latency statistics
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use clap::Args;
+use serde_json::json;
+use term_table::{
+    row::Row,
+    table_cell::{Alignment, TableCell},
+    Table,
+};
+use wash_lib::{capture::percentile, cli::CommandOutput};
+
+use super::PreparedCall;
+
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct BenchOpts {
+    /// Benchmark the actor by sending this many invocations, then report latency statistics
+    /// instead of the response
+    #[clap(long = "bench-count")]
+    pub(crate) count: Option<u64>,
+
+    /// Benchmark the actor by sending invocations for this many seconds, then report latency
+    /// statistics instead of the response. If used with --bench-count, stops at whichever
+    /// limit is reached first
+    #[clap(long = "bench-duration")]
+    pub(crate) duration_secs: Option<u64>,
+
+    /// Number of invocations in flight at the same time while benchmarking
+    #[clap(long = "bench-concurrency", default_value_t = 1)]
+    pub(crate) concurrency: u64,
+}
+
+/// Limits of a benchmark run
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BenchSettings {
+    count: Option<u64>,
+    duration: Option<Duration>,
+    concurrency: u64,
+}
+
+impl BenchOpts {
+    /// Returns the benchmark settings, or `None` if benchmarking was not requested
+    pub(crate) fn settings(&self) -> Result<Option<BenchSettings>> {
+        if self.count.is_none() && self.duration_secs.is_none() {
+            return Ok(None);
+        }
+        if self.count == Some(0) || self.duration_secs == Some(0) {
+            bail!("--bench-count and --bench-duration must be greater than zero");
+        }
+        if self.concurrency == 0 {
+            bail!("--bench-concurrency must be greater than zero");
+        }
+        Ok(Some(BenchSettings {
+            count: self.count,
+            duration: self.duration_secs.map(Duration::from_secs),
+            concurrency: self.concurrency,
+        }))
+    }
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Default)]
+pub(crate) struct BenchReport {
+    /// latencies of successful invocations, sorted ascending
+    latencies: Vec<Duration>,
+    /// number of failed invocations per error message
+    errors: BTreeMap<String, u64>,
+    elapsed: Duration,
+    concurrency: u64,
+}
+
+/// Sends the prepared invocation according to the settings, using `concurrency`
+/// workers that each keep one invocation in flight
+pub(crate) async fn run(call: &PreparedCall, settings: BenchSettings) -> BenchReport {
+    let started = Instant::now();
+    let deadline = settings.duration.map(|d| started + d);
+    let sent = AtomicU64::new(0);
+
+    let workers = (0..settings.concurrency).map(|_| async {
+        let mut latencies = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                break;
+            }
+            let n = sent.fetch_add(1, Ordering::Relaxed);
+            if matches!(settings.count, Some(count) if n >= count) {
+                break;
+            }
+            let start = Instant::now();
+            match call.send().await {
+                Ok(_) => latencies.push(start.elapsed()),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        (latencies, errors)
+    });
+    let results = futures::future::join_all(workers).await;
+
+    let mut report = BenchReport {
+        elapsed: started.elapsed(),
+        concurrency: settings.concurrency,
+        ..Default::default()
+    };
+    for (latencies, errors) in results {
+        report.latencies.extend(latencies);
+        for error in errors {
+            *report.errors.entry(error).or_default() += 1;
+        }
+    }
+    report.latencies.sort();
+    report
+}
+
+impl BenchReport {
+    fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    fn total(&self) -> u64 {
+        self.latencies.len() as u64 + self.error_count()
+    }
+
+    /// Completed invocations (successful or not) per second
+    fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.total() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the latency at the given percentile (0-100)
+    fn percentile(&self, pct: f64) -> Option<Duration> {
+        percentile(&self.latencies, pct)
+    }
+
+    fn mean(&self) -> Option<Duration> {
+        let count = self.latencies.len() as u32;
+        (count > 0).then(|| self.latencies.iter().sum::<Duration>() / count)
+    }
+
+    pub(crate) fn output(&self) -> CommandOutput {
+        let latency = [
+            ("min", self.latencies.first().copied()),
+            ("mean", self.mean()),
+            ("p50", self.percentile(50.0)),
+            ("p90", self.percentile(90.0)),
+            ("p99", self.percentile(99.0)),
+            ("max", self.latencies.last().copied()),
+        ];
+
+        let mut map = std::collections::HashMap::new();
+        map.insert("invocations".to_string(), json!(self.total()));
+        map.insert("successes".to_string(), json!(self.latencies.len()));
+        map.insert("errors".to_string(), json!(self.error_count()));
+        map.insert("error_messages".to_string(), json!(self.errors));
+        map.insert("concurrency".to_string(), json!(self.concurrency));
+        map.insert("duration_ms".to_string(), json!(as_millis(self.elapsed)));
+        map.insert("throughput_per_sec".to_string(), json!(self.throughput()));
+        map.insert(
+            "latency_ms".to_string(),
+            json!(latency
+                .iter()
+                .map(|(name, value)| (name.to_string(), json!(value.map(as_millis))))
+                .collect::<serde_json::Map<_, _>>()),
+        );
+
+        let mut table = Table::new();
+        crate::util::configure_table_style(&mut table);
+        let mut row = |name: &str, value: String| {
+            table.add_row(Row::new(vec![
+                TableCell::new_with_alignment(name, 1, Alignment::Left),
+                TableCell::new_with_alignment(value, 1, Alignment::Right),
+            ]))
+        };
+        row("Invocations", self.total().to_string());
+        row("Errors", self.error_count().to_string());
+        row("Concurrency", self.concurrency.to_string());
+        row("Duration", format!("{:.3}s", self.elapsed.as_secs_f64()));
+        row("Throughput", format!("{:.2}/s", self.throughput()));
+        for (name, value) in latency {
+            row(
+                &format!("Latency {name}"),
+                value
+                    .map(|v| format!("{:.3}ms", as_millis(v)))
+                    .unwrap_or_else(|| "N/A".to_string()),
+            );
+        }
+
+        let mut text = format!("\nBenchmark results:\n{}", table.render());
+        if !self.errors.is_empty() {
+            text.push_str("\nErrors:\n");
+            for (message, count) in self.errors.iter() {
+                text.push_str(&format!("  {count} x {message}\n"));
+            }
+        }
+        CommandOutput::new(text, map)
+    }
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let report = BenchReport {
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            elapsed: Duration::from_secs(2),
+            concurrency: 4,
+            ..Default::default()
+        };
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(report.percentile(90.0), Some(Duration::from_millis(90)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(report.percentile(100.0), Some(Duration::from_millis(100)));
+        assert_eq!(report.throughput(), 50.0);
+        assert_eq!(BenchReport::default().percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_settings() {
+        assert!(BenchOpts::default().settings().unwrap().is_none());
+        let opts = BenchOpts {
+            count: Some(10),
+            duration_secs: None,
+            concurrency: 0,
+        };
+        assert!(opts.settings().is_err());
+    }
+}
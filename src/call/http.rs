@@ -0,0 +1,164 @@
+//! Helpers for invoking actors that implement `wasmcloud:httpserver` with `wash call`,
+//! building the `HttpRequest` payload and decoding the `HttpResponse`
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::json;
+use wash_lib::{
+    cli::CommandOutput,
+    httpserver::{HttpRequest, HttpResponse},
+};
+
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct HttpOpts {
+    /// Invoke the actor as if the httpserver provider received a request for this path, which
+    /// may include a query string. The operation defaults to HttpServer.HandleRequest and the
+    /// HttpResponse is displayed instead of the raw response
+    #[clap(long = "http-path")]
+    pub(crate) path: Option<String>,
+
+    /// HTTP method of the request when using --http-path
+    #[clap(long = "http-method", default_value = "GET", requires = "path")]
+    pub(crate) method: String,
+
+    /// HTTP header of the request when using --http-path, in the form 'Name: value'.
+    /// May be specified more than once
+    #[clap(
+        short = 'H',
+        long = "http-header",
+        number_of_values = 1,
+        requires = "path"
+    )]
+    pub(crate) headers: Vec<String>,
+
+    /// Body of the request when using --http-path. The -d/--data file may be used instead
+    #[clap(long = "http-body", requires = "path", conflicts_with = "data")]
+    pub(crate) body: Option<String>,
+}
+
+impl HttpOpts {
+    /// Builds the request, or returns `None` if --http-path was not used. The body is taken
+    /// from --http-body, or else from the provided data file contents
+    pub(crate) fn request(&self, data: Option<Vec<u8>>) -> Result<Option<HttpRequest>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let (path, query_string) = path.split_once('?').unwrap_or((path, ""));
+        if !path.starts_with('/') {
+            bail!("--http-path must start with '/'");
+        }
+        let mut header: HashMap<String, Vec<String>> = HashMap::new();
+        for value in self.headers.iter() {
+            let (name, value) = value
+                .split_once(':')
+                .with_context(|| format!("invalid header '{value}', use the form 'Name: value'"))?;
+            header
+                .entry(name.trim().to_lowercase())
+                .or_default()
+                .push(value.trim().to_string());
+        }
+        Ok(Some(HttpRequest {
+            method: self.method.to_uppercase(),
+            path: path.to_string(),
+            query_string: query_string.to_string(),
+            header,
+            body: match &self.body {
+                Some(body) => body.as_bytes().to_vec(),
+                None => data.unwrap_or_default(),
+            },
+        }))
+    }
+}
+
+/// Displays the response with its status, headers and body
+pub(crate) fn response_output(response: &HttpResponse) -> CommandOutput {
+    let body = response.body_json();
+    let mut text = format!("\nHTTP response: {}\n", response.status_code);
+    let mut headers = response.header.iter().collect::<Vec<_>>();
+    headers.sort();
+    for (name, values) in headers {
+        for value in values {
+            text.push_str(&format!("{name}: {value}\n"));
+        }
+    }
+    if !response.body.is_empty() {
+        text.push('\n');
+        match &body {
+            serde_json::Value::String(body) => text.push_str(body),
+            body => text.push_str(&serde_json::to_string_pretty(body).unwrap_or_default()),
+        }
+    }
+
+    let mut map = HashMap::new();
+    map.insert("status".to_string(), json!(response.status_code));
+    map.insert("headers".to_string(), json!(response.header));
+    map.insert("body".to_string(), body);
+    CommandOutput::new(text, map)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let opts = HttpOpts {
+            path: Some("/orders/7?verbose=true".to_string()),
+            method: "post".to_string(),
+            headers: vec![
+                "Content-Type: application/json".to_string(),
+                "X-Tag: a".to_string(),
+                "x-tag:b".to_string(),
+            ],
+            body: None,
+        };
+        let request = opts.request(Some(b"{}".to_vec())).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/orders/7");
+        assert_eq!(request.query_string, "verbose=true");
+        assert_eq!(request.header["x-tag"], vec!["a", "b"]);
+        assert_eq!(request.body, b"{}");
+
+        let decoded: HttpRequest =
+            wasmbus_rpc::common::deserialize(&request.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, request);
+
+        assert!(HttpOpts::default().request(None).unwrap().is_none());
+        let bad = HttpOpts {
+            path: Some("/".to_string()),
+            headers: vec!["no-colon".to_string()],
+            ..Default::default()
+        };
+        assert!(bad.request(None).is_err());
+    }
+
+    #[test]
+    fn test_response_output() {
+        let response = HttpResponse {
+            status_code: 201,
+            header: HashMap::from([(
+                "content-type".to_string(),
+                vec!["application/json".to_string()],
+            )]),
+            body: br#"{"id":7}"#.to_vec(),
+        };
+        let bytes = wasmbus_rpc::common::serialize(&response).unwrap();
+        let decoded = HttpResponse::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, response);
+
+        let output = response_output(&decoded);
+        assert_eq!(output.map["status"], json!(201));
+        assert_eq!(output.map["body"], json!({"id": 7}));
+        assert!(output
+            .text
+            .contains("HTTP response: 201\ncontent-type: application/json\n"));
+
+        let text = HttpResponse {
+            status_code: 404,
+            body: b"not found".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(text.body_json(), json!("not found"));
+    }
+}
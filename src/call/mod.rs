@@ -1,24 +1,37 @@
-use std::{collections::HashMap, path::PathBuf, time::Duration};
+use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
 
 use anyhow::{bail, Context, Result};
 use clap::Args;
 use log::{debug, error};
-use wash_lib::cli::CommandOutput;
+use wash_lib::cli::{CommandOutput, OutputKind};
+use wash_lib::common::{find_actor_id, FindIdError};
 use wash_lib::config::{context_dir, DEFAULT_LATTICE_PREFIX, DEFAULT_NATS_HOST, DEFAULT_NATS_PORT};
 use wash_lib::context::{
     ensure_host_config_context,
     fs::{load_context, ContextDir},
     ContextManager,
 };
+use wash_lib::httpserver::{HttpResponse, HANDLE_REQUEST};
 use wash_lib::id::{ClusterSeed, ModuleId};
 use wasmbus_rpc::{common::Message, core::WasmCloudEntity, rpc_client::RpcClient};
+use wasmcloud_control_interface::ClientBuilder as CtlClientBuilder;
 use wasmcloud_test_util::testing::TestResults;
 
+use crate::appearance::spinner::Spinner;
 use crate::util::{
-    default_timeout_ms, extract_arg_value, json_str_to_msgpack_bytes, msgpack_to_json_val,
-    nats_client_from_opts,
+    convert_error, default_timeout_ms, extract_arg_value, json_str_to_msgpack_bytes,
+    msgpack_to_json_val, nats_client_from_opts,
 };
 
+mod bench;
+mod http;
+mod schema;
+mod suite;
+use bench::BenchOpts;
+use http::{response_output, HttpOpts};
+pub(crate) use schema::{CallSchema, OperationSchema, SchemaOpts};
+use suite::SuiteOpts;
+
 /// fake key (not a real public key)  used to construct origin for invoking actors
 const WASH_ORIGIN_KEY: &str = "__WASH__";
 
@@ -38,12 +51,36 @@ impl CallCli {
     }
 }
 
-pub(crate) async fn handle_command(cmd: CallCommand) -> Result<CommandOutput> {
+pub(crate) async fn handle_command(
+    cmd: CallCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
     let is_test = cmd.test;
     let save_output = cmd.save.clone();
     let bin = cmd.bin;
-    let res = handle_call(cmd).await?;
-    call_output(res, save_output, bin, is_test)
+    let schema = CallSchema::from_opts(&cmd.schema)?;
+    if cmd.suite.suite.is_some() {
+        return suite::run(cmd, schema.as_ref()).await;
+    }
+    let is_http = cmd.http.path.is_some();
+    let operation = match (&schema, &cmd.operation) {
+        (Some(schema), Some(operation)) if !is_http => Some(schema.operation(operation)?),
+        _ => None,
+    };
+    if let Some(bench) = cmd.bench.settings()? {
+        let actor_id = cmd.actor_id.to_string();
+        let call = prepare_call(cmd, operation.as_ref()).await?;
+        let spinner = Spinner::new(&output_kind)?;
+        spinner.update_spinner_message(format!(" Benchmarking {actor_id} ..."));
+        let report = bench::run(&call, bench).await;
+        spinner.finish_and_clear();
+        return Ok(report.output());
+    }
+    let res = handle_call(cmd, operation.as_ref()).await?;
+    if is_http && save_output.is_none() && !is_test {
+        return Ok(response_output(&HttpResponse::from_bytes(&res)?));
+    }
+    call_output(res, save_output, bin, is_test, operation.as_ref())
 }
 
 #[derive(Debug, Clone, Args)]
@@ -92,6 +129,18 @@ pub(crate) struct CallCommand {
     #[clap(flatten)]
     opts: ConnectionOpts,
 
+    #[clap(flatten)]
+    pub(crate) schema: SchemaOpts,
+
+    #[clap(flatten)]
+    pub(crate) bench: BenchOpts,
+
+    #[clap(flatten)]
+    pub(crate) suite: SuiteOpts,
+
+    #[clap(flatten)]
+    pub(crate) http: HttpOpts,
+
     /// Optional json file to send as the operation payload
     #[clap(short, long)]
     pub(crate) data: Option<PathBuf>,
@@ -119,64 +168,176 @@ pub(crate) struct CallCommand {
     )]
     pub(crate) cluster_seed: Option<ClusterSeed>,
 
-    /// Public key or OCI reference of actor
+    /// Public key, call alias, or name of the actor to invoke. Call aliases and names are resolved
+    /// using the actor claims in the lattice. If more than one actor matches, an error will be
+    /// returned listing the options to choose from
     #[clap(name = "actor-id")]
-    pub(crate) actor_id: ModuleId,
+    pub(crate) actor_id: String,
 
     /// Operation to invoke on actor
-    #[clap(name = "operation")]
-    pub(crate) operation: String,
+    #[clap(name = "operation", required_unless_present_any = ["suite", "path"])]
+    pub(crate) operation: Option<String>,
 
     /// Payload to send with operation (in the form of '{"field": "value"}' )
     #[clap(name = "payload")]
     pub(crate) payload: Vec<String>,
 }
 
-pub(crate) async fn handle_call(cmd: CallCommand) -> Result<Vec<u8>> {
+pub(crate) async fn handle_call(
+    cmd: CallCommand,
+    operation: Option<&OperationSchema<'_>>,
+) -> Result<Vec<u8>> {
+    prepare_call(cmd, operation).await?.send().await
+}
+
+/// A connection to a lattice, used to invoke actors
+pub(crate) struct LatticeClient {
+    client: RpcClient,
+    origin: WasmCloudEntity,
+    lattice_prefix: String,
+    timeout: Duration,
+}
+
+impl LatticeClient {
+    /// Connects to the lattice using the provided options, falling back to context values
+    pub(crate) async fn connect(
+        opts: ConnectionOpts,
+        cluster_seed: Option<ClusterSeed>,
+    ) -> Result<Self> {
+        let lattice_prefix = opts
+            .lattice_prefix
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LATTICE_PREFIX.to_string());
+        let (client, timeout_ms) = rpc_client_from_opts(opts, cluster_seed).await?;
+        Ok(LatticeClient {
+            client,
+            origin: WasmCloudEntity::new_actor(WASH_ORIGIN_KEY)?,
+            lattice_prefix,
+            timeout: Duration::from_millis(timeout_ms),
+        })
+    }
+
+    /// Resolves a public key, call alias, or actor name to an actor using the claims in the lattice
+    pub(crate) async fn resolve_actor(&self, value: &str) -> Result<WasmCloudEntity> {
+        if let Ok(id) = ModuleId::from_str(value) {
+            return Ok(WasmCloudEntity::new_actor(id)?);
+        }
+        let ctl_client = CtlClientBuilder::new(self.client.client())
+            .lattice_prefix(&self.lattice_prefix)
+            .rpc_timeout(self.timeout)
+            .build()
+            .await
+            .map_err(convert_error)?;
+        match find_actor_id(value, &ctl_client).await {
+            Ok((id, _)) => Ok(WasmCloudEntity::new_actor(id)?),
+            Err(FindIdError::NoMatches) => bail!(
+                "No actor found matching '{value}'. Use the actor's public key, or a name or call alias from its claims"
+            ),
+            Err(FindIdError::MultipleMatches(matches)) => bail!(
+                "Multiple actors match '{value}', use a more specific name or one of these actor IDs:\n  {}",
+                matches.join("\n  ")
+            ),
+            Err(FindIdError::Error(e)) => {
+                Err(e.context(format!("Failed to resolve actor '{value}' in the lattice")))
+            }
+        }
+    }
+
+    /// Invokes an operation on the target actor and waits for its response
+    pub(crate) async fn call(
+        &self,
+        target: &WasmCloudEntity,
+        operation: &str,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        Ok(self
+            .client
+            .send_timeout(
+                self.origin.clone(),
+                target.clone(),
+                &self.lattice_prefix,
+                Message {
+                    method: operation,
+                    arg: payload.into(),
+                },
+                self.timeout,
+            )
+            .await?)
+    }
+}
+
+/// An invocation that is ready to be sent (possibly many times) to an actor
+pub(crate) struct PreparedCall {
+    lattice: LatticeClient,
+    target: WasmCloudEntity,
+    operation: String,
+    payload: Vec<u8>,
+}
+
+impl PreparedCall {
+    /// Sends the invocation and waits for the actor's response
+    pub(crate) async fn send(&self) -> Result<Vec<u8>> {
+        self.lattice
+            .call(&self.target, &self.operation, &self.payload)
+            .await
+    }
+}
+
+/// Validates the command, encodes its payload and connects to the lattice
+pub(crate) async fn prepare_call(
+    cmd: CallCommand,
+    operation: Option<&OperationSchema<'_>>,
+) -> Result<PreparedCall> {
+    let operation_name = match cmd.operation {
+        Some(operation) => operation,
+        None if cmd.http.path.is_some() => HANDLE_REQUEST.to_string(),
+        None => bail!("an operation is required unless --suite or --http-path is used"),
+    };
     debug!(
         "calling actor with operation: {}, data: {}",
-        &cmd.operation,
+        &operation_name,
         cmd.payload.join("")
     );
     if !"bs2".contains(cmd.bin) {
         bail!("'bin' parameter must be 'b', 's', or '2'");
     }
 
-    let origin = WasmCloudEntity::new_actor(WASH_ORIGIN_KEY)?;
-    let target = WasmCloudEntity::new_actor(&cmd.actor_id)?;
-
     if cmd.data.is_some() && !cmd.payload.is_empty() {
         bail!("you can use either -d/--data or the payload args, but not both.");
     }
-    let payload = if let Some(fname) = cmd.data {
-        std::fs::read_to_string(fname)?
+    let bytes = if cmd.http.path.is_some() {
+        if !cmd.payload.is_empty() {
+            bail!("use --http-body or -d/--data to send a request body with --http-path");
+        }
+        let data = cmd.data.map(std::fs::read).transpose()?;
+        // unwrap is safe, the request is always built when --http-path is used
+        let request = cmd.http.request(data)?.unwrap();
+        debug!(
+            "calling actor with http request: {} {}",
+            request.method, request.path
+        );
+        request.to_bytes()?
     } else {
-        cmd.payload.join("")
+        let payload = if let Some(fname) = cmd.data {
+            std::fs::read_to_string(fname)?
+        } else {
+            cmd.payload.join("")
+        };
+        debug!(
+            "calling actor with operation: {}, data: {}",
+            &operation_name, &payload
+        );
+        encode_payload(&payload, operation)?
     };
-    debug!(
-        "calling actor with operation: {}, data: {}",
-        &cmd.operation, &payload
-    );
-    let bytes = json_str_to_msgpack_bytes(&payload)?;
-    let lattice_prefix = cmd
-        .opts
-        .lattice_prefix
-        .clone()
-        .unwrap_or_else(|| DEFAULT_LATTICE_PREFIX.to_string());
-
-    let (client, timeout_ms) = rpc_client_from_opts(cmd.opts, cmd.cluster_seed).await?;
-    Ok(client
-        .send_timeout(
-            origin,
-            target,
-            &lattice_prefix,
-            Message {
-                method: &cmd.operation,
-                arg: bytes.into(),
-            },
-            Duration::from_millis(timeout_ms),
-        )
-        .await?)
+
+    let lattice = LatticeClient::connect(cmd.opts, cmd.cluster_seed).await?;
+    let target = lattice.resolve_actor(&cmd.actor_id).await?;
+    Ok(PreparedCall {
+        lattice,
+        target,
+        operation: operation_name,
+        payload: bytes,
+    })
 }
 
 // Helper output functions, used to ensure consistent output between call & standalone commands
@@ -185,6 +346,7 @@ pub(crate) fn call_output(
     save_output: Option<PathBuf>,
     bin: char,
     is_test: bool,
+    operation: Option<&OperationSchema<'_>>,
 ) -> Result<CommandOutput> {
     if let Some(ref save_path) = save_output {
         std::fs::write(save_path, response)
@@ -213,9 +375,18 @@ pub(crate) fn call_output(
     }
 
     let mut json = HashMap::new();
+    if operation.is_some() {
+        let decoded = decode_response(&response, bin, operation)?;
+        let text = format!(
+            "\nCall response: {}",
+            serde_json::to_string_pretty(&decoded)?
+        );
+        json.insert("response".to_string(), decoded);
+        return Ok(CommandOutput::new(text, json));
+    }
     json.insert(
         "response".to_string(),
-        msgpack_to_json_val(response.clone(), bin),
+        decode_response(&response, bin, None)?,
     );
 
     Ok(CommandOutput::new(
@@ -227,6 +398,31 @@ pub(crate) fn call_output(
     ))
 }
 
+/// Encodes a json payload as msgpack, validating it against the operation's input shape if a
+/// smithy model was supplied. An empty payload is sent as nil
+pub(crate) fn encode_payload(
+    payload: &str,
+    operation: Option<&OperationSchema<'_>>,
+) -> Result<Vec<u8>> {
+    match operation {
+        Some(operation) => operation.encode_input(payload),
+        None if payload.trim().is_empty() => json_str_to_msgpack_bytes("null"),
+        None => json_str_to_msgpack_bytes(payload),
+    }
+}
+
+/// Decodes a response to json, using the operation's output shape if a smithy model was supplied
+pub(crate) fn decode_response(
+    response: &[u8],
+    bin: char,
+    operation: Option<&OperationSchema<'_>>,
+) -> Result<serde_json::Value> {
+    match operation {
+        Some(operation) => operation.decode_output(response, bin),
+        None => Ok(msgpack_to_json_val(response.to_vec(), bin)),
+    }
+}
+
 async fn rpc_client_from_opts(
     opts: ConnectionOpts,
     cmd_cluster_seed: Option<ClusterSeed>,
@@ -313,14 +509,15 @@ mod test {
     use super::CallCommand;
     use anyhow::Result;
     use clap::Parser;
-    use std::{path::PathBuf, str::FromStr};
-    use wash_lib::id::ModuleId;
+    use std::path::PathBuf;
 
     const RPC_HOST: &str = "127.0.0.1";
     const RPC_PORT: &str = "4222";
     const LATTICE_PREFIX: &str = "default";
     const SAVE_FNAME: &str = "/dev/null";
     const DATA_FNAME: &str = "/tmp/data.json";
+    const MODEL_FNAME: &str = "./interface.smithy";
+    const MODEL_CONFIG_FNAME: &str = "./codegen.toml";
 
     const ACTOR_ID: &str = "MDPDJEYIAK6MACO67PRFGOSSLODBISK4SCEYDY3HEOY4P5CVJN6UCWUK";
 
@@ -353,6 +550,16 @@ mod test {
             RPC_PORT,
             "--rpc-timeout-ms",
             "0",
+            "--model",
+            MODEL_FNAME,
+            "--model-config",
+            MODEL_CONFIG_FNAME,
+            "--bench-count",
+            "100",
+            "--bench-duration",
+            "10",
+            "--bench-concurrency",
+            "4",
             ACTOR_ID,
             "HandleOperation",
             "{ \"hello\": \"world\"}",
@@ -360,6 +567,10 @@ mod test {
         match call_all.command {
             CallCommand {
                 opts,
+                schema,
+                bench,
+                suite,
+                http,
                 data,
                 save,
                 bin,
@@ -377,6 +588,13 @@ mod test {
                     opts.context,
                     Some(PathBuf::from("~/.wash/contexts/default.json"))
                 );
+                assert_eq!(schema.model, vec![MODEL_FNAME.to_string()]);
+                assert_eq!(schema.model_config, Some(PathBuf::from(MODEL_CONFIG_FNAME)));
+                assert_eq!(bench.count, Some(100));
+                assert_eq!(bench.duration_secs, Some(10));
+                assert_eq!(bench.concurrency, 4);
+                assert_eq!(suite.suite, None);
+                assert_eq!(http.path, None);
                 assert_eq!(data, Some(PathBuf::from(DATA_FNAME)));
                 assert_eq!(save, Some(PathBuf::from(SAVE_FNAME)));
                 assert_eq!(
@@ -387,8 +605,8 @@ mod test {
                 );
                 assert!(test);
                 assert_eq!(bin, '2');
-                assert_eq!(actor_id, ModuleId::from_str(ACTOR_ID).unwrap());
-                assert_eq!(operation, "HandleOperation");
+                assert_eq!(actor_id, ACTOR_ID);
+                assert_eq!(operation.as_deref(), Some("HandleOperation"));
                 assert_eq!(payload, vec!["{ \"hello\": \"world\"}".to_string()])
             }
             #[allow(unreachable_patterns)]
@@ -0,0 +1,641 @@
+//! Schema-aware encoding and decoding of `wash call` payloads
+//!
+//! When a smithy model is supplied, the JSON payload is checked against the input shape
+//! of the invoked operation and encoded with the same field names and types that the
+//! generated wasmbus code expects. Responses are decoded using the operation's output shape.
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use atelier_core::{
+    model::{
+        shapes::{HasTraits, MemberShape, ShapeKind, Simple, StructureOrUnion},
+        values::Value as NodeValue,
+        HasIdentity, Model, ShapeID,
+    },
+    prelude::prelude_namespace_id,
+};
+use clap::Args;
+use rmpv::Value as MsgValue;
+use serde_json::Value as JsonValue;
+
+use crate::smithy::{build_model, select_config};
+use crate::util::msgpack_to_json;
+
+/// namespace of the wasmcloud core model, which declares `Unit` and the `serialization` trait
+const WASMCLOUD_MODEL_NAMESPACE: &str = "org.wasmcloud.model";
+
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct SchemaOpts {
+    /// Smithy model file or url describing the actor's interface. May be specified more than once.
+    /// When a model is supplied, the payload is validated against the operation's input shape
+    /// and the response is decoded using the operation's output shape
+    #[clap(long = "model", number_of_values = 1)]
+    pub(crate) model: Vec<String>,
+
+    /// Codegen configuration file (toml) whose `models` are used for schema-aware calls.
+    /// Only used if no --model is specified
+    #[clap(long = "model-config")]
+    pub(crate) model_config: Option<PathBuf>,
+}
+
+impl SchemaOpts {
+    fn is_empty(&self) -> bool {
+        self.model.is_empty() && self.model_config.is_none()
+    }
+}
+
+/// A smithy model loaded for schema-aware calls
+pub(crate) struct CallSchema {
+    model: Model,
+}
+
+impl CallSchema {
+    /// Loads the model described by the options, or returns `None` if no model was requested
+    pub(crate) fn from_opts(opts: &SchemaOpts) -> Result<Option<Self>> {
+        if opts.is_empty() {
+            return Ok(None);
+        }
+        let config = select_config(&opts.model_config)?;
+        let model = build_model(opts.model.clone(), config.models, config.base_dir, 0)
+            .context("failed to load smithy model for call")?;
+        Ok(Some(CallSchema { model }))
+    }
+
+    /// Finds the operation invoked by a wasmbus method name, e.g. `HttpServer.HandleRequest`.
+    /// The service prefix may be omitted if the operation name is unique within the model.
+    pub(crate) fn operation(&self, method: &str) -> Result<OperationSchema<'_>> {
+        let (service_name, op_name) = match method.split_once('.') {
+            Some((service, op)) => (Some(service), op),
+            None => (None, method),
+        };
+        let mut found = Vec::new();
+        for shape in self.model.shapes() {
+            if let ShapeKind::Service(service) = shape.body() {
+                let name = to_pascal_case(&shape.id().shape_name().to_string());
+                if matches!(service_name, Some(s) if s != name) {
+                    continue;
+                }
+                found.extend(
+                    service
+                        .operations()
+                        .filter(|op| to_pascal_case(&op.shape_name().to_string()) == op_name)
+                        .map(|op| (name.clone(), op)),
+                );
+            }
+        }
+        let (service, op_id) = match found.len() {
+            0 => bail!("operation `{method}` was not found in any service of the smithy model"),
+            1 => found.remove(0),
+            _ => bail!(
+                "operation `{method}` is ambiguous, use one of: {}",
+                found
+                    .iter()
+                    .map(|(service, op)| format!("{service}.{}", op.shape_name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let operation = match self.model.shape(op_id).map(|s| s.body()) {
+            Some(ShapeKind::Operation(operation)) => operation,
+            _ => bail!("operation `{op_id}` is not defined in the smithy model"),
+        };
+        let not_unit = |id: &&ShapeID| !is_unit(id);
+        Ok(OperationSchema {
+            model: &self.model,
+            name: format!(
+                "{service}.{}",
+                to_pascal_case(&op_id.shape_name().to_string())
+            ),
+            input: operation.input().as_ref().filter(not_unit),
+            output: operation.output().as_ref().filter(not_unit),
+        })
+    }
+}
+
+/// Input and output shapes of a single operation
+pub(crate) struct OperationSchema<'m> {
+    model: &'m Model,
+    name: String,
+    input: Option<&'m ShapeID>,
+    output: Option<&'m ShapeID>,
+}
+
+impl<'m> OperationSchema<'m> {
+    /// Validates a JSON payload against the input shape and encodes it as msgpack
+    pub(crate) fn encode_input(&self, payload: &str) -> Result<Vec<u8>> {
+        let json = if payload.trim().is_empty() {
+            JsonValue::Null
+        } else {
+            serde_json::from_str::<JsonValue>(payload).context("payload is not valid json")?
+        };
+        let input = match self.input {
+            Some(input) => input,
+            None if json.is_null() => return Ok(Vec::new()),
+            None => bail!("operation `{}` does not take any input", self.name),
+        };
+        let mut errors = Vec::new();
+        let value = self.encode(input, &json, "$", &mut errors);
+        if !errors.is_empty() {
+            bail!(
+                "payload does not match input shape `{input}` of `{}`:\n  - {}",
+                self.name,
+                errors.join("\n  - ")
+            );
+        }
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &value)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a msgpack response using the output shape. Blobs are displayed according to
+    /// `bin`: binary('b'), string('s'), or both('2')
+    pub(crate) fn decode_output(&self, response: &[u8], bin: char) -> Result<JsonValue> {
+        let output = match self.output {
+            Some(output) => output,
+            None => return Ok(JsonValue::Null),
+        };
+        let value = rmpv::decode::read_value(&mut &response[..]).map_err(|e| {
+            anyhow!(
+                "response to `{}` is not valid msgpack ({e}). Response: {}",
+                self.name,
+                String::from_utf8_lossy(response)
+            )
+        })?;
+        self.decode(output, value, "$", bin)
+    }
+
+    fn encode(
+        &self,
+        id: &ShapeID,
+        json: &JsonValue,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) -> MsgValue {
+        let mismatch = |errors: &mut Vec<String>, expected: &str| {
+            errors.push(format!("{path}: expected {expected}, found {json}"));
+            MsgValue::Nil
+        };
+        match self.resolve(id) {
+            Shape::Simple(simple, unsigned) => match (&simple, json) {
+                (Simple::Boolean, JsonValue::Bool(b)) => MsgValue::Boolean(*b),
+                (Simple::String, JsonValue::String(s)) => MsgValue::from(s.as_str()),
+                (Simple::Blob, JsonValue::String(s)) => MsgValue::Binary(s.as_bytes().to_vec()),
+                (Simple::Blob, JsonValue::Array(items)) => {
+                    match items
+                        .iter()
+                        .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+                        .collect::<Option<Vec<u8>>>()
+                    {
+                        Some(bytes) => MsgValue::Binary(bytes),
+                        None => mismatch(errors, "a string or an array of bytes"),
+                    }
+                }
+                (Simple::Byte | Simple::Short | Simple::Integer | Simple::Long, _) => {
+                    let bits = match simple {
+                        Simple::Byte => 8,
+                        Simple::Short => 16,
+                        Simple::Integer => 32,
+                        _ => 64,
+                    };
+                    let value = match json {
+                        JsonValue::Number(n) => integer_in_range(n, bits, unsigned),
+                        _ => None,
+                    };
+                    match value {
+                        Some(v) => v,
+                        None if unsigned => {
+                            mismatch(errors, &format!("an unsigned {bits}-bit integer"))
+                        }
+                        None => mismatch(errors, &format!("a {bits}-bit integer")),
+                    }
+                }
+                (Simple::Float, JsonValue::Number(n)) if n.as_f64().is_some() => {
+                    MsgValue::F32(n.as_f64().unwrap_or_default() as f32)
+                }
+                (Simple::Double, JsonValue::Number(n)) if n.as_f64().is_some() => {
+                    MsgValue::F64(n.as_f64().unwrap_or_default())
+                }
+                (Simple::Timestamp, JsonValue::Number(n)) if n.as_i64().is_some() => {
+                    timestamp(n.as_i64().unwrap_or_default(), 0)
+                }
+                (Simple::Timestamp, JsonValue::Object(obj)) => {
+                    match (
+                        obj.get("sec").and_then(JsonValue::as_i64),
+                        obj.get("nsec").map(|v| v.as_u64()).unwrap_or(Some(0)),
+                    ) {
+                        (Some(sec), Some(nsec)) if nsec < 1_000_000_000 => {
+                            timestamp(sec, nsec as u32)
+                        }
+                        _ => mismatch(errors, "a timestamp ({\"sec\": <i64>, \"nsec\": <u32>})"),
+                    }
+                }
+                (Simple::Document, _) => untyped_json(json),
+                (Simple::BigInteger | Simple::BigDecimal, _) => {
+                    errors.push(format!("{path}: {simple} is not supported by wasmbus"));
+                    MsgValue::Nil
+                }
+                (Simple::Timestamp, _) => {
+                    mismatch(errors, "a timestamp (seconds or {\"sec\", \"nsec\"})")
+                }
+                (Simple::Blob, _) => mismatch(errors, "a string or an array of bytes"),
+                _ => mismatch(errors, &simple.to_string().to_lowercase()),
+            },
+            Shape::List(member) => match json {
+                JsonValue::Array(items) => MsgValue::Array(
+                    items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| {
+                            self.encode(member.target(), item, &format!("{path}[{i}]"), errors)
+                        })
+                        .collect(),
+                ),
+                _ => mismatch(errors, "an array"),
+            },
+            Shape::Map(_, value) => match json {
+                JsonValue::Object(obj) => MsgValue::Map(
+                    obj.iter()
+                        .map(|(k, v)| {
+                            (
+                                MsgValue::from(k.as_str()),
+                                self.encode(value.target(), v, &format!("{path}.{k}"), errors),
+                            )
+                        })
+                        .collect(),
+                ),
+                _ => mismatch(errors, "an object"),
+            },
+            Shape::Structure(strukt) => match json {
+                JsonValue::Object(obj) => self.encode_structure(strukt, obj, path, errors),
+                _ => mismatch(errors, &format!("an object of shape `{id}`")),
+            },
+            Shape::Unsupported(kind) => {
+                errors.push(format!(
+                    "{path}: {kind} shape `{id}` is not supported by wasmbus"
+                ));
+                MsgValue::Nil
+            }
+        }
+    }
+
+    fn encode_structure(
+        &self,
+        strukt: &StructureOrUnion,
+        obj: &serde_json::Map<String, JsonValue>,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) -> MsgValue {
+        let members = strukt
+            .members()
+            .map(|m| (serialized_name(m), m))
+            .collect::<Vec<_>>();
+        for key in obj.keys() {
+            if members.iter().any(|(name, _)| name == key) {
+                continue;
+            }
+            let normalized = normalize_field(key);
+            match members
+                .iter()
+                .find(|(name, _)| normalize_field(name) == normalized)
+            {
+                Some((name, _)) => errors.push(format!(
+                    "{path}: unknown field `{key}`, did you mean `{name}`?"
+                )),
+                None => errors.push(format!(
+                    "{path}: unknown field `{key}`, expected one of: {}",
+                    members
+                        .iter()
+                        .map(|(name, _)| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            }
+        }
+        let mut fields = Vec::new();
+        for (name, member) in members.iter() {
+            match obj.get(name) {
+                Some(JsonValue::Null) | None if member.is_required() => {
+                    errors.push(format!("{path}: missing required field `{name}`"))
+                }
+                Some(JsonValue::Null) | None => {}
+                Some(value) => fields.push((
+                    MsgValue::from(name.as_str()),
+                    self.encode(member.target(), value, &format!("{path}.{name}"), errors),
+                )),
+            }
+        }
+        MsgValue::Map(fields)
+    }
+
+    fn decode(&self, id: &ShapeID, value: MsgValue, path: &str, bin: char) -> Result<JsonValue> {
+        Ok(match (self.resolve(id), value) {
+            (_, MsgValue::Nil) => JsonValue::Null,
+            (Shape::Simple(Simple::Blob, _), MsgValue::Binary(bytes)) => blob_to_json(bytes, bin),
+            (Shape::Simple(Simple::Blob, _), MsgValue::String(s)) => {
+                blob_to_json(s.into_bytes(), bin)
+            }
+            (Shape::Simple(Simple::Document, _), value) => msgpack_to_json(value, bin),
+            (Shape::Simple(simple, _), value) => {
+                let matches = match simple {
+                    Simple::Boolean => value.is_bool(),
+                    Simple::String => value.is_str(),
+                    Simple::Byte | Simple::Short | Simple::Integer | Simple::Long => {
+                        value.is_i64() || value.is_u64()
+                    }
+                    Simple::Float | Simple::Double => value.is_number(),
+                    Simple::Timestamp => value.is_map(),
+                    _ => false,
+                };
+                if !matches {
+                    bail!("{path}: expected {simple} in response, found {value}");
+                }
+                msgpack_to_json(value, bin)
+            }
+            (Shape::List(member), MsgValue::Array(items)) => JsonValue::Array(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        self.decode(member.target(), item, &format!("{path}[{i}]"), bin)
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            (Shape::Map(_, member), MsgValue::Map(entries)) => JsonValue::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let key = map_key(k);
+                        let value =
+                            self.decode(member.target(), v, &format!("{path}.{key}"), bin)?;
+                        Ok((key, value))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            (Shape::Structure(strukt), MsgValue::Map(entries)) => {
+                let mut obj = serde_json::Map::new();
+                for (k, v) in entries {
+                    let key = map_key(k);
+                    let value = match strukt.members().find(|m| serialized_name(m) == key) {
+                        Some(member) => {
+                            self.decode(member.target(), v, &format!("{path}.{key}"), bin)?
+                        }
+                        None => msgpack_to_json(v, bin),
+                    };
+                    obj.insert(key, value);
+                }
+                JsonValue::Object(obj)
+            }
+            (Shape::List(_), value) => bail!("{path}: expected array in response, found {value}"),
+            (Shape::Map(..) | Shape::Structure(_), value) => {
+                bail!("{path}: expected `{id}` in response, found {value}")
+            }
+            (Shape::Unsupported(_), value) => msgpack_to_json(value, bin),
+        })
+    }
+
+    /// Resolves a shape id to its underlying shape, following simple type aliases
+    /// such as `org.wasmcloud.model#U32`
+    fn resolve(&self, id: &ShapeID) -> Shape<'m> {
+        if id.namespace() == prelude_namespace_id() {
+            return prelude_simple(&id.shape_name().to_string())
+                .map(|simple| Shape::Simple(simple, false))
+                .unwrap_or(Shape::Unsupported("unknown"));
+        }
+        let shape = match self.model.shape(id) {
+            Some(shape) => shape,
+            None => return Shape::Unsupported("unresolved"),
+        };
+        match shape.body() {
+            ShapeKind::Simple(simple) => Shape::Simple(
+                simple.clone(),
+                shape
+                    .traits()
+                    .keys()
+                    .any(|t| t.shape_name().to_string() == "unsignedInt"),
+            ),
+            ShapeKind::List(list) | ShapeKind::Set(list) => Shape::List(list.member()),
+            ShapeKind::Map(map) => Shape::Map(map.key(), map.value()),
+            ShapeKind::Structure(strukt) => Shape::Structure(strukt),
+            ShapeKind::Union(_) => Shape::Unsupported("union"),
+            _ => Shape::Unsupported("non-data"),
+        }
+    }
+}
+
+/// The parts of a resolved shape that matter for serialization
+enum Shape<'m> {
+    /// simple shape, and whether it carries the `unsignedInt` trait
+    Simple(Simple, bool),
+    List(&'m MemberShape),
+    Map(&'m MemberShape, &'m MemberShape),
+    Structure(&'m StructureOrUnion),
+    Unsupported(&'static str),
+}
+
+fn prelude_simple(name: &str) -> Option<Simple> {
+    Some(match name.trim_start_matches("Primitive") {
+        "Blob" => Simple::Blob,
+        "Boolean" => Simple::Boolean,
+        "Document" => Simple::Document,
+        "String" => Simple::String,
+        "Byte" => Simple::Byte,
+        "Short" => Simple::Short,
+        "Integer" => Simple::Integer,
+        "Long" => Simple::Long,
+        "Float" => Simple::Float,
+        "Double" => Simple::Double,
+        "BigInteger" => Simple::BigInteger,
+        "BigDecimal" => Simple::BigDecimal,
+        "Timestamp" => Simple::Timestamp,
+        _ => return None,
+    })
+}
+
+fn is_unit(id: &ShapeID) -> bool {
+    id.shape_name().to_string() == "Unit"
+        && (id.namespace() == prelude_namespace_id()
+            || id.namespace().to_string() == WASMCLOUD_MODEL_NAMESPACE)
+}
+
+/// Returns the name used on the wire for a structure member: the declared member name,
+/// unless it is overridden with `@serialization(name: "...")`
+fn serialized_name(member: &MemberShape) -> String {
+    member
+        .traits()
+        .iter()
+        .filter(|(id, _)| {
+            id.shape_name().to_string() == "serialization"
+                && id.namespace().to_string() == WASMCLOUD_MODEL_NAMESPACE
+        })
+        .find_map(|(_, value)| match value {
+            Some(NodeValue::Object(obj)) => match obj.get("name") {
+                Some(NodeValue::String(name)) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap_or_else(|| member.id().to_string())
+}
+
+/// Lowercases a field name and strips separators, so `query_string` matches `queryString`
+fn normalize_field(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn to_pascal_case(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn integer_in_range(n: &serde_json::Number, bits: u32, unsigned: bool) -> Option<MsgValue> {
+    if unsigned {
+        let v = n.as_u64()?;
+        (bits == 64 || v < (1u64 << bits)).then(|| MsgValue::from(v))
+    } else {
+        let v = n.as_i64()?;
+        let max = if bits == 64 {
+            i64::MAX
+        } else {
+            (1i64 << (bits - 1)) - 1
+        };
+        (v >= -max - 1 && v <= max).then(|| MsgValue::from(v))
+    }
+}
+
+fn timestamp(sec: i64, nsec: u32) -> MsgValue {
+    MsgValue::Map(vec![
+        (MsgValue::from("sec"), MsgValue::from(sec)),
+        (MsgValue::from("nsec"), MsgValue::from(nsec)),
+    ])
+}
+
+fn map_key(key: MsgValue) -> String {
+    match key {
+        MsgValue::String(s) => s.into_str().unwrap_or_default(),
+        other => other.to_string(),
+    }
+}
+
+fn blob_to_json(bytes: Vec<u8>, bin: char) -> JsonValue {
+    msgpack_to_json(MsgValue::Binary(bytes), bin)
+}
+
+/// Converts arbitrary json into msgpack without a schema, as `Document` values are
+fn untyped_json(json: &JsonValue) -> MsgValue {
+    match json {
+        JsonValue::Null => MsgValue::Nil,
+        JsonValue::Bool(b) => MsgValue::Boolean(*b),
+        JsonValue::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => MsgValue::from(u),
+            (_, Some(i)) => MsgValue::from(i),
+            _ => MsgValue::F64(n.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => MsgValue::from(s.as_str()),
+        JsonValue::Array(items) => MsgValue::Array(items.iter().map(untyped_json).collect()),
+        JsonValue::Object(obj) => MsgValue::Map(
+            obj.iter()
+                .map(|(k, v)| (MsgValue::from(k.as_str()), untyped_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const MODEL: &str = r#"
+$version: "1.0"
+
+namespace org.example.call
+
+service Orders {
+  version: "0.1",
+  operations: [ PlaceOrder, Ping ]
+}
+
+operation PlaceOrder {
+  input: Order,
+  output: Receipt
+}
+
+operation Ping {}
+
+structure Order {
+  @required
+  customerId: String,
+  quantity: Integer,
+  notes: Blob,
+  tags: TagList,
+}
+
+list TagList {
+  member: String
+}
+
+structure Receipt {
+  @required
+  orderId: String,
+  payload: Blob,
+}
+"#;
+
+    fn load_schema() -> CallSchema {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.smithy");
+        std::fs::write(&path, MODEL).unwrap();
+        CallSchema::from_opts(&SchemaOpts {
+            model: vec![path.to_string_lossy().to_string()],
+            model_config: None,
+        })
+        .unwrap()
+        .expect("model should be loaded")
+    }
+
+    #[test]
+    fn test_encode_input_validates_fields() {
+        let schema = load_schema();
+        let op = schema.operation("Orders.PlaceOrder").unwrap();
+
+        let bytes = op
+            .encode_input(r#"{"customerId": "c1", "quantity": 3, "notes": "hi", "tags": ["a"]}"#)
+            .unwrap();
+        let value = rmpv::decode::read_value(&mut &bytes[..]).unwrap();
+        let map = value.as_map().unwrap();
+        assert!(map
+            .iter()
+            .any(|(k, v)| k.as_str() == Some("notes") && v.as_slice() == Some(&b"hi"[..])));
+
+        let err = op
+            .encode_input(r#"{"customer_id": "c1", "quantity": "three"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown field `customer_id`, did you mean `customerId`?"));
+        assert!(err.contains("missing required field `customerId`"));
+        assert!(err.contains("$.quantity: expected a 32-bit integer"));
+    }
+
+    #[test]
+    fn test_operation_lookup_and_output() {
+        let schema = load_schema();
+        assert!(schema.operation("Orders.Missing").is_err());
+
+        let ping = schema.operation("Ping").unwrap();
+        assert!(ping.encode_input("").unwrap().is_empty());
+        assert!(ping.encode_input(r#"{"a": 1}"#).is_err());
+
+        let op = schema.operation("PlaceOrder").unwrap();
+        let response = wasmbus_rpc::common::serialize(&json!({"orderId": "o-1"})).unwrap();
+        assert_eq!(
+            op.decode_output(&response, 'b').unwrap(),
+            json!({"orderId": "o-1"})
+        );
+    }
+}
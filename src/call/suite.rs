@@ -0,0 +1,500 @@
+//! Scripted call suites for `wash call`, which send a series of invocations described in a
+//! YAML or JSON file to an actor and check each response against an expectation
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use wash_lib::cli::CommandOutput;
+use wasmbus_rpc::core::WasmCloudEntity;
+
+use super::{decode_response, encode_payload, CallCommand, CallSchema, LatticeClient};
+
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct SuiteOpts {
+    /// Run the invocations described in this YAML or JSON suite file against the actor and
+    /// report which of them returned the expected response. wash exits with a non-zero code if
+    /// any of them failed. The operation and payload arguments are not used when running a suite
+    #[clap(long = "suite")]
+    pub(crate) suite: Option<PathBuf>,
+
+    /// Format of the suite report, either "tap" or "junit"
+    #[clap(long = "suite-format", default_value = "tap")]
+    pub(crate) format: ReportFormat,
+
+    /// Write the suite report to this file instead of printing it
+    #[clap(long = "suite-report")]
+    pub(crate) report: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub(crate) enum ReportFormat {
+    #[default]
+    Tap,
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tap" => Ok(ReportFormat::Tap),
+            "junit" => Ok(ReportFormat::Junit),
+            _ => bail!("suite format may be either 'tap' or 'junit'"),
+        }
+    }
+}
+
+/// A suite of invocations, loaded from a YAML or JSON file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Suite {
+    /// Name of the suite, defaults to the file name
+    #[serde(default)]
+    name: Option<String>,
+    tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestCase {
+    name: String,
+    operation: String,
+    #[serde(default)]
+    payload: JsonValue,
+    #[serde(default)]
+    expect: Expectation,
+}
+
+/// What a test case expects the actor to return. With no expectations, a test passes as long
+/// as the invocation succeeds
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expectation {
+    /// Expected response. Objects in the response may contain fields that are not listed here,
+    /// unless `exact` is set
+    #[serde(default)]
+    response: Option<JsonValue>,
+    /// Require the response to equal `response` exactly
+    #[serde(default)]
+    exact: bool,
+    /// Regular expressions that fields of the response must match, keyed by a path such as
+    /// `items[0].name`. Values that are not strings are matched in their JSON form
+    #[serde(default)]
+    matches: BTreeMap<String, String>,
+    /// Regular expression the error message must match. If set, the invocation must fail
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+struct TestResult {
+    name: String,
+    operation: String,
+    duration: Duration,
+    failures: Vec<String>,
+}
+
+impl Suite {
+    fn from_path(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read suite file {}", path.display()))?;
+        let mut suite: Suite = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)?,
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => bail!("suite file must be a .yaml, .yml, or .json file"),
+        };
+        if suite.name.is_none() {
+            suite.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+        }
+        Ok(suite)
+    }
+}
+
+/// Runs every test case in the suite file against the actor and renders the report
+pub(crate) async fn run(cmd: CallCommand, schema: Option<&CallSchema>) -> Result<CommandOutput> {
+    let path = cmd.suite.suite.clone().context("--suite is required")?;
+    if !"bs2".contains(cmd.bin) {
+        bail!("'bin' parameter must be 'b', 's', or '2'");
+    }
+    let suite = Suite::from_path(&path)?;
+    let suite_name = suite.name.clone().unwrap_or_default();
+    let (format, report_path, bin) = (cmd.suite.format, cmd.suite.report.clone(), cmd.bin);
+
+    let lattice = LatticeClient::connect(cmd.opts, cmd.cluster_seed).await?;
+    let target = lattice.resolve_actor(&cmd.actor_id).await?;
+
+    let mut results = Vec::with_capacity(suite.tests.len());
+    for test in suite.tests {
+        let started = Instant::now();
+        let failures = match run_test(&lattice, &target, schema, &test, bin).await {
+            Ok(failures) => failures,
+            Err(e) => vec![format!("{e:#}")],
+        };
+        results.push(TestResult {
+            name: test.name,
+            operation: test.operation,
+            duration: started.elapsed(),
+            failures,
+        });
+    }
+
+    let report = match format {
+        ReportFormat::Tap => render_tap(&results),
+        ReportFormat::Junit => render_junit(&suite_name, &results),
+    };
+    let failed = results.iter().filter(|r| !r.failures.is_empty()).count();
+    let passed = results.len() - failed;
+
+    let mut map = HashMap::new();
+    map.insert("suite".to_string(), json!(suite_name));
+    map.insert("passed".to_string(), json!(passed));
+    map.insert("failed".to_string(), json!(failed));
+    map.insert(
+        "results".to_string(),
+        json!(results
+            .iter()
+            .map(|r| json!({
+                "name": r.name,
+                "operation": r.operation,
+                "passed": r.failures.is_empty(),
+                "duration_ms": r.duration.as_secs_f64() * 1000.0,
+                "failures": r.failures,
+            }))
+            .collect::<Vec<_>>()),
+    );
+
+    let text = match report_path {
+        Some(report_path) => {
+            std::fs::write(&report_path, report).with_context(|| {
+                format!("Error writing suite report to {}", report_path.display())
+            })?;
+            map.insert("report".to_string(), json!(report_path));
+            format!(
+                "Suite {suite_name}: {passed} passed, {failed} failed. Report written to {}",
+                report_path.display()
+            )
+        }
+        None => {
+            map.insert("report".to_string(), json!(report));
+            report
+        }
+    };
+    // Failing tests fail the command, so CI can gate on the suite
+    Ok(CommandOutput::new(text, map).with_success(failed == 0))
+}
+
+/// Sends a single test case and returns the reasons it failed, if any
+async fn run_test(
+    lattice: &LatticeClient,
+    target: &WasmCloudEntity,
+    schema: Option<&CallSchema>,
+    test: &TestCase,
+    bin: char,
+) -> Result<Vec<String>> {
+    let operation = schema.map(|s| s.operation(&test.operation)).transpose()?;
+    let payload = match &test.payload {
+        JsonValue::Null => String::new(),
+        payload => payload.to_string(),
+    };
+    let bytes = encode_payload(&payload, operation.as_ref())?;
+
+    match lattice.call(target, &test.operation, &bytes).await {
+        Ok(response) => {
+            if test.expect.error.is_some() {
+                return Ok(vec![
+                    "expected the invocation to fail, but it succeeded".into()
+                ]);
+            }
+            let response = decode_response(&response, bin, operation.as_ref())?;
+            check_response(&test.expect, &response)
+        }
+        Err(e) => {
+            let message = format!("{e:#}");
+            match &test.expect.error {
+                Some(pattern) if compile(pattern)?.is_match(&message) => Ok(Vec::new()),
+                Some(pattern) => Ok(vec![format!(
+                    "error `{message}` does not match /{pattern}/"
+                )]),
+                None => Ok(vec![format!("invocation failed: {message}")]),
+            }
+        }
+    }
+}
+
+/// Compares a decoded response against the expectation, collecting every mismatch
+fn check_response(expect: &Expectation, response: &JsonValue) -> Result<Vec<String>> {
+    let mut failures = Vec::new();
+    if let Some(expected) = &expect.response {
+        compare("$", expected, response, expect.exact, &mut failures);
+    }
+    for (path, pattern) in expect.matches.iter() {
+        let re = compile(pattern)?;
+        match lookup(response, path)? {
+            Some(JsonValue::String(s)) if re.is_match(s) => {}
+            Some(value) if !value.is_string() && re.is_match(&value.to_string()) => {}
+            Some(value) => failures.push(format!("{path}: {value} does not match /{pattern}/")),
+            None => failures.push(format!("{path}: not found in the response")),
+        }
+    }
+    Ok(failures)
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("invalid regular expression /{pattern}/"))
+}
+
+/// Recursively compares expected and actual values. Unless `exact` is set, objects in the
+/// actual value may contain extra fields
+fn compare(
+    path: &str,
+    expected: &JsonValue,
+    actual: &JsonValue,
+    exact: bool,
+    failures: &mut Vec<String>,
+) {
+    match (expected, actual) {
+        (JsonValue::Object(expected), JsonValue::Object(actual)) => {
+            for (key, value) in expected.iter() {
+                let field = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual) => compare(&field, value, actual, exact, failures),
+                    None => failures.push(format!("{field}: missing from the response")),
+                }
+            }
+            if exact {
+                failures.extend(
+                    actual
+                        .keys()
+                        .filter(|key| !expected.contains_key(*key))
+                        .map(|key| format!("{path}.{key}: not expected in the response")),
+                );
+            }
+        }
+        (JsonValue::Array(expected), JsonValue::Array(actual)) => {
+            if expected.len() != actual.len() {
+                failures.push(format!(
+                    "{path}: expected {} items, got {}",
+                    expected.len(),
+                    actual.len()
+                ));
+                return;
+            }
+            for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+                compare(&format!("{path}[{i}]"), expected, actual, exact, failures);
+            }
+        }
+        // integers and floats that hold the same value are equal
+        (JsonValue::Number(e), JsonValue::Number(a)) if e.as_f64() == a.as_f64() => {}
+        (expected, actual) if expected == actual => {}
+        (expected, actual) => failures.push(format!("{path}: expected {expected}, got {actual}")),
+    }
+}
+
+/// Finds the value at a path like `items[0].name` (optionally starting with `$`)
+fn lookup<'v>(value: &'v JsonValue, path: &str) -> Result<Option<&'v JsonValue>> {
+    let mut current = value;
+    let path = path.strip_prefix('$').unwrap_or(path);
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (field, indexes) = match segment.find('[') {
+            Some(pos) => segment.split_at(pos),
+            None => (segment, ""),
+        };
+        if !field.is_empty() {
+            current = match current.get(field) {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+        }
+        for index in indexes.split('[').filter(|s| !s.is_empty()) {
+            let index = index
+                .strip_suffix(']')
+                .and_then(|i| i.parse::<usize>().ok())
+                .ok_or_else(|| anyhow!("invalid path `{path}`, indexes must look like `[0]`"))?;
+            current = match current.get(index) {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Renders results in the Test Anything Protocol (version 13)
+fn render_tap(results: &[TestResult]) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, result) in results.iter().enumerate() {
+        let status = if result.failures.is_empty() {
+            "ok"
+        } else {
+            "not ok"
+        };
+        let _ = writeln!(
+            out,
+            "{status} {} - {}",
+            i + 1,
+            result.name.replace('#', "\\#")
+        );
+        if !result.failures.is_empty() {
+            let _ = writeln!(out, "  ---\n  operation: {}\n  failures:", result.operation);
+            for failure in result.failures.iter() {
+                let _ = writeln!(out, "    - {}", serde_json::to_string(failure).unwrap());
+            }
+            out.push_str("  ...\n");
+        }
+    }
+    out
+}
+
+/// Renders results as a JUnit XML report with a single test suite
+fn render_junit(suite_name: &str, results: &[TestResult]) -> String {
+    let failed = results.iter().filter(|r| !r.failures.is_empty()).count();
+    let total_time: f64 = results.iter().map(|r| r.duration.as_secs_f64()).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{failed}\" time=\"{total_time:.3}\">",
+        xml_escape(suite_name),
+        results.len()
+    );
+    for result in results {
+        let _ = write!(
+            out,
+            "  <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            xml_escape(&result.name),
+            xml_escape(&result.operation),
+            result.duration.as_secs_f64()
+        );
+        if result.failures.is_empty() {
+            out.push_str("/>\n");
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>",
+            xml_escape(&result.failures[0]),
+            xml_escape(&result.failures.join("\n"))
+        );
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expectation(yaml: &str) -> Expectation {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_partial_and_exact_match() {
+        let response = json!({"id": 7, "items": [{"name": "widget", "qty": 2}], "note": null});
+
+        let expect = expectation("response: {id: 7.0, items: [{name: widget}]}");
+        assert!(check_response(&expect, &response).unwrap().is_empty());
+
+        let expect = expectation("response: {items: [{name: widget}]}\nexact: true");
+        let failures = check_response(&expect, &response).unwrap();
+        assert!(failures.contains(&"$.id: not expected in the response".to_string()));
+        assert!(failures.contains(&"$.items[0].qty: not expected in the response".to_string()));
+
+        let expect = expectation("response: {id: 8, items: []}");
+        assert_eq!(
+            check_response(&expect, &response).unwrap(),
+            vec![
+                "$.id: expected 8, got 7",
+                "$.items: expected 0 items, got 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_regexes() {
+        let response = json!({"id": 7, "items": [{"name": "widget"}]});
+        let expect = expectation("matches:\n  items[0].name: ^wid\n  $.id: '^\\d+$'");
+        assert!(check_response(&expect, &response).unwrap().is_empty());
+
+        let expect = expectation("matches:\n  items[1].name: .*\n  id: ^8");
+        assert_eq!(
+            check_response(&expect, &response).unwrap(),
+            vec![
+                "id: 7 does not match /^8/",
+                "items[1].name: not found in the response"
+            ]
+        );
+        assert!(lookup(&response, "items[x]").is_err());
+    }
+
+    #[test]
+    fn test_reports() {
+        let results = vec![
+            TestResult {
+                name: "places an order".into(),
+                operation: "Orders.PlaceOrder".into(),
+                duration: Duration::from_millis(12),
+                failures: Vec::new(),
+            },
+            TestResult {
+                name: "rejects <empty> orders".into(),
+                operation: "Orders.PlaceOrder".into(),
+                duration: Duration::from_millis(3),
+                failures: vec!["$.ok: expected false, got true".into()],
+            },
+        ];
+        assert_eq!(
+            render_tap(&results),
+            "TAP version 13\n1..2\nok 1 - places an order\nnot ok 2 - rejects <empty> orders\n  ---\n  operation: Orders.PlaceOrder\n  failures:\n    - \"$.ok: expected false, got true\"\n  ...\n"
+        );
+        let junit = render_junit("orders", &results);
+        assert!(junit.contains("<testsuite name=\"orders\" tests=\"2\" failures=\"1\""));
+        assert!(junit.contains(
+            "<testcase name=\"places an order\" classname=\"Orders.PlaceOrder\" time=\"0.012\"/>"
+        ));
+        assert!(junit.contains("name=\"rejects &lt;empty&gt; orders\""));
+        assert!(junit.contains("<failure message=\"$.ok: expected false, got true\">"));
+    }
+
+    #[test]
+    fn test_load_suite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.yaml");
+        std::fs::write(
+            &path,
+            "tests:\n  - name: ping\n    operation: Orders.Ping\n  - name: bad\n    operation: Orders.PlaceOrder\n    payload: {quantity: -1}\n    expect:\n      error: quantity\n",
+        )
+        .unwrap();
+        let suite = Suite::from_path(&path).unwrap();
+        assert_eq!(suite.name.as_deref(), Some("orders"));
+        assert_eq!(suite.tests.len(), 2);
+        assert_eq!(suite.tests[0].payload, JsonValue::Null);
+        assert_eq!(suite.tests[1].expect.error.as_deref(), Some("quantity"));
+
+        std::fs::write(
+            &path,
+            "tests:\n  - name: ping\n    operation: Ping\n    expected: {}\n",
+        )
+        .unwrap();
+        assert!(Suite::from_path(&path).is_err());
+    }
+}
@@ -1,26 +1,43 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::warn;
 use oci_distribution::{
     client::{Client, ClientConfig, ClientProtocol},
-    secrets::RegistryAuth,
+    manifest::{OciDescriptor, OciManifest, OCI_IMAGE_INDEX_MEDIA_TYPE, OCI_IMAGE_MEDIA_TYPE},
     Reference,
 };
 use serde_json::json;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use wash_lib::cli::{
+    inspect::render_actor_claims,
     labels_vec_to_hashmap,
-    registry::{RegistryCommand, RegistryPingCommand, RegistryPullCommand, RegistryPushCommand},
+    registry::{
+        RegistryCatalogCommand, RegistryCommand, RegistryCopyCommand, RegistryExportCommand,
+        RegistryImportCommand, RegistryInspectCommand, RegistryLoginCommand, RegistryLogoutCommand,
+        RegistryPingCommand, RegistryPullCommand, RegistryPushCommand, RegistryTagsCommand,
+    },
     CommandOutput, OutputKind,
 };
 use wash_lib::registry::{
-    pull_oci_artifact, push_oci_artifact, validate_artifact, OciPullOptions, OciPushOptions,
+    bundle::{is_wadm_manifest, pull_reference, push_app_bundle, AppBundle, PulledReference},
+    config_claims_jwt, copy_oci_artifact,
+    credentials::{CredentialStore, Credentials},
+    inspect_oci_manifest,
+    layout::{export_oci_layout, push_oci_layout, LayoutImage, OciExportOptions},
+    list_repositories, list_tags, push_oci_artifact, resolve_auth, validate_artifact,
+    verify_credentials, OciCopyOptions, OciPullOptions, OciPushOptions, OciRegistryOptions,
     SupportedArtifacts,
 };
 
+use wascap::jwt::{validate_token, Actor, Claims};
+
 use crate::appearance::spinner::Spinner;
+use crate::drain::format_size;
 
 pub(crate) const SHOWER_EMOJI: &str = "\u{1F6BF}";
 pub(crate) const PROVIDER_ARCHIVE_FILE_EXTENSION: &str = ".par.gz";
@@ -36,17 +53,34 @@ pub(crate) async fn registry_pull(
     let spinner = Spinner::new(&output_kind)?;
     spinner.update_spinner_message(format!(" Downloading {} ...", image.whole()));
 
-    let artifact = pull_oci_artifact(
-        artifact_url,
-        OciPullOptions {
-            digest: cmd.digest,
-            allow_latest: cmd.allow_latest,
-            user: cmd.opts.user,
-            password: cmd.opts.password,
-            insecure: cmd.opts.insecure,
-        },
-    )
-    .await?;
+    let options = OciPullOptions {
+        digest: cmd.digest,
+        allow_latest: cmd.allow_latest,
+        user: cmd.opts.user,
+        password: cmd.opts.password,
+        insecure: cmd.opts.insecure,
+        trust_policy: cmd.trust.into_policy()?,
+    };
+
+    let artifact = match pull_reference(&artifact_url, options).await? {
+        PulledReference::Artifact(artifact) => artifact,
+        // Application bundles are restored into a directory
+        PulledReference::Bundle(index) => {
+            let bundle_dir = cmd.destination.unwrap_or_else(|| ".".to_string());
+            check_bundle_destination(&bundle_dir)?;
+            let bundle = index.pull_into(&bundle_dir).await?;
+            spinner.finish_and_clear();
+            let manifest_path = bundle.manifest_path.clone().unwrap_or_default();
+            return Ok(app_bundle_output(
+                format!(
+                    "\n{SHOWER_EMOJI} Successfully pulled application {} into {bundle_dir}\n  Manifest: {}",
+                    bundle.name,
+                    manifest_path.display()
+                ),
+                bundle,
+            ));
+        }
+    };
 
     let outfile = write_artifact(&artifact, &image, cmd.destination).await?;
 
@@ -60,6 +94,52 @@ pub(crate) async fn registry_pull(
     ))
 }
 
+/// Application bundles are pulled into a directory, so refuse destinations that are, or are named
+/// like, an artifact or manifest file
+fn check_bundle_destination(destination: &str) -> Result<()> {
+    let path = Path::new(destination);
+    let is_file_name = [
+        WASM_FILE_EXTENSION,
+        PROVIDER_ARCHIVE_FILE_EXTENSION,
+        ".yaml",
+        ".yml",
+    ]
+    .iter()
+    .any(|extension| destination.ends_with(extension));
+    if (path.exists() && !path.is_dir()) || (!path.exists() && is_file_name) {
+        bail!("{destination} is a file, but application bundles are pulled into a directory. Use --destination to pass a directory");
+    }
+    Ok(())
+}
+
+/// Renders the result of pushing or pulling an application bundle, listing its components
+/// below the summary
+fn app_bundle_output(summary: String, bundle: AppBundle) -> CommandOutput {
+    let mut text = format!("{summary}\n  Digest: {}", bundle.digest);
+    for component in bundle.components.iter() {
+        text.push_str(&format!("\n  {}: {}", component.name, component.reference));
+        if let Some(path) = component.path.as_ref() {
+            text.push_str(&format!(" ({})", path.display()));
+        }
+    }
+
+    let mut map = HashMap::new();
+    map.insert("url".to_string(), json!(bundle.reference));
+    map.insert("digest".to_string(), json!(bundle.digest));
+    map.insert("name".to_string(), json!(bundle.name));
+    map.insert("version".to_string(), json!(bundle.version));
+    map.insert("manifest".to_string(), json!(bundle.manifest_path));
+    map.insert(
+        "components".to_string(),
+        json!(bundle
+            .components
+            .iter()
+            .map(|c| json!({"name": c.name, "reference": c.reference, "path": c.path}))
+            .collect::<Vec<_>>()),
+    );
+    CommandOutput::new(text, map)
+}
+
 pub(crate) async fn registry_ping(cmd: RegistryPingCommand) -> Result<CommandOutput> {
     let image: Reference = cmd.url.parse()?;
     let mut client = Client::new(ClientConfig {
@@ -70,14 +150,370 @@ pub(crate) async fn registry_ping(cmd: RegistryPingCommand) -> Result<CommandOut
         },
         ..Default::default()
     });
-    let auth = match (cmd.opts.user, cmd.opts.password) {
-        (Some(user), Some(password)) => RegistryAuth::Basic(user, password),
-        _ => RegistryAuth::Anonymous,
-    };
+    let auth = resolve_auth(&image, cmd.opts.user, cmd.opts.password).await;
     let (_, _) = client.pull_manifest(&image, &auth).await?;
     Ok(CommandOutput::from("Pong!"))
 }
 
+pub(crate) async fn registry_tags(cmd: RegistryTagsCommand) -> Result<CommandOutput> {
+    let mut tags = list_tags(
+        &cmd.url,
+        OciRegistryOptions {
+            user: cmd.opts.user,
+            password: cmd.opts.password,
+            insecure: cmd.opts.insecure,
+        },
+    )
+    .await?;
+    sort_tags(&mut tags);
+
+    let text = if tags.is_empty() {
+        format!("No tags found for {}", cmd.url)
+    } else {
+        tags.join("\n")
+    };
+    let mut map = HashMap::new();
+    map.insert("tags".to_string(), json!(tags));
+    Ok(CommandOutput::new(text, map))
+}
+
+pub(crate) async fn registry_catalog(cmd: RegistryCatalogCommand) -> Result<CommandOutput> {
+    let mut repositories = list_repositories(
+        &cmd.registry,
+        OciRegistryOptions {
+            user: cmd.opts.user,
+            password: cmd.opts.password,
+            insecure: cmd.opts.insecure,
+        },
+    )
+    .await?;
+    repositories.sort();
+
+    let text = if repositories.is_empty() {
+        format!("No repositories found in {}", cmd.registry)
+    } else {
+        repositories.join("\n")
+    };
+    let mut map = HashMap::new();
+    map.insert("repositories".to_string(), json!(repositories));
+    Ok(CommandOutput::new(text, map))
+}
+
+/// Sorts tags by semantic version (ignoring a leading `v`), oldest first. Tags that aren't
+/// versions, like `latest`, are sorted alphabetically after them
+fn sort_tags(tags: &mut [String]) {
+    let version = |tag: &str| semver::Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok();
+    tags.sort_by(|a, b| match (version(a), version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
+}
+
+pub(crate) async fn registry_login(cmd: RegistryLoginCommand) -> Result<CommandOutput> {
+    let password = match cmd.password {
+        Some(password) => password,
+        None if cmd.password_stdin => {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password)?;
+            password.trim_end_matches(['\r', '\n']).to_string()
+        }
+        None => dialoguer::Password::new()
+            .with_prompt(format!("Password for {}", cmd.user))
+            .interact()?,
+    };
+    let credentials = Credentials {
+        username: cmd.user,
+        password,
+    };
+    if !cmd.no_verify {
+        verify_credentials(&cmd.registry, &credentials, cmd.insecure).await?;
+    }
+    let store = CredentialStore::default_store()?;
+    store.save(&cmd.registry, &credentials)?;
+
+    let mut map = HashMap::new();
+    map.insert("registry".to_string(), json!(cmd.registry));
+    map.insert("credentials_file".to_string(), json!(store.path()));
+    Ok(CommandOutput::new(
+        format!(
+            "Logged in to {}, credentials stored in {}",
+            cmd.registry,
+            store.path().display()
+        ),
+        map,
+    ))
+}
+
+pub(crate) async fn registry_logout(cmd: RegistryLogoutCommand) -> Result<CommandOutput> {
+    let removed = CredentialStore::default_store()?.remove(&cmd.registry)?;
+    let mut map = HashMap::new();
+    map.insert("registry".to_string(), json!(cmd.registry));
+    map.insert("removed".to_string(), json!(removed));
+    Ok(CommandOutput::new(
+        if removed {
+            format!("Removed the stored credentials for {}", cmd.registry)
+        } else {
+            format!("No credentials were stored for {}", cmd.registry)
+        },
+        map,
+    ))
+}
+
+pub(crate) async fn registry_copy(
+    cmd: RegistryCopyCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    let spinner = Spinner::new(&output_kind)?;
+    spinner.update_spinner_message(format!(
+        " Copying {} to {} ...",
+        cmd.source, cmd.destination
+    ));
+
+    let result = copy_oci_artifact(
+        &cmd.source,
+        &cmd.destination,
+        OciCopyOptions {
+            extra_tags: cmd.tags,
+            allow_latest: cmd.allow_latest,
+            source: OciRegistryOptions {
+                user: cmd.source_user,
+                password: cmd.source_password,
+                insecure: cmd.source_insecure,
+            },
+            destination: OciRegistryOptions {
+                user: cmd.destination_user,
+                password: cmd.destination_password,
+                insecure: cmd.destination_insecure,
+            },
+        },
+    )
+    .await?;
+
+    spinner.finish_and_clear();
+
+    let mut text = format!(
+        "{SHOWER_EMOJI} Successfully copied {} to {}\n  Digest: {}",
+        cmd.source,
+        result.references.join(", "),
+        result.source_digest
+    );
+    if result.destination_digest != result.source_digest {
+        text.push_str(&format!(
+            "\n  Destination digest: {}",
+            result.destination_digest
+        ));
+    }
+    let mut map = HashMap::new();
+    map.insert("source".to_string(), json!(cmd.source));
+    map.insert("references".to_string(), json!(result.references));
+    map.insert("source_digest".to_string(), json!(result.source_digest));
+    map.insert(
+        "destination_digest".to_string(),
+        json!(result.destination_digest),
+    );
+    Ok(CommandOutput::new(text, map))
+}
+
+pub(crate) async fn registry_export(
+    cmd: RegistryExportCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    let spinner = Spinner::new(&output_kind)?;
+    spinner.update_spinner_message(format!(
+        " Exporting {} artifact(s) to {} ...",
+        cmd.urls.len(),
+        cmd.destination.display()
+    ));
+
+    let images = export_oci_layout(
+        &cmd.urls,
+        &cmd.destination,
+        OciExportOptions {
+            allow_latest: cmd.allow_latest,
+            registry: OciRegistryOptions {
+                user: cmd.opts.user,
+                password: cmd.opts.password,
+                insecure: cmd.opts.insecure,
+            },
+        },
+    )
+    .await?;
+
+    spinner.finish_and_clear();
+
+    let text = format!(
+        "{SHOWER_EMOJI} Successfully exported to {}{}",
+        cmd.destination.display(),
+        layout_images_text(&images)
+    );
+    let mut map = HashMap::new();
+    map.insert("destination".to_string(), json!(cmd.destination));
+    map.insert("artifacts".to_string(), layout_images_json(&images));
+    Ok(CommandOutput::new(text, map))
+}
+
+pub(crate) async fn registry_import(
+    cmd: RegistryImportCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    let spinner = Spinner::new(&output_kind)?;
+    let registry = cmd.registry;
+    if registry.starts_with("localhost:") && !cmd.opts.insecure {
+        warn!(" Unless an SSL certificate has been installed, pushing to localhost without the --insecure option will fail")
+    }
+    spinner.update_spinner_message(format!(
+        " Importing {} into {registry} ...",
+        cmd.bundle.display()
+    ));
+    let images = push_oci_layout(
+        &cmd.bundle,
+        &registry,
+        OciRegistryOptions {
+            user: cmd.opts.user,
+            password: cmd.opts.password,
+            insecure: cmd.opts.insecure,
+        },
+    )
+    .await?;
+
+    spinner.finish_and_clear();
+
+    let text = format!(
+        "{SHOWER_EMOJI} Successfully imported {} into {registry}{}",
+        cmd.bundle.display(),
+        layout_images_text(&images)
+    );
+    let mut map = HashMap::new();
+    map.insert("bundle".to_string(), json!(cmd.bundle));
+    map.insert("target".to_string(), json!(registry));
+    map.insert("artifacts".to_string(), layout_images_json(&images));
+    Ok(CommandOutput::new(text, map))
+}
+
+fn layout_images_text(images: &[LayoutImage]) -> String {
+    images
+        .iter()
+        .map(|image| format!("\n  {} ({})", image.reference, image.digest))
+        .collect()
+}
+
+fn layout_images_json(images: &[LayoutImage]) -> serde_json::Value {
+    json!(images
+        .iter()
+        .map(|image| json!({
+            "reference": image.reference,
+            "digest": image.digest,
+            "size": image.size,
+        }))
+        .collect::<Vec<_>>())
+}
+
+pub(crate) async fn registry_inspect(cmd: RegistryInspectCommand) -> Result<CommandOutput> {
+    let info = inspect_oci_manifest(
+        &cmd.url,
+        OciRegistryOptions {
+            user: cmd.opts.user,
+            password: cmd.opts.password,
+            insecure: cmd.opts.insecure,
+        },
+    )
+    .await?;
+
+    let mut map = HashMap::new();
+    map.insert("url".to_string(), json!(cmd.url));
+    map.insert("digest".to_string(), json!(info.digest));
+    let mut text = format!("Reference: {}\nDigest: {}", cmd.url, info.digest);
+    match &info.manifest {
+        OciManifest::Image(manifest) => {
+            let media_type = manifest
+                .media_type
+                .as_deref()
+                .unwrap_or(OCI_IMAGE_MEDIA_TYPE);
+            text.push_str(&format!(
+                "\nMedia type: {media_type}\nConfig: {}",
+                descriptor_text(&manifest.config)
+            ));
+            text.push_str("\nLayers:");
+            for layer in manifest.layers.iter() {
+                text.push_str(&format!("\n  {}", descriptor_text(layer)));
+            }
+            text.push_str(&annotations_text(manifest.annotations.as_ref()));
+            map.insert("media_type".to_string(), json!(media_type));
+            map.insert("config".to_string(), json!(manifest.config));
+            map.insert("layers".to_string(), json!(manifest.layers));
+            map.insert("annotations".to_string(), json!(manifest.annotations));
+        }
+        OciManifest::ImageIndex(index) => {
+            let media_type = index
+                .media_type
+                .as_deref()
+                .unwrap_or(OCI_IMAGE_INDEX_MEDIA_TYPE);
+            text.push_str(&format!("\nMedia type: {media_type}\nManifests:"));
+            for entry in index.manifests.iter() {
+                text.push_str(&format!(
+                    "\n  {} {} ({})",
+                    entry.media_type,
+                    entry.digest,
+                    format_size(entry.size.max(0) as u64)
+                ));
+            }
+            text.push_str(&annotations_text(index.annotations.as_ref()));
+            map.insert("media_type".to_string(), json!(media_type));
+            map.insert("manifests".to_string(), json!(index.manifests));
+            map.insert("annotations".to_string(), json!(index.annotations));
+        }
+    }
+
+    match info.config.as_deref().and_then(config_claims_jwt) {
+        Some(jwt) => {
+            let claims = render_actor_claims(
+                Claims::<Actor>::decode(&jwt)?,
+                validate_token::<Actor>(&jwt)?,
+            );
+            text.push_str(&format!("\n\n{}", claims.text));
+            map.insert("claims".to_string(), json!(claims.map));
+        }
+        None => {
+            map.insert("claims".to_string(), serde_json::Value::Null);
+        }
+    }
+    Ok(CommandOutput::new(text, map))
+}
+
+fn descriptor_text(descriptor: &OciDescriptor) -> String {
+    let mut text = format!(
+        "{} {} ({})",
+        descriptor.media_type,
+        descriptor.digest,
+        format_size(descriptor.size.max(0) as u64)
+    );
+    if let Some(annotations) = descriptor.annotations.as_ref() {
+        let mut annotations = annotations.iter().collect::<Vec<_>>();
+        annotations.sort();
+        for (key, value) in annotations {
+            text.push_str(&format!("\n    {key}: {value}"));
+        }
+    }
+    text
+}
+
+fn annotations_text(annotations: Option<&HashMap<String, String>>) -> String {
+    let mut annotations = annotations
+        .map(|a| a.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+    if annotations.is_empty() {
+        return "\nAnnotations: None".to_string();
+    }
+    annotations.sort();
+    let mut text = "\nAnnotations:".to_string();
+    for (key, value) in annotations {
+        text.push_str(&format!("\n  {key}: {value}"));
+    }
+    text
+}
+
 pub(crate) async fn write_artifact(
     artifact: &[u8],
     image: &Reference,
@@ -120,25 +556,39 @@ pub(crate) async fn registry_push(
     spinner.update_spinner_message(format!(" Pushing {} to {} ...", cmd.artifact, artifact_url));
 
     let annotations = labels_vec_to_hashmap(cmd.annotations.unwrap_or_default())?;
+    let options = OciPushOptions {
+        config: cmd.config.map(PathBuf::from),
+        allow_latest: cmd.allow_latest,
+        user: cmd.opts.user,
+        password: cmd.opts.password,
+        insecure: cmd.opts.insecure,
+        annotations: Some(annotations),
+    };
 
-    push_oci_artifact(
-        artifact_url.clone(),
-        cmd.artifact,
-        OciPushOptions {
-            config: cmd.config.map(PathBuf::from),
-            allow_latest: cmd.allow_latest,
-            user: cmd.opts.user,
-            password: cmd.opts.password,
-            insecure: cmd.opts.insecure,
-            annotations: Some(annotations),
-        },
-    )
-    .await?;
+    // wadm manifests are pushed as an application bundle along with their local artifacts
+    let is_manifest = tokio::fs::read(&cmd.artifact)
+        .await
+        .map(|data| is_wadm_manifest(&data))
+        .unwrap_or(false);
+    if is_manifest {
+        let bundle = push_app_bundle(&artifact_url, &cmd.artifact, options).await?;
+        spinner.finish_and_clear();
+        return Ok(app_bundle_output(
+            format!(
+                "{SHOWER_EMOJI} Successfully pushed application {} to {artifact_url}",
+                bundle.name
+            ),
+            bundle,
+        ));
+    }
+
+    let digest = push_oci_artifact(artifact_url.clone(), cmd.artifact, options).await?;
 
     spinner.finish_and_clear();
 
     let mut map = HashMap::new();
     map.insert("url".to_string(), json!(cmd.url));
+    map.insert("digest".to_string(), json!(digest));
     Ok(CommandOutput::new(
         format!("{SHOWER_EMOJI} Successfully validated and pushed to {artifact_url}"),
         map,
@@ -159,13 +609,26 @@ pub(crate) async fn handle_command(
             registry_push(cmd, output_kind).await
         }
         RegistryCommand::Ping(cmd) => registry_ping(cmd).await,
+        RegistryCommand::Tags(cmd) => registry_tags(cmd).await,
+        RegistryCommand::Catalog(cmd) => registry_catalog(cmd).await,
+        RegistryCommand::Login(cmd) => registry_login(cmd).await,
+        RegistryCommand::Logout(cmd) => registry_logout(cmd).await,
+        RegistryCommand::Copy(cmd) => registry_copy(cmd, output_kind).await,
+        RegistryCommand::Export(cmd) => registry_export(cmd, output_kind).await,
+        RegistryCommand::Import(cmd) => registry_import(cmd, output_kind).await,
+        RegistryCommand::Inspect(cmd) => registry_inspect(cmd).await,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::common::registry_cmd::{RegistryCommand, RegistryPullCommand, RegistryPushCommand};
+    use crate::common::registry_cmd::{
+        check_bundle_destination, sort_tags, RegistryCommand, RegistryCopyCommand,
+        RegistryExportCommand, RegistryImportCommand, RegistryInspectCommand, RegistryPullCommand,
+        RegistryPushCommand, RegistryTagsCommand,
+    };
     use clap::Parser;
+    use std::path::PathBuf;
 
     const ECHO_WASM: &str = "wasmcloud.azurecr.io/echo:0.2.0";
     const LOCAL_REGISTRY: &str = "localhost:5001";
@@ -200,6 +663,10 @@ mod tests {
             "password",
             "--user",
             "user",
+            "--trusted-issuer",
+            "ACOJJN6WUP4ODD75XEBKKTCCUJJCY5ZKQ56XVKYK4BEJWGVAOOQHZMCW",
+            "--required-tag",
+            "prod",
         ])
         .unwrap();
         match pull_basic.reg {
@@ -229,6 +696,7 @@ mod tests {
                 destination,
                 digest,
                 opts,
+                trust,
                 ..
             }) => {
                 assert_eq!(url, ECHO_WASM);
@@ -239,6 +707,11 @@ mod tests {
                 );
                 assert_eq!(opts.user.unwrap(), "user");
                 assert_eq!(opts.password.unwrap(), "password");
+                assert_eq!(
+                    trust.trusted_issuers,
+                    vec!["ACOJJN6WUP4ODD75XEBKKTCCUJJCY5ZKQ56XVKYK4BEJWGVAOOQHZMCW"]
+                );
+                assert_eq!(trust.required_tags, vec!["prod"]);
             }
             _ => panic!("`reg pull` constructed incorrect command"),
         };
@@ -340,4 +813,166 @@ mod tests {
             _ => panic!("`reg push` constructed incorrect command"),
         };
     }
+
+    #[test]
+    fn test_tags() {
+        let tags: Cmd = Parser::try_parse_from([
+            "reg",
+            "tags",
+            "localhost:5001/echo",
+            "--insecure",
+            "--user",
+            "user",
+        ])
+        .unwrap();
+        match tags.reg {
+            RegistryCommand::Tags(RegistryTagsCommand { url, opts }) => {
+                assert_eq!(url, "localhost:5001/echo");
+                assert!(opts.insecure);
+                assert_eq!(opts.user.unwrap(), "user");
+            }
+            _ => panic!("`reg tags` constructed incorrect command"),
+        };
+
+        let mut tags = [
+            "latest",
+            "v0.10.0",
+            "0.2.0",
+            "0.10.0-rc.1",
+            "canary",
+            "0.9.1",
+        ]
+        .map(String::from);
+        sort_tags(&mut tags);
+        assert_eq!(
+            tags,
+            [
+                "0.2.0",
+                "0.9.1",
+                "0.10.0-rc.1",
+                "v0.10.0",
+                "canary",
+                "latest"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let copy: Cmd = Parser::try_parse_from([
+            "reg",
+            "copy",
+            ECHO_WASM,
+            &format!("{LOCAL_REGISTRY}/echo:0.2.0"),
+            "--tag",
+            "stable",
+            "--tag",
+            "0.2",
+            "--source-user",
+            "staging",
+            "--destination-insecure",
+        ])
+        .unwrap();
+        match copy.reg {
+            RegistryCommand::Copy(RegistryCopyCommand {
+                source,
+                destination,
+                tags,
+                source_user,
+                source_insecure,
+                destination_user,
+                destination_insecure,
+                ..
+            }) => {
+                assert_eq!(source, ECHO_WASM);
+                assert_eq!(destination, format!("{LOCAL_REGISTRY}/echo:0.2.0"));
+                assert_eq!(tags, vec!["stable", "0.2"]);
+                assert_eq!(source_user.unwrap(), "staging");
+                assert!(!source_insecure);
+                assert!(destination_user.is_none());
+                assert!(destination_insecure);
+            }
+            _ => panic!("`reg copy` constructed incorrect command"),
+        };
+    }
+
+    #[test]
+    fn test_export_import() {
+        let export: Cmd = Parser::try_parse_from([
+            "reg",
+            "export",
+            ECHO_WASM,
+            &format!("{LOCAL_REGISTRY}/httpserver:0.17.0"),
+            "--destination",
+            "bundle.tar.gz",
+            "--insecure",
+        ])
+        .unwrap();
+        match export.reg {
+            RegistryCommand::Export(RegistryExportCommand {
+                urls,
+                destination,
+                allow_latest,
+                opts,
+            }) => {
+                assert_eq!(
+                    urls,
+                    vec![
+                        ECHO_WASM.to_string(),
+                        format!("{LOCAL_REGISTRY}/httpserver:0.17.0")
+                    ]
+                );
+                assert_eq!(destination, PathBuf::from("bundle.tar.gz"));
+                assert!(!allow_latest);
+                assert!(opts.insecure);
+            }
+            _ => panic!("`reg export` constructed incorrect command"),
+        };
+        assert!(Cmd::try_parse_from(["reg", "export", "-d", "bundle"]).is_err());
+
+        let import: Cmd = Parser::try_parse_from([
+            "reg",
+            "import",
+            "bundle.tar.gz",
+            "--registry",
+            LOCAL_REGISTRY,
+        ])
+        .unwrap();
+        match import.reg {
+            RegistryCommand::Import(RegistryImportCommand {
+                bundle, registry, ..
+            }) => {
+                assert_eq!(bundle, PathBuf::from("bundle.tar.gz"));
+                assert_eq!(registry, LOCAL_REGISTRY);
+            }
+            _ => panic!("`reg import` constructed incorrect command"),
+        };
+    }
+
+    #[test]
+    fn test_inspect() {
+        let inspect: Cmd =
+            Parser::try_parse_from(["reg", "inspect", ECHO_WASM, "--user", "name"]).unwrap();
+        match inspect.reg {
+            RegistryCommand::Inspect(RegistryInspectCommand { url, opts }) => {
+                assert_eq!(url, ECHO_WASM);
+                assert_eq!(opts.user.unwrap(), "name");
+                assert!(!opts.insecure);
+            }
+            _ => panic!("`reg inspect` constructed incorrect command"),
+        };
+    }
+
+    #[test]
+    fn test_check_bundle_destination() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("echo");
+        std::fs::write(&file, b"").unwrap();
+
+        assert!(check_bundle_destination(tempdir.path().to_str().unwrap()).is_ok());
+        assert!(check_bundle_destination("./app").is_ok());
+        assert!(check_bundle_destination(file.to_str().unwrap()).is_err());
+        assert!(check_bundle_destination("./echo.wasm").is_err());
+        assert!(check_bundle_destination("./app.yaml").is_err());
+    }
 }
@@ -16,6 +16,7 @@ use wash_lib::{
     },
     context::{fs::ContextDir, ContextManager, WashContext, HOST_CONFIG_NAME},
     id::ClusterSeed,
+    registry::policy::TrustPolicy,
 };
 
 use wash_lib::{
@@ -390,6 +391,7 @@ fn prompt_for_context() -> Result<WashContext> {
         rpc_seed,
         rpc_credsfile: rpc_credsfile.map(PathBuf::from),
         rpc_timeout: rpc_timeout.parse()?,
+        trust_policy: TrustPolicy::default(),
     })
 }
 
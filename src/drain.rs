@@ -6,13 +6,44 @@ use wash_lib::cli::CommandOutput;
 use wash_lib::drain::Drain;
 
 pub(crate) fn handle_command(cmd: Drain) -> Result<CommandOutput> {
+    let usage = cmd.usage()?;
     let paths = cmd.drain()?;
+    let freed: u64 = usage.iter().map(|(_, size)| size).sum();
+    let mut text = format!("Successfully cleared caches at: {paths:?}");
+    for (path, size) in usage.iter() {
+        text.push_str(&format!("\n  {}: {}", path.display(), format_size(*size)));
+    }
+    text.push_str(&format!("\nFreed {} in total", format_size(freed)));
+
     let mut map = HashMap::new();
     map.insert("drained".to_string(), json!(paths));
-    Ok(CommandOutput::new(
-        format!("Successfully cleared caches at: {paths:?}"),
-        map,
-    ))
+    map.insert(
+        "usage".to_string(),
+        json!(usage
+            .iter()
+            .map(|(path, size)| json!({"path": path, "bytes": size}))
+            .collect::<Vec<_>>()),
+    );
+    map.insert("freed_bytes".to_string(), json!(freed));
+    Ok(CommandOutput::new(text, map))
+}
+
+/// Formats a number of bytes with a binary unit, e.g. 1.5 MiB
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
 }
 
 #[cfg(test)]
@@ -26,6 +57,13 @@ mod test {
         drain: Drain,
     }
 
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
     #[test]
     // Enumerates all options of drain subcommands to ensure
     // changes are not made to the drain API
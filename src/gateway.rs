@@ -0,0 +1,256 @@
+//! A local HTTP gateway that forwards JSON requests to actors as RPC invocations, so actors
+//! can be exercised with any HTTP client
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use hyper::{
+    header::{ALLOW, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{debug, info};
+use serde_json::{json, Value as JsonValue};
+use wash_lib::cli::{CommandOutput, OutputKind};
+use wash_lib::id::ClusterSeed;
+
+use crate::call::{
+    decode_response, encode_payload, CallSchema, ConnectionOpts, LatticeClient, SchemaOpts,
+};
+
+#[derive(Debug, Clone, Parser)]
+pub(crate) struct GatewayCommand {
+    #[clap(flatten)]
+    opts: ConnectionOpts,
+
+    #[clap(flatten)]
+    schema: SchemaOpts,
+
+    /// Address the gateway listens on
+    #[clap(long = "address", default_value = "127.0.0.1")]
+    address: String,
+
+    /// Port the gateway listens on
+    #[clap(long = "port", default_value_t = 8087)]
+    port: u16,
+
+    /// Display binary in responses as binary('b'), string('s'), or both('2')
+    #[clap(long, default_value = "b")]
+    bin: char,
+
+    /// wasmCloud host cluster seed. This cluster seed must match the cluster seed used to
+    /// launch the wasmCloud host in order to pass antiforgery checks made by the host
+    /// This is only optional if a default context is available or a context is provided
+    #[clap(
+        short = 'c',
+        long = "cluster-seed",
+        env = "WASMCLOUD_CLUSTER_SEED",
+        value_parser
+    )]
+    cluster_seed: Option<ClusterSeed>,
+}
+
+struct Gateway {
+    lattice: LatticeClient,
+    schema: Option<CallSchema>,
+    bin: char,
+}
+
+/// An error returned to the HTTP client as `{"error": "..."}`
+struct GatewayError {
+    status: StatusCode,
+    message: String,
+}
+
+impl GatewayError {
+    fn new(status: StatusCode, error: impl std::fmt::Display) -> Self {
+        GatewayError {
+            status,
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+pub(crate) async fn handle_command(
+    cmd: GatewayCommand,
+    output_kind: OutputKind,
+) -> Result<CommandOutput> {
+    if !"bs2".contains(cmd.bin) {
+        bail!("'bin' parameter must be 'b', 's', or '2'");
+    }
+    let addr: SocketAddr = format!("{}:{}", cmd.address, cmd.port)
+        .parse()
+        .with_context(|| format!("invalid gateway address {}:{}", cmd.address, cmd.port))?;
+    let gateway = Arc::new(Gateway {
+        schema: CallSchema::from_opts(&cmd.schema)?,
+        lattice: LatticeClient::connect(cmd.opts, cmd.cluster_seed).await?,
+        bin: cmd.bin,
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let gateway = gateway.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let gateway = gateway.clone();
+                async move { Ok::<_, Infallible>(gateway.handle(req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("Unable to listen on {addr}"))?
+        .serve(make_service);
+
+    if output_kind != OutputKind::Json {
+        println!("🚪 Gateway listening on http://{addr}, send requests to POST /actors/{{actor}}/{{operation}}");
+        println!("🛑 Press `CTRL+c` at any time to exit");
+    }
+    server
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("Gateway server failed")?;
+
+    let mut map = HashMap::new();
+    map.insert("address".to_string(), json!(addr.to_string()));
+    Ok(CommandOutput::new("Gateway stopped", map))
+}
+
+impl Gateway {
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let (method, path) = (req.method().clone(), req.uri().path().to_string());
+        let (status, body) = match self.invoke(req).await {
+            Ok(response) => (StatusCode::OK, response),
+            Err(e) => (e.status, json!({ "error": e.message })),
+        };
+        info!("{method} {path} {}", status.as_u16());
+
+        let mut response = Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json");
+        if status == StatusCode::METHOD_NOT_ALLOWED {
+            response = response.header(ALLOW, "POST");
+        }
+        // unwrap is safe, the status and headers are always valid
+        response.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn invoke(&self, req: Request<Body>) -> Result<JsonValue, GatewayError> {
+        let (actor, operation) = parse_path(req.uri().path()).ok_or_else(|| {
+            GatewayError::new(
+                StatusCode::NOT_FOUND,
+                "requests must be sent to /actors/{actor}/{operation}",
+            )
+        })?;
+        if req.method() != Method::POST {
+            return Err(GatewayError::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "only POST requests are supported",
+            ));
+        }
+        let body = hyper::body::to_bytes(req.into_body())
+            .await
+            .map_err(|e| GatewayError::new(StatusCode::BAD_REQUEST, e))?;
+        let body = std::str::from_utf8(&body).map_err(|_| {
+            GatewayError::new(StatusCode::BAD_REQUEST, "request body must be UTF-8 JSON")
+        })?;
+        debug!("invoking {operation} on actor {actor} with payload {body}");
+
+        let schema = self
+            .schema
+            .as_ref()
+            .map(|schema| schema.operation(&operation))
+            .transpose()
+            .map_err(|e| GatewayError::new(StatusCode::NOT_FOUND, e))?;
+        let payload = encode_payload(body, schema.as_ref())
+            .map_err(|e| GatewayError::new(StatusCode::BAD_REQUEST, e))?;
+        let target = self
+            .lattice
+            .resolve_actor(&actor)
+            .await
+            .map_err(|e| GatewayError::new(StatusCode::NOT_FOUND, e))?;
+        let response = self
+            .lattice
+            .call(&target, &operation, &payload)
+            .await
+            .map_err(|e| GatewayError::new(StatusCode::BAD_GATEWAY, e))?;
+        decode_response(&response, self.bin, schema.as_ref())
+            .map_err(|e| GatewayError::new(StatusCode::BAD_GATEWAY, e))
+    }
+}
+
+/// Splits `/actors/{actor}/{operation}` into the actor and operation, decoding percent-encoded
+/// characters so actor names containing spaces can be used
+fn parse_path(path: &str) -> Option<(String, String)> {
+    let mut segments = path.trim_matches('/').split('/');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some("actors"), Some(actor), Some(operation), None)
+            if !actor.is_empty() && !operation.is_empty() =>
+        {
+            Some((percent_decode(actor)?, percent_decode(operation)?))
+        }
+        _ => None,
+    }
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("/actors/echo/HttpServer.HandleRequest"),
+            Some(("echo".to_string(), "HttpServer.HandleRequest".to_string()))
+        );
+        assert_eq!(
+            parse_path("/actors/hello%20world/Ping/"),
+            Some(("hello world".to_string(), "Ping".to_string()))
+        );
+        assert_eq!(parse_path("/actors/echo"), None);
+        assert_eq!(parse_path("/actors//Ping"), None);
+        assert_eq!(parse_path("/actors/echo/Ping/extra"), None);
+        assert_eq!(parse_path("/providers/echo/Ping"), None);
+        assert_eq!(parse_path("/actors/bad%zz/Ping"), None);
+    }
+
+    #[test]
+    fn test_gateway_command() {
+        let cmd: GatewayCommand = Parser::try_parse_from([
+            "gateway",
+            "--port",
+            "9000",
+            "--lattice-prefix",
+            "dev",
+            "--model",
+            "./interface.smithy",
+        ])
+        .unwrap();
+        assert_eq!(cmd.address, "127.0.0.1");
+        assert_eq!(cmd.port, 9000);
+        assert_eq!(cmd.bin, 'b');
+        assert_eq!(cmd.schema.model, vec!["./interface.smithy".to_string()]);
+    }
+}
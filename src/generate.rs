@@ -104,5 +104,6 @@ pub(crate) async fn handle_command(cmd: NewCliCommand) -> Result<CommandOutput>
                 "Project generated and is located at: {}",
                 path.to_string_lossy()
             ),
+            success: true,
         })
 }
@@ -28,6 +28,7 @@ use completions::CompletionOpts;
 use ctl::CtlCliCommand;
 use ctx::CtxCommand;
 use down::DownCommand;
+use gateway::GatewayCommand;
 use generate::NewCliCommand;
 use keys::KeysCliCommand;
 use par::ParCliCommand;
@@ -45,6 +46,7 @@ mod ctx;
 mod dev;
 mod down;
 mod drain;
+mod gateway;
 mod generate;
 mod keys;
 mod par;
@@ -77,12 +79,13 @@ Run:
   up           Bootstrap a local wasmCloud environment
   down         Tear down a local wasmCloud environment (launched with wash up)
   app          Manage declarative applications and deployments (wadm)
-  spy          Spy on all invocations between an actor and its linked providers
+  spy          Spy on invocations of an actor and its linked providers, or of the whole lattice
 
 Iterate:
   start        Start an actor or provider
   link         Link an actor and a provider
   call         Invoke a wasmCloud actor
+  gateway      Serve a local HTTP endpoint that invokes actors
   stop         Stop an actor or provider, or host
   ctl          Interact with a wasmCloud control interface
 
@@ -173,6 +176,9 @@ enum CliCommand {
     /// Manage contents of local wasmCloud caches
     #[clap(name = "drain", subcommand)]
     Drain(DrainSelection),
+    /// Serve a local HTTP endpoint that invokes actors
+    #[clap(name = "gateway")]
+    Gateway(GatewayCommand),
     /// Generate code from smithy IDL files
     #[clap(name = "gen")]
     Gen(GenerateCli),
@@ -206,7 +212,7 @@ enum CliCommand {
     /// Pull an artifact from an OCI compliant registry
     #[clap(name = "pull")]
     RegPull(RegistryPullCommand),
-    /// (experimental) Spy on all invocations between an actor and its linked providers
+    /// (experimental) Spy on invocations of an actor and its linked providers, or of the whole lattice
     #[clap(name = "spy")]
     Spy(SpyCommand),
     /// Start an actor or a provider
@@ -234,14 +240,32 @@ async fn main() {
     let res: Result<CommandOutput> = match cli.command {
         CliCommand::App(app_cli) => app::handle_command(app_cli, output_kind).await,
         CliCommand::Build(build_cli) => build::handle_command(build_cli),
-        CliCommand::Call(call_cli) => call::handle_command(call_cli.command()).await,
+        CliCommand::Call(call_cli) => call::handle_command(call_cli.command(), output_kind).await,
         CliCommand::Capture(capture_cli) => {
             if !cli.experimental {
                 experimental_error_message("capture")
-            } else if let Some(CaptureSubcommand::Replay(cmd)) = capture_cli.replay {
-                wash_lib::cli::capture::handle_replay_command(cmd).await
             } else {
-                wash_lib::cli::capture::handle_command(capture_cli).await
+                match capture_cli.subcommand {
+                    Some(CaptureSubcommand::Replay(cmd)) => {
+                        wash_lib::cli::capture::handle_replay_command(*cmd, output_kind).await
+                    }
+                    Some(CaptureSubcommand::Stats(cmd)) => {
+                        wash_lib::cli::capture::handle_stats_command(cmd).await
+                    }
+                    Some(CaptureSubcommand::Diff(cmd)) => {
+                        wash_lib::cli::capture::handle_diff_command(cmd).await
+                    }
+                    Some(CaptureSubcommand::Export(cmd)) => {
+                        wash_lib::cli::capture::handle_export_command(cmd).await
+                    }
+                    Some(CaptureSubcommand::Status(cmd)) => {
+                        wash_lib::cli::capture::handle_status_command(cmd).await
+                    }
+                    Some(CaptureSubcommand::Keygen(cmd)) => {
+                        wash_lib::cli::capture::handle_keygen_command(cmd).await
+                    }
+                    None => wash_lib::cli::capture::handle_command(capture_cli).await,
+                }
             }
         }
         CliCommand::Claims(claims_cli) => {
@@ -262,6 +286,7 @@ async fn main() {
         CliCommand::Down(down_cli) => down::handle_command(down_cli, output_kind).await,
         CliCommand::Drain(drain_cli) => drain::handle_command(drain_cli),
         CliCommand::Get(get_cli) => common::get_cmd::handle_command(get_cli, output_kind).await,
+        CliCommand::Gateway(gateway_cli) => gateway::handle_command(gateway_cli, output_kind).await,
         CliCommand::Gen(generate_cli) => smithy::handle_gen_command(generate_cli),
         CliCommand::Inspect(inspect_cli) => {
             wash_lib::cli::inspect::handle_command(inspect_cli, output_kind).await
@@ -284,7 +309,7 @@ async fn main() {
             if !cli.experimental {
                 experimental_error_message("spy")
             } else {
-                wash_lib::cli::spy::handle_command(spy_cli).await
+                wash_lib::cli::spy::handle_command(spy_cli, output_kind).await
             }
         }
         CliCommand::Start(start_cli) => {
@@ -297,12 +322,13 @@ async fn main() {
 
     std::process::exit(match res {
         Ok(out) => {
+            let failure_code = i32::from(!out.success);
             match output_kind {
                 OutputKind::Json => {
                     let mut map = out.map;
-                    map.insert("success".to_string(), json!(true));
+                    map.insert("success".to_string(), json!(out.success));
                     println!("\n{}", serde_json::to_string_pretty(&map).unwrap());
-                    0
+                    failure_code
                 }
                 OutputKind::Text => {
                     println!("\n{}", out.text);
@@ -310,11 +336,11 @@ async fn main() {
                     match completions::first_run_suggestion() {
                         Ok(Some(suggestion)) => {
                             println!("\n{}", suggestion);
-                            0
+                            failure_code
                         }
                         Ok(None) => {
                             // >1st run,  no message
-                            0
+                            failure_code
                         }
                         Err(e) => {
                             // error creating first-run token file
@@ -192,6 +192,7 @@ impl From<InspectCommand> for inspect::InspectCliCommand {
             password: cmd.password,
             insecure: cmd.insecure,
             no_cache: cmd.no_cache,
+            trust: Default::default(),
         }
     }
 }
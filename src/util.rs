@@ -37,16 +37,18 @@ pub(crate) fn json_str_to_msgpack_bytes(payload: &str) -> Result<Vec<u8>> {
     Ok(payload)
 }
 
-use once_cell::sync::OnceCell;
-static BIN_STR: OnceCell<char> = OnceCell::new();
-
-fn msgpack_to_json(mval: rmpv::Value) -> serde_json::Value {
+/// Transform a msgpack value into json, displaying binary as binary('b'), string('s'), or both('2')
+pub(crate) fn msgpack_to_json(mval: rmpv::Value, bin_str: char) -> serde_json::Value {
     use rmpv::Value as RV;
     use serde_json::Value as JV;
     match mval {
-        RV::String(s) => JV::String(s.to_string()),
+        RV::String(s) => JV::String(String::from_utf8_lossy(s.as_bytes()).into_owned()),
         RV::Boolean(b) => JV::Bool(b),
-        RV::Array(v) => JV::Array(v.into_iter().map(msgpack_to_json).collect::<Vec<_>>()),
+        RV::Array(v) => JV::Array(
+            v.into_iter()
+                .map(|v| msgpack_to_json(v, bin_str))
+                .collect::<Vec<_>>(),
+        ),
         RV::F64(f) => JV::from(f),
         RV::F32(f) => JV::from(f),
         RV::Integer(i) => match (i.is_u64(), i.is_i64()) {
@@ -59,12 +61,12 @@ fn msgpack_to_json(mval: rmpv::Value) -> serde_json::Value {
                 .map(|(k, v)| {
                     (
                         k.as_str().unwrap_or_default().to_string(),
-                        msgpack_to_json(v),
+                        msgpack_to_json(v, bin_str),
                     )
                 })
                 .collect::<serde_json::Map<_, _>>(),
         ),
-        RV::Binary(v) => match BIN_STR.get().unwrap() {
+        RV::Binary(v) => match bin_str {
             's' => JV::String(String::from_utf8_lossy(&v).into_owned()),
             '2' => serde_json::json!({
                 "str": String::from_utf8_lossy(&v),
@@ -84,11 +86,9 @@ fn msgpack_to_json(mval: rmpv::Value) -> serde_json::Value {
 pub(crate) fn msgpack_to_json_val(msg: Vec<u8>, bin_str: char) -> serde_json::Value {
     use bytes::Buf;
 
-    BIN_STR.set(bin_str).unwrap();
-
     let bytes = bytes::Bytes::from(msg);
     if let Ok(v) = rmpv::decode::value::read_value(&mut bytes.reader()) {
-        msgpack_to_json(v)
+        msgpack_to_json(v, bin_str)
     } else {
         serde_json::json!({ "error": "Could not decode data" })
     }